@@ -0,0 +1,112 @@
+//! Baseline timings for the media encoders: `cargo bench --features bench`
+
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use image::{Delay, DynamicImage, Frame, Frames, RgbaImage};
+
+// The binary has no library target, so the encoder modules are compiled straight into the bench
+#[allow(dead_code)]
+#[path = "../src/media.rs"]
+mod media;
+#[allow(dead_code)]
+#[path = "../src/progress.rs"]
+mod progress;
+
+use media::{encode_gif, encode_image, GifOptions, ImageOptions};
+use progress::ProgressFormat;
+
+/// Screen sizes to encode for: the zoom65v3 and a wide 320x172 panel
+const SIZES: [(u32, u32); 2] = [(110, 110), (320, 172)];
+/// Frames in the benchmark animation
+const FRAME_COUNT: u32 = 24;
+/// Side length of the source media, larger than any screen so every run resizes
+const SOURCE_SIZE: u32 = 480;
+
+/// A semi transparent gradient, shifted per frame so frames don't compress to nothing
+fn source(shift: u32) -> RgbaImage {
+    RgbaImage::from_fn(SOURCE_SIZE, SOURCE_SIZE, |x, y| {
+        image::Rgba([
+            (x + shift) as u8,
+            (y + shift * 3) as u8,
+            (x ^ y) as u8,
+            if (x / 32 + y / 32) % 2 == 0 { 255 } else { 128 },
+        ])
+    })
+}
+
+fn animation() -> Vec<Frame> {
+    (0..FRAME_COUNT)
+        .map(|i| Frame::from_parts(source(i * 8), 0, 0, Delay::from_numer_denom_ms(100, 1)))
+        .collect()
+}
+
+fn filter_name(nearest: bool) -> &'static str {
+    if nearest {
+        "nearest"
+    } else {
+        "gaussian"
+    }
+}
+
+fn bench_image(c: &mut Criterion) {
+    progress::set_format(ProgressFormat::Quiet);
+    let image = DynamicImage::ImageRgba8(source(0));
+
+    let mut group = c.benchmark_group("encode_image");
+    for (width, height) in SIZES {
+        for nearest in [true, false] {
+            let id = BenchmarkId::new(filter_name(nearest), format!("{width}x{height}"));
+            group.bench_function(id, |b| {
+                b.iter_batched(
+                    || image.clone(),
+                    |image| {
+                        encode_image(
+                            image,
+                            [0; 3],
+                            nearest,
+                            width,
+                            height,
+                            ImageOptions::default(),
+                        )
+                        .expect("image encodes")
+                    },
+                    BatchSize::LargeInput,
+                )
+            });
+        }
+    }
+    group.finish();
+}
+
+fn bench_gif(c: &mut Criterion) {
+    progress::set_format(ProgressFormat::Quiet);
+    let frames = animation();
+
+    let mut group = c.benchmark_group("encode_gif");
+    group.sample_size(10);
+    for (width, height) in SIZES {
+        for nearest in [true, false] {
+            let id = BenchmarkId::new(filter_name(nearest), format!("{width}x{height}"));
+            group.bench_function(id, |b| {
+                b.iter_batched(
+                    || Frames::new(Box::new(frames.clone().into_iter().map(Ok))),
+                    |frames| {
+                        encode_gif(
+                            frames,
+                            [0; 3],
+                            nearest,
+                            width,
+                            height,
+                            GifOptions::default(),
+                        )
+                        .expect("animation encodes")
+                    },
+                    BatchSize::LargeInput,
+                )
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_image, bench_gif);
+criterion_main!(benches);
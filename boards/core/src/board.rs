@@ -1,6 +1,9 @@
 //! Core Board trait and related types.
 
-use crate::features::{HasGif, HasImage, HasScreen, HasSystemInfo, HasTime, HasWeather};
+use crate::features::{
+    BoardError, HasBattery, HasBrightness, HasGif, HasImage, HasScreen, HasSystemInfo, HasTheme,
+    HasTime, HasWeather, Result,
+};
 
 /// Static information about a board type for detection and CLI
 #[derive(Debug, Clone, Copy)]
@@ -11,6 +14,79 @@ pub struct BoardInfo {
     pub product_id: u16,
     pub usage_page: Option<u16>,
     pub usage: Option<u16>,
+    pub capabilities: Capabilities,
+}
+
+impl BoardInfo {
+    /// udev rule giving logged in users access to the board's hid devices
+    pub fn udev_rule(&self) -> String {
+        format!(
+            r#"SUBSYSTEM=="hidraw", ATTRS{{idVendor}}=="{:04x}", ATTRS{{idProduct}}=="{:04x}", MODE="0660", TAG+="uaccess""#,
+            self.vendor_id, self.product_id
+        )
+    }
+}
+
+/// Features a board type supports, known without opening the device. Should agree with the
+/// board's `as_*` opt-ins.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Capabilities {
+    pub time: bool,
+    pub weather: bool,
+    pub system_info: bool,
+    pub screen: bool,
+    pub theme: bool,
+    pub image: bool,
+    pub gif: bool,
+    pub battery: bool,
+    pub brightness: bool,
+}
+
+impl Capabilities {
+    /// Capabilities a board actually opts into through its `as_*` methods
+    pub fn of(board: &mut dyn Board) -> Self {
+        Self {
+            time: board.as_time().is_some(),
+            weather: board.as_weather().is_some(),
+            system_info: board.as_system_info().is_some(),
+            screen: board.as_screen().is_some(),
+            theme: board.as_theme().is_some(),
+            image: board.as_image().is_some(),
+            gif: board.as_gif().is_some(),
+            battery: board.as_battery().is_some(),
+            brightness: board.as_brightness().is_some(),
+        }
+    }
+
+    /// Features supported by either set of capabilities
+    pub fn union(self, other: Self) -> Self {
+        Self {
+            time: self.time || other.time,
+            weather: self.weather || other.weather,
+            system_info: self.system_info || other.system_info,
+            screen: self.screen || other.screen,
+            theme: self.theme || other.theme,
+            image: self.image || other.image,
+            gif: self.gif || other.gif,
+            battery: self.battery || other.battery,
+            brightness: self.brightness || other.brightness,
+        }
+    }
+
+    /// Every feature by display name, with whether it's supported
+    pub fn list(&self) -> [(&'static str, bool); 9] {
+        [
+            ("time", self.time),
+            ("weather", self.weather),
+            ("system", self.system_info),
+            ("screen", self.screen),
+            ("theme", self.theme),
+            ("image", self.image),
+            ("gif", self.gif),
+            ("battery", self.battery),
+            ("brightness", self.brightness),
+        ]
+    }
 }
 
 /// Screen position for menu building
@@ -21,15 +97,22 @@ pub struct ScreenPosition {
     pub group: ScreenGroup,
 }
 
-/// Screen position grouping for menu organization
+/// Screen position grouping for menu organization. Positions are shown in the order the board
+/// lists them, with a separator wherever the group changes.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum ScreenGroup {
     System,
     Time,
     Logo,
     Battery,
+    /// Board specific group that doesn't fit the ones above, by name
+    Custom(&'static str),
 }
 
+/// Times a rejected upload chunk is resent before giving up, unless configured otherwise with
+/// [`Board::set_upload_retries`]
+pub const DEFAULT_UPLOAD_RETRIES: u8 = 3;
+
 /// Core board trait - object-safe for `dyn Board`
 ///
 /// Instance methods (`info`, `as_*`) are object-safe.
@@ -40,6 +123,26 @@ pub trait Board: Send {
     /// Get board info (instance method for object safety)
     fn info(&self) -> &'static BoardInfo;
 
+    /// Re-acquire the underlying device in place after a transient disconnect.
+    /// Boards that can't reopen themselves return an error, and should be opened from scratch.
+    fn reconnect(&mut self) -> Result<()> {
+        Err(BoardError::DeviceNotFound)
+    }
+
+    /// Resend a rejected media upload chunk up to `retries` times before failing the upload.
+    /// Boards without chunked uploads ignore this.
+    fn set_upload_retries(&mut self, _retries: u8) {}
+
+    /// Override the hid report id sent as the first byte of every command, for firmware
+    /// variants that expect a different one. Boards without a report id prefix ignore this.
+    fn set_report_id(&mut self, _id: u8) {}
+
+    /// Write an arbitrary report to the device and return the raw response, for experimenting
+    /// with unknown commands. Empty if the device didn't respond in time.
+    fn execute_raw(&mut self, _payload: &[u8]) -> Result<Vec<u8>> {
+        Err(BoardError::CommandFailed("raw commands are not supported"))
+    }
+
     /// Feature opt-in methods - override to return `Some(self)` if feature is supported
     fn as_time(&mut self) -> Option<&mut dyn HasTime> {
         None
@@ -53,6 +156,9 @@ pub trait Board: Send {
     fn as_screen(&mut self) -> Option<&mut dyn HasScreen> {
         None
     }
+    fn as_theme(&mut self) -> Option<&mut dyn HasTheme> {
+        None
+    }
     fn as_screen_size(&self) -> Option<(u32, u32)> {
         None
     }
@@ -62,4 +168,10 @@ pub trait Board: Send {
     fn as_gif(&mut self) -> Option<&mut dyn HasGif> {
         None
     }
+    fn as_battery(&mut self) -> Option<&mut dyn HasBattery> {
+        None
+    }
+    fn as_brightness(&mut self) -> Option<&mut dyn HasBrightness> {
+        None
+    }
 }
@@ -3,9 +3,12 @@
 //! Boards opt-in to features by implementing these traits and returning
 //! `Some(self)` from the corresponding `as_*()` method in the Board trait.
 
+use std::ffi::CStr;
+use std::io::ErrorKind;
+
 use chrono::{DateTime, Local};
 
-use crate::ScreenPosition;
+use crate::{BoardInfo, ScreenPosition};
 
 /// Errors that can occur during board operations
 #[derive(Debug, thiserror::Error)]
@@ -14,6 +17,21 @@ pub enum BoardError {
     #[error("device not found")]
     DeviceNotFound,
 
+    /// More than one supported board is connected and none was picked
+    #[error("multiple boards found ({0}), pick one with --board or --device-path")]
+    MultipleBoards(String),
+
+    /// Device was found, but this user isn't allowed to open it
+    #[error("permission denied opening {path}\n{hint}")]
+    PermissionDenied { path: String, hint: String },
+
+    /// Device was found, but another program holds it open
+    #[error(
+        "device {path} is busy, close any other program using it (ie the vendor's web driver) \
+         and try again"
+    )]
+    DeviceBusy { path: String },
+
     /// Command failed on the device
     #[error("command failed: {0}")]
     CommandFailed(&'static str),
@@ -26,6 +44,14 @@ pub enum BoardError {
     #[error("invalid media: {0}")]
     InvalidMedia(&'static str),
 
+    /// Theme id not supported by the board
+    #[error("invalid theme id: {0}")]
+    InvalidTheme(u8),
+
+    /// Image doesn't match what the device expects
+    #[error("invalid image: {0}")]
+    InvalidImage(String),
+
     /// Media too large for device
     #[error("media too large: {0}")]
     MediaTooLarge(&'static str),
@@ -41,20 +67,110 @@ pub enum BoardError {
 
 pub type Result<T> = std::result::Result<T, BoardError>;
 
+impl BoardError {
+    /// Explain a failure to open the hid device at `path`. hidapi reports permission and busy
+    /// errors as generic errors, so they're picked out here and given a hint on fixing them.
+    pub fn open_failed(error: hidapi::HidError, path: &CStr, info: &BoardInfo) -> Self {
+        let kind = match &error {
+            hidapi::HidError::IoError { error } => Some(error.kind()),
+            _ => None,
+        };
+        let message = error.to_string().to_lowercase();
+        let path = path.to_string_lossy().into_owned();
+        if kind == Some(ErrorKind::PermissionDenied) || message.contains("permission denied") {
+            BoardError::PermissionDenied {
+                path,
+                hint: permission_hint(info),
+            }
+        } else if kind == Some(ErrorKind::ResourceBusy) || message.contains("resource busy") {
+            BoardError::DeviceBusy { path }
+        } else {
+            BoardError::Hid(error)
+        }
+    }
+}
+
+/// How to get access to a board's hid devices
+fn permission_hint(info: &BoardInfo) -> String {
+    if cfg!(target_os = "linux") {
+        format!(
+            "allow access with a udev rule, by running `sudo zoom-sync udev --install` or adding \
+             this line to /etc/udev/rules.d/99-zoom-sync.rules:\n  {}\n\
+             then run `sudo udevadm control --reload-rules && sudo udevadm trigger`. Headless \
+             setups can add GROUP=\"plugdev\" to the rule and the user to the plugdev group.",
+            info.udev_rule()
+        )
+    } else {
+        "run zoom-sync as a user that can access hid devices".into()
+    }
+}
+
 /// Time synchronization capability
 pub trait HasTime {
     fn set_time(&mut self, time: DateTime<Local>, use_12hr: bool) -> Result<()>;
 }
 
+/// Optional weather readings beyond the basic forecast
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct WeatherExtras {
+    /// Relative humidity in percent
+    pub humidity: Option<u8>,
+    /// Apparent ("feels like") temperature in whole degrees
+    pub apparent_temp: Option<i16>,
+    /// Wind speed in km/h
+    pub wind_speed: Option<f32>,
+}
+
 /// Weather display capability
 pub trait HasWeather {
     /// Set weather display. WMO code is converted to board-specific icon internally.
-    fn set_weather(&mut self, wmo: u8, is_day: bool, current: u8, low: u8, high: u8) -> Result<()>;
+    /// Temperatures are signed whole degrees; boards clamp to whatever range they can display.
+    fn set_weather(
+        &mut self,
+        wmo: u8,
+        is_day: bool,
+        current: i16,
+        low: i16,
+        high: i16,
+    ) -> Result<()>;
+
+    /// Set weather display with extra readings. Boards that can show them override this,
+    /// the default drops the extras and calls `set_weather`.
+    fn set_weather_extended(
+        &mut self,
+        wmo: u8,
+        is_day: bool,
+        current: i16,
+        low: i16,
+        high: i16,
+        _extras: WeatherExtras,
+    ) -> Result<()> {
+        self.set_weather(wmo, is_day, current, low, high)
+    }
 }
 
 /// System info display capability (CPU temp, GPU temp, download speed)
 pub trait HasSystemInfo {
+    /// Set the system info, with the download rate in MB/s
     fn set_system_info(&mut self, cpu: u8, gpu: u8, download: f32) -> Result<()>;
+    /// Largest download rate in MB/s the screen can show, if the board has a known limit.
+    /// Higher rates are pinned to this value by the board.
+    fn max_download(&self) -> Option<f32> {
+        None
+    }
+}
+
+/// Battery level reporting capability
+pub trait HasBattery {
+    /// Current battery charge in percent, `None` if the firmware doesn't report it
+    fn battery_percent(&mut self) -> Result<Option<u8>>;
+}
+
+/// Screen brightness capability
+pub trait HasBrightness {
+    /// Set the screen brightness in percent. Boards scale it onto the range their firmware
+    /// supports, and treat anything above 100 as 100.
+    fn set_brightness(&mut self, percent: u8) -> Result<()>;
 }
 
 /// Screen position control capability
@@ -69,19 +185,68 @@ pub trait HasScreen {
     fn reset_screen(&mut self) -> Result<()>;
 }
 
+/// Screen theme capability
+pub trait HasTheme {
+    /// Known theme presets for this board, as `(id, name)` pairs
+    fn available_themes(&self) -> &'static [(u8, &'static str)];
+    /// Set the screen theme by preset id
+    fn set_theme(&mut self, theme_id: u8) -> Result<()>;
+    /// Set the screen theme from an rgb color. Boards with fixed presets pick the closest one.
+    fn set_theme_color(&mut self, rgb: [u8; 3]) -> Result<()>;
+    /// Preset id the board ships with, if known
+    fn default_theme(&self) -> Option<u8> {
+        None
+    }
+}
+
 /// Screen dimensions - boards with media support should also implement as_screen_size()
 pub trait HasScreenSize {
     fn screen_size(&self) -> (u32, u32);
 }
 
+/// Byte order of each rgb565 pixel in an encoded image. Colors come out swapped and tinted when
+/// it doesn't match the firmware.
+#[derive(Clone, Copy, Debug, Default, Hash, PartialEq, Eq)]
+pub enum ByteOrder {
+    #[default]
+    BigEndian,
+    LittleEndian,
+}
+
 /// Static image upload capability
 pub trait HasImage {
+    /// Upload an encoded image. `progress` is called after each chunk with the number of chunks
+    /// sent so far, out of `data.len().div_ceil(self.chunk_size())`.
     fn upload_image(&mut self, data: &[u8], progress: &mut dyn FnMut(usize)) -> Result<()>;
     fn clear_image(&mut self) -> Result<()>;
+    /// Bytes of media data sent per upload chunk
+    fn chunk_size(&self) -> usize;
+    /// Byte order the firmware expects rgb565 pixels in
+    fn byte_order(&self) -> ByteOrder {
+        ByteOrder::BigEndian
+    }
+    /// Read back the stored image and compare it with the uploaded `data`, failing with
+    /// [`BoardError::CommandFailed`] on a mismatch. `None` if the firmware can't report it.
+    fn verify_image(&mut self, _data: &[u8]) -> Option<Result<()>> {
+        None
+    }
 }
 
 /// Animated GIF upload capability
 pub trait HasGif {
+    /// Upload an encoded animation. `progress` is called after each chunk with the number of
+    /// chunks sent so far, out of `data.len().div_ceil(self.chunk_size())`.
     fn upload_gif(&mut self, data: &[u8], progress: &mut dyn FnMut(usize)) -> Result<()>;
     fn clear_gif(&mut self) -> Result<()>;
+    /// Bytes of media data sent per upload chunk
+    fn chunk_size(&self) -> usize;
+    /// Read back the stored animation and compare it with the uploaded `data`, failing with
+    /// [`BoardError::CommandFailed`] on a mismatch. `None` if the firmware can't report it.
+    fn verify_gif(&mut self, _data: &[u8]) -> Option<Result<()>> {
+        None
+    }
+    /// Encoded gifs must be smaller than this many bytes, if the board has a known limit
+    fn max_size(&self) -> Option<usize> {
+        None
+    }
 }
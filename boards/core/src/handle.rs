@@ -0,0 +1,246 @@
+//! Async access to a board that stays on its own thread.
+//!
+//! Hid io is blocking and a device should only be used from one thread at a time, so
+//! [`BoardHandle`] moves the board onto a dedicated thread and queues work for it. Every call
+//! returns a future for the result, which works with any async runtime since nothing is polled
+//! on the runtime's own threads.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::thread;
+
+use crate::board::{Board, BoardInfo};
+use crate::features::{BoardError, Result};
+
+type Job = Box<dyn FnOnce(&mut dyn Board) + Send>;
+
+/// Owns a board on a dedicated thread, running queued work on it in order.
+///
+/// The thread exits once the handle is dropped and any queued work has finished.
+pub struct BoardHandle {
+    jobs: mpsc::Sender<Job>,
+    info: &'static BoardInfo,
+}
+
+impl BoardHandle {
+    /// Move a board onto its own thread
+    pub fn spawn(mut board: Box<dyn Board>) -> Self {
+        let info = board.info();
+        let (jobs, queue) = mpsc::channel::<Job>();
+        thread::Builder::new()
+            .name(format!("{} io", info.cli_name))
+            .spawn(move || {
+                for job in queue {
+                    job(board.as_mut());
+                }
+            })
+            .expect("failed to spawn board thread");
+        Self { jobs, info }
+    }
+
+    /// Static info of the board
+    pub fn info(&self) -> &'static BoardInfo {
+        self.info
+    }
+
+    /// Run `f` with the board on its thread, resolving to the result once it's done
+    pub fn run<T, F>(&self, f: F) -> Reply<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut dyn Board) -> T + Send + 'static,
+    {
+        let slot = Arc::new(Mutex::new(Slot::Pending(None)));
+        let reply = Reply { slot: slot.clone() };
+        let sender = Sender { slot };
+        let job: Job = Box::new(move |board| sender.send(f(board)));
+        // If the thread is gone the job is dropped unsent, and the reply resolves to an error
+        let _ = self.jobs.send(job);
+        reply
+    }
+
+    /// Upload an encoded image, resolving once the whole upload is done
+    pub async fn upload_image(&self, data: Vec<u8>) -> Result<()> {
+        self.run(move |board| {
+            board
+                .as_image()
+                .ok_or(BoardError::CommandFailed("board does not support images"))?
+                .upload_image(&data, &mut |_| {})
+        })
+        .await?
+    }
+
+    /// Upload an encoded gif, resolving once the whole upload is done
+    pub async fn upload_gif(&self, data: Vec<u8>) -> Result<()> {
+        self.run(move |board| {
+            board
+                .as_gif()
+                .ok_or(BoardError::CommandFailed("board does not support gifs"))?
+                .upload_gif(&data, &mut |_| {})
+        })
+        .await?
+    }
+}
+
+enum Slot<T> {
+    /// Still running, with the waker of the last poll
+    Pending(Option<Waker>),
+    Done(T),
+    /// The job was dropped without running, ie the board thread panicked
+    Dropped,
+    Taken,
+}
+
+/// Result of work queued on a [`BoardHandle`]. Resolves to an error if the board thread stopped
+/// before the work ran.
+pub struct Reply<T> {
+    slot: Arc<Mutex<Slot<T>>>,
+}
+
+impl<T> Future for Reply<T> {
+    type Output = Result<T>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut slot = self.slot.lock().unwrap();
+        match std::mem::replace(&mut *slot, Slot::Taken) {
+            Slot::Pending(_) => {
+                *slot = Slot::Pending(Some(cx.waker().clone()));
+                Poll::Pending
+            },
+            Slot::Done(value) => Poll::Ready(Ok(value)),
+            Slot::Dropped => Poll::Ready(Err(BoardError::CommandFailed("board thread stopped"))),
+            Slot::Taken => panic!("reply polled after completion"),
+        }
+    }
+}
+
+/// Sending half of a [`Reply`], which marks the reply as dropped if it never sends
+struct Sender<T> {
+    slot: Arc<Mutex<Slot<T>>>,
+}
+
+impl<T> Sender<T> {
+    fn send(self, value: T) {
+        self.finish(Slot::Done(value));
+    }
+
+    /// Resolve the reply if it's still pending
+    fn finish(&self, result: Slot<T>) {
+        let mut slot = self.slot.lock().unwrap();
+        if !matches!(*slot, Slot::Pending(_)) {
+            return;
+        }
+        if let Slot::Pending(Some(waker)) = std::mem::replace(&mut *slot, result) {
+            drop(slot);
+            waker.wake();
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        // A no-op after `send`, since the slot is no longer pending
+        self.finish(Slot::Dropped);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::task::Wake;
+
+    use super::*;
+
+    static INFO: BoardInfo = BoardInfo {
+        name: "Test Board",
+        cli_name: "test",
+        vendor_id: 0,
+        product_id: 0,
+        usage_page: None,
+        usage: None,
+        capabilities: crate::board::Capabilities {
+            time: false,
+            weather: false,
+            system_info: false,
+            screen: false,
+            theme: false,
+            image: false,
+            gif: false,
+            battery: false,
+            brightness: false,
+        },
+    };
+
+    /// Board without any features
+    struct TestBoard;
+
+    impl Board for TestBoard {
+        fn info(&self) -> &'static BoardInfo {
+            &INFO
+        }
+    }
+
+    /// Minimal executor, parking the thread until the future is woken
+    fn block_on<F: Future>(future: F) -> F::Output {
+        struct Unpark(thread::Thread);
+        impl Wake for Unpark {
+            fn wake(self: Arc<Self>) {
+                self.0.unpark();
+            }
+        }
+        let waker = Waker::from(Arc::new(Unpark(thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        let mut future = std::pin::pin!(future);
+        loop {
+            match future.as_mut().poll(&mut cx) {
+                Poll::Ready(output) => return output,
+                Poll::Pending => thread::park(),
+            }
+        }
+    }
+
+    #[test]
+    fn work_runs_on_the_board_thread() {
+        let handle = BoardHandle::spawn(Box::new(TestBoard));
+        assert_eq!(handle.info().cli_name, "test");
+        let name = block_on(handle.run(|board| {
+            assert_eq!(board.info().name, "Test Board");
+            thread::current().name().map(str::to_string)
+        }))
+        .unwrap();
+        assert_eq!(name.as_deref(), Some("test io"));
+    }
+
+    #[test]
+    fn work_runs_in_order() {
+        let handle = BoardHandle::spawn(Box::new(TestBoard));
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let replies: Vec<_> = (0..5)
+            .map(|i| {
+                let order = order.clone();
+                handle.run(move |_| order.lock().unwrap().push(i))
+            })
+            .collect();
+        for reply in replies {
+            block_on(reply).unwrap();
+        }
+        assert_eq!(*order.lock().unwrap(), [0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn unsupported_upload_fails() {
+        let handle = BoardHandle::spawn(Box::new(TestBoard));
+        let res = block_on(handle.upload_image(vec![0; 3]));
+        assert!(matches!(res, Err(BoardError::CommandFailed(_))));
+    }
+
+    #[test]
+    fn panicked_work_resolves_to_an_error() {
+        let handle = BoardHandle::spawn(Box::new(TestBoard));
+        let res = block_on(handle.run(|_| -> u8 { panic!("device exploded") }));
+        assert!(res.is_err());
+        // the thread is gone, so later work fails instead of hanging
+        assert!(block_on(handle.run(|_| ())).is_err());
+    }
+}
@@ -4,12 +4,17 @@
 //! - Feature traits (`HasTime`, `HasWeather`, etc.) that boards can implement
 //! - The `Board` trait with `as_*()` methods for feature discovery
 //! - Common types like `BoardInfo`, `ScreenPosition`
+//! - `BoardHandle`, for using a board from async code while its blocking io stays on one thread
 
 mod board;
 mod features;
+mod handle;
 
-pub use board::{Board, BoardInfo, ScreenGroup, ScreenPosition};
+pub use board::{
+    Board, BoardInfo, Capabilities, ScreenGroup, ScreenPosition, DEFAULT_UPLOAD_RETRIES,
+};
 pub use features::{
-    BoardError, HasGif, HasImage, HasScreen, HasScreenSize, HasSystemInfo, HasTime, HasWeather,
-    Result,
+    BoardError, ByteOrder, HasBattery, HasBrightness, HasGif, HasImage, HasScreen, HasScreenSize,
+    HasSystemInfo, HasTheme, HasTime, HasWeather, Result, WeatherExtras,
 };
+pub use handle::{BoardHandle, Reply};
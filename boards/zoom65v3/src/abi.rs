@@ -1,5 +1,5 @@
 use crate::float::DumbFloat16;
-use crate::types::{Icon, ScreenTheme, UploadChannel};
+use crate::types::{Icon, ScreenTheme, Temperature, UploadChannel};
 
 pub trait Arg {
     const SIZE: usize;
@@ -91,7 +91,7 @@ impl_command_abi![
     fn set_time([165, 1, 16], year: u8, month: u8, day: u8, hour: u8, minute: u8, second: u8);
 
     /// setting the weather icon and current/min/max temperatures
-    fn set_weather([165, 1, 32], icon: Icon, current: u8, low: u8, high: u8);
+    fn set_weather([165, 1, 32], icon: Icon, current: Temperature, low: Temperature, high: Temperature);
 
     /// setting the cpu/gpu temp and download rate
     fn set_system_info([165, 1, 64], cpu_temp: u8, gpu_temp: u8, download: DumbFloat16);
@@ -105,3 +105,30 @@ pub const fn get_version() -> [u8; 33] {
     buf[1] = 1;
     buf
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn set_weather_negative_celsius() {
+        let t = Temperature::new;
+        let buf = set_weather(Icon::Snowfall, t(-20), t(-25), t(-12));
+        assert_eq!(buf[2], 7, "payload length");
+        assert_eq!(buf[3..7], [165, 1, 32, 7]);
+        assert_eq!(buf[7..10], [(-20i8) as u8, (-25i8) as u8, (-12i8) as u8]);
+    }
+
+    #[test]
+    fn set_weather_hot_fahrenheit() {
+        let t = Temperature::new;
+        let buf = set_weather(Icon::DayClear, t(105), t(88), t(110));
+        assert_eq!(buf[7..10], [105, 88, 110]);
+    }
+
+    #[test]
+    fn temperature_clamps_to_device_range() {
+        assert_eq!(Temperature::new(300), Temperature::new(127));
+        assert_eq!(Temperature::new(-300), Temperature::new(-128));
+    }
+}
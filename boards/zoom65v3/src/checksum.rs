@@ -22,14 +22,53 @@ pub fn checksum(data: &[u8]) -> [u8; 4] {
 }
 
 #[cfg(test)]
-#[test]
-fn checksum_test() {
-    assert_eq!(
-        checksum(&[
-            0, 0, 71, 73, 70, 56, 57, 97, 111, 0, 111, 0, 247, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-            0, 0, 0,
-        ]),
-        [94, 148, 189, 206],
-        "checksum should be the same as test data"
-    );
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checksum_test() {
+        assert_eq!(
+            checksum(&[
+                0, 0, 71, 73, 70, 56, 57, 97, 111, 0, 111, 0, 247, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+                0, 0, 0, 0,
+            ]),
+            [94, 148, 189, 206],
+            "checksum should be the same as test data"
+        );
+    }
+
+    /// The checksum is CRC-32/MPEG-2, so the standard check value applies
+    #[test]
+    fn matches_crc32_mpeg2_check_value() {
+        assert_eq!(checksum(b"123456789"), 0x0376e6e7u32.to_be_bytes());
+    }
+
+    #[test]
+    fn empty_input_is_the_initial_value() {
+        assert_eq!(checksum(&[]), [0xff; 4]);
+    }
+
+    #[test]
+    fn single_bytes() {
+        assert_eq!(checksum(&[0x00]), 0x4e08bfb4u32.to_be_bytes());
+        assert_eq!(checksum(&[0xff]), 0xffffff00u32.to_be_bytes());
+    }
+
+    /// A full 24 byte image chunk, checksummed with its index and two trailing zero bytes the
+    /// same way the upload payloads are
+    #[test]
+    fn full_image_chunk() {
+        let mut region = vec![0, 0];
+        region.extend([0x12, 0x34, 0xff].repeat(8));
+        region.extend([0, 0]);
+        assert_eq!(checksum(&region), [0x5e, 0xc0, 0x01, 0x86]);
+    }
+
+    /// Upload payloads copy the output as is, most significant byte first
+    #[test]
+    fn output_is_big_endian() {
+        let crc = u32::from_be_bytes(checksum(b"123456789"));
+        assert_eq!(crc, 0x0376e6e7);
+        assert_ne!(u32::from_le_bytes(checksum(b"123456789")), crc);
+    }
 }
@@ -1,18 +1,22 @@
 use std::fmt::Debug;
-use std::ops::Not;
 
 use crate::abi::Arg;
 
+/// Unsigned fixed point number with a resolution of 0.01, as used for the download rate.
+///
+/// Encoding rounds to the nearest hundredth, so values are off by at most 0.005. Decoding is
+/// exact to f32 precision, which near [`DumbFloat16::MAX`] is about 0.00006, so 655.34 may read
+/// back as 655.33997.
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub struct DumbFloat16(u16);
 
 impl DumbFloat16 {
     /// 0.0
     pub const MIN: DumbFloat16 = DumbFloat16(u16::MIN);
-    /// 655.33997
+    /// 655.35
     pub const MAX: DumbFloat16 = DumbFloat16(u16::MAX);
     pub const MIN_F32: f32 = 0.0;
-    pub const MAX_F32: f32 = 655.33997;
+    pub const MAX_F32: f32 = 655.35;
 
     /// Create a new float, clamping at the minimum and maximum values
     pub fn new(mut float: f32) -> Self {
@@ -68,11 +72,11 @@ impl From<&DumbFloat16> for f32 {
 impl TryFrom<f32> for DumbFloat16 {
     type Error = ();
     #[inline(always)]
+    /// Convert a value without clamping, failing if it's out of range (or nan)
     fn try_from(value: f32) -> Result<Self, Self::Error> {
         (Self::MIN_F32..=Self::MAX_F32)
             .contains(&value)
-            .not()
-            .then_some(DumbFloat16::new(value))
+            .then(|| DumbFloat16::new(value))
             .ok_or(())
     }
 }
@@ -105,4 +109,43 @@ mod tests {
             println!("{x:?}");
         }
     }
+
+    #[test]
+    fn encode_decode_roundtrip() {
+        for n in 0..=u16::MAX {
+            let value = f32::from(&DumbFloat16(n));
+            assert_eq!(DumbFloat16::new(value), DumbFloat16(n), "{value}");
+        }
+    }
+
+    #[test]
+    fn encode_is_within_quantization_error() {
+        // Half a step, plus f32 error when decoding near the max
+        const TOLERANCE: f32 = 0.005 + 0.0001;
+        for i in 0..=655_350 {
+            let value = i as f32 / 1000.;
+            let decoded = f32::from(&DumbFloat16::new(value));
+            assert!(
+                (decoded - value).abs() <= TOLERANCE,
+                "{value} decoded as {decoded}"
+            );
+        }
+    }
+
+    #[test]
+    fn encode_clamps() {
+        assert_eq!(DumbFloat16::new(-1.), DumbFloat16::MIN);
+        assert_eq!(DumbFloat16::new(1000.), DumbFloat16::MAX);
+        assert_eq!(f32::from(&DumbFloat16::MAX), DumbFloat16::MAX_F32);
+    }
+
+    #[test]
+    fn try_from_accepts_in_range() {
+        assert_eq!(DumbFloat16::try_from(0.), Ok(DumbFloat16::MIN));
+        assert_eq!(DumbFloat16::try_from(1.5), Ok(DumbFloat16(150)));
+        assert_eq!(DumbFloat16::try_from(655.35), Ok(DumbFloat16::MAX));
+        assert_eq!(DumbFloat16::try_from(-0.01), Err(()));
+        assert_eq!(DumbFloat16::try_from(655.36), Err(()));
+        assert_eq!(DumbFloat16::try_from(f32::NAN), Err(()));
+    }
 }
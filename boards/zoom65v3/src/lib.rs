@@ -1,20 +1,24 @@
 //! High level hidapi abstraction for interacting with zoom65v3 screen modules
 
+use std::ffi::{CStr, CString};
 use std::sync::{LazyLock, RwLock};
 
 use checksum::checksum;
 use chrono::{DateTime, Datelike, Local, TimeZone, Timelike};
 use float::DumbFloat16;
 use hidapi::{HidApi, HidDevice};
-use types::{Icon, ScreenPosition, ScreenTheme, UploadChannel};
+use transport::HidTransport;
+use types::{Icon, ScreenPosition, ScreenTheme, Temperature, UploadChannel};
 use zoom_sync_core::{
-    Board, BoardError, BoardInfo, HasGif, HasImage, HasScreen, HasScreenSize, HasSystemInfo,
-    HasTime, HasWeather, Result, ScreenGroup, ScreenPosition as CoreScreenPosition,
+    Board, BoardError, BoardInfo, Capabilities, HasBattery, HasGif, HasImage, HasScreen,
+    HasScreenSize, HasSystemInfo, HasTheme, HasTime, HasWeather, Result, ScreenGroup,
+    ScreenPosition as CoreScreenPosition, DEFAULT_UPLOAD_RETRIES,
 };
 
 pub mod abi;
 pub mod checksum;
 pub mod float;
+pub mod transport;
 pub mod types;
 
 pub mod consts {
@@ -32,6 +36,20 @@ pub static INFO: BoardInfo = BoardInfo {
     product_id: consts::ZOOM65_PRODUCT_ID,
     usage_page: Some(consts::ZOOM65_USAGE_PAGE),
     usage: Some(consts::ZOOM65_USAGE),
+    capabilities: Capabilities {
+        time: true,
+        weather: true,
+        system_info: true,
+        screen: true,
+        theme: true,
+        image: true,
+        gif: true,
+        // no known command to read the battery level, the firmware draws its own battery screen
+        // and `HasBattery` always reports none
+        battery: false,
+        // no known brightness command, see the `Board` impl
+        brightness: false,
+    },
 };
 
 /// Screen positions for this board
@@ -92,40 +110,108 @@ pub static SCREEN_POSITIONS: &[CoreScreenPosition] = &[
 pub const SCREEN_WIDTH: u32 = 110;
 pub const SCREEN_HEIGHT: u32 = 110;
 
+/// Size of an encoded image: rgb565 plus an alpha byte for each pixel
+pub const IMAGE_SIZE: usize = (SCREEN_WIDTH * SCREEN_HEIGHT * 3) as usize;
+
+/// Encoded gifs must be smaller than this many bytes
+pub const GIF_MAX_SIZE: usize = 1013808;
+
+/// Bytes of media data sent in each upload payload
+pub const UPLOAD_CHUNK_SIZE: usize = 24;
+
+/// Hid report id prefixed to every command, unless configured otherwise
+pub const DEFAULT_REPORT_ID: u8 = 0;
+
 /// Lazy handle to hidapi
 static API: LazyLock<RwLock<HidApi>> =
     LazyLock::new(|| RwLock::new(HidApi::new().expect("failed to init hidapi")));
 
 /// High level abstraction for managing a zoom65 v3 keyboard
 pub struct Zoom65v3 {
-    pub device: HidDevice,
+    pub device: Box<dyn HidTransport>,
+    /// Explicit hid path the device was opened from, reused when reconnecting
+    path: Option<CString>,
     buf: [u8; 64],
+    upload_retries: u8,
+    report_id: u8,
+    /// Position last set with [`Zoom65v3::set_screen`], or `None` once any other command may
+    /// have moved the screen. Moving it with the keyboard's own controls can't be seen here.
+    screen: Option<ScreenPosition>,
 }
 
 impl Zoom65v3 {
     /// Find and open the device for modifications
     pub fn open() -> Result<Self> {
-        API.write().unwrap().refresh_devices()?;
-        let api = API.read().unwrap();
-        let this = Self {
-            device: api
-                .device_list()
-                .find(|d| {
-                    d.vendor_id() == consts::ZOOM65_VENDOR_ID
-                        && d.product_id() == consts::ZOOM65_PRODUCT_ID
-                        && d.usage_page() == consts::ZOOM65_USAGE_PAGE
-                        && d.usage() == consts::ZOOM65_USAGE
-                })
-                .ok_or(BoardError::DeviceNotFound)?
-                .open_device(&api)?,
+        Ok(Self::with_transport(Self::find_device()?))
+    }
+
+    /// Open the device at a specific hid path, skipping the vendor/product/usage search
+    pub fn open_path(path: &CStr) -> Result<Self> {
+        Ok(Self {
+            path: Some(path.to_owned()),
+            ..Self::with_transport(Self::open_hid_path(path)?)
+        })
+    }
+
+    /// Talk to the screen module over an already opened transport. Reconnecting still searches
+    /// for the real device.
+    pub fn with_transport(device: impl HidTransport + 'static) -> Self {
+        Self {
+            device: Box::new(device),
+            path: None,
             buf: [0u8; 64],
-        };
+            upload_retries: DEFAULT_UPLOAD_RETRIES,
+            report_id: DEFAULT_REPORT_ID,
+            screen: None,
+        }
+    }
 
-        Ok(this)
+    /// Re-run the device search (or reopen the explicit path) and swap in the new handle,
+    /// keeping all other state. The screen position is forgotten, since the keyboard may have
+    /// been power cycled.
+    pub fn reconnect(&mut self) -> Result<()> {
+        self.screen = None;
+        self.device = match &self.path {
+            Some(path) => Box::new(Self::open_hid_path(path)?),
+            None => Box::new(Self::find_device()?),
+        };
+        Ok(())
     }
 
-    /// Internal method to execute a payload and read the response
-    fn execute(&mut self, payload: [u8; 33]) -> Result<Vec<u8>> {
+    /// Refresh the device list and open the first matching screen interface
+    fn find_device() -> Result<HidDevice> {
+        API.write().unwrap().refresh_devices()?;
+        let api = API.read().unwrap();
+        let info = api
+            .device_list()
+            .find(|d| {
+                d.vendor_id() == consts::ZOOM65_VENDOR_ID
+                    && d.product_id() == consts::ZOOM65_PRODUCT_ID
+                    && d.usage_page() == consts::ZOOM65_USAGE_PAGE
+                    && d.usage() == consts::ZOOM65_USAGE
+            })
+            .ok_or(BoardError::DeviceNotFound)?;
+        info.open_device(&api)
+            .map_err(|e| BoardError::open_failed(e, info.path(), &INFO))
+    }
+
+    /// Open an explicit hid path
+    fn open_hid_path(path: &CStr) -> Result<HidDevice> {
+        API.read()
+            .unwrap()
+            .open_path(path)
+            .map_err(|e| BoardError::open_failed(e, path, &INFO))
+    }
+
+    /// Use a different hid report id for firmware variants of near identical boards
+    pub fn set_report_id(&mut self, id: u8) {
+        self.report_id = id;
+    }
+
+    /// Internal method to execute a payload and read the response.
+    /// The payload builders leave the report id zeroed, it's filled in here.
+    fn execute(&mut self, mut payload: [u8; 33]) -> Result<Vec<u8>> {
+        payload[0] = self.report_id;
         self.device.write(&payload)?;
         let len = self.device.read(&mut self.buf)?;
         let slice = &self.buf[..len];
@@ -133,9 +219,26 @@ impl Zoom65v3 {
         Ok(slice.to_vec())
     }
 
+    /// Write an arbitrary payload (including the leading report id), zero padded to a full
+    /// report, and return whatever the device responds with within a second
+    pub fn execute_raw(&mut self, payload: &[u8]) -> Result<Vec<u8>> {
+        self.screen = None;
+        let mut report = [0u8; 33];
+        report
+            .get_mut(..payload.len())
+            .ok_or(BoardError::CommandFailed(
+                "raw payload is longer than 33 bytes",
+            ))?
+            .copy_from_slice(payload);
+        self.device.write(&report)?;
+        let len = self.device.read_timeout(&mut self.buf, 1000)?;
+        Ok(self.buf[..len].to_vec())
+    }
+
     /// Set the screen theme. Will reset the screen back to the meletrix logo
     #[inline(always)]
     pub fn screen_theme(&mut self, theme: ScreenTheme) -> Result<()> {
+        self.screen = None;
         let res = self.execute(abi::screen_theme(theme))?;
         (res[1] == 1 && res[2] == 1)
             .then_some(())
@@ -145,6 +248,7 @@ impl Zoom65v3 {
     /// Increment the screen position
     #[inline(always)]
     pub fn screen_up(&mut self) -> Result<()> {
+        self.screen = None;
         let res = self.execute(abi::screen_up())?;
         (res[1] == 1 && res[2] == 1)
             .then_some(())
@@ -154,6 +258,7 @@ impl Zoom65v3 {
     /// Decrement the screen position
     #[inline(always)]
     pub fn screen_down(&mut self) -> Result<()> {
+        self.screen = None;
         let res = self.execute(abi::screen_down())?;
         (res[1] == 1 && res[2] == 1)
             .then_some(())
@@ -163,6 +268,7 @@ impl Zoom65v3 {
     /// Switch the active screen
     #[inline(always)]
     pub fn screen_switch(&mut self) -> Result<()> {
+        self.screen = None;
         let res = self.execute(abi::screen_switch())?;
         (res[1] == 1 && res[2] == 1)
             .then_some(())
@@ -172,18 +278,27 @@ impl Zoom65v3 {
     /// Reset the screen back to the meletrix logo
     #[inline(always)]
     pub fn reset_screen(&mut self) -> Result<()> {
+        self.screen = None;
         let res = self.execute(abi::reset_screen())?;
         (res[1] == 1 && res[2] == 1)
             .then_some(())
             .ok_or(BoardError::CommandFailed("device rejected command"))
     }
 
-    /// Set the screen to a specific position and offset
+    /// Set the screen to a specific position and offset. Moves straight there from the last set
+    /// position when that takes fewer commands than resetting first, and does nothing if the
+    /// screen is already there.
     pub fn set_screen(&mut self, position: ScreenPosition) -> Result<()> {
-        let (y, x) = position.to_directions();
-
-        // Back to default
-        self.reset_screen()?;
+        let steps = |(y, x): (isize, usize)| y.unsigned_abs() + x;
+        let from_default = position.to_directions();
+        let (y, x) = match self.screen.and_then(|from| from.directions_to(&position)) {
+            Some(relative) if steps(relative) < 1 + steps(from_default) => relative,
+            _ => {
+                // Back to default
+                self.reset_screen()?;
+                from_default
+            },
+        };
 
         // Move screen up or down
         match y {
@@ -205,32 +320,32 @@ impl Zoom65v3 {
             self.screen_switch()?;
         }
 
+        self.screen = Some(position);
         Ok(())
     }
 
     /// Update the keyboards current time.
-    /// If 12hr is true, hardcodes the time to 01:00-12:00 for the current day.
+    ///
+    /// The firmware only has a 24 hour clock and no AM/PM indicator. If `use_12hr` is true, the
+    /// hour is sent as 01-12 instead, so the caller must resync every hour to keep it in range.
     #[inline(always)]
-    pub fn set_time<Tz: TimeZone>(&mut self, time: DateTime<Tz>, _12hr: bool) -> Result<()> {
-        let res = self.execute(abi::set_time(
-            // Provide the current year without the century.
-            // This prevents overflows on the year 2256 (meletrix web ui just subtracts 2000)
-            (time.year() % 100) as u8,
-            time.month() as u8,
-            time.day() as u8,
-            if _12hr { time.hour12().1 } else { time.hour() } as u8,
-            time.minute() as u8,
-            time.second() as u8,
-        ))?;
+    pub fn set_time<Tz: TimeZone>(&mut self, time: DateTime<Tz>, use_12hr: bool) -> Result<()> {
+        let res = self.execute(time_payload(&time, use_12hr))?;
         (res[1] == 1 && res[2] == 1)
             .then_some(())
             .ok_or(BoardError::CommandFailed("device rejected command"))
     }
 
-    /// Update the keyboards current weather report
+    /// Update the keyboards current weather report.
+    /// Temperatures are clamped to the signed byte range the firmware displays (-128..=127).
     #[inline(always)]
-    pub fn set_weather(&mut self, icon: Icon, current: u8, low: u8, high: u8) -> Result<()> {
-        let res = self.execute(abi::set_weather(icon, current, low, high))?;
+    pub fn set_weather(&mut self, icon: Icon, current: i16, low: i16, high: i16) -> Result<()> {
+        let res = self.execute(abi::set_weather(
+            icon,
+            Temperature::new(current),
+            Temperature::new(low),
+            Temperature::new(high),
+        ))?;
         (res[1] == 1 && res[2] == 1)
             .then_some(())
             .ok_or(BoardError::CommandFailed("device rejected command"))
@@ -269,44 +384,11 @@ impl Zoom65v3 {
             return Err(BoardError::CommandFailed("device rejected command"));
         }
 
-        for (i, chunk) in image.chunks(24).enumerate() {
-            cb(i);
-
-            let chunk_len = chunk.len();
-            let mut buf = [0u8; 33];
-
-            // command prefix
-            buf[0] = 0x0;
-            buf[1] = 88;
-            buf[2] = 2 + chunk_len as u8 + 4;
-
-            // chunk index and data
-            buf[3] = (i >> 8) as u8;
-            buf[4] = (i & 255) as u8;
-            buf[5..5 + chunk.len()].copy_from_slice(chunk);
-
-            let mut offset = 3 + 2 + chunk_len;
-
-            // Images are always aligned, but we need to manually align the last chunk of gifs
-            if channel == UploadChannel::Gif && i == image.len() / 24 {
-                // compute padding for final payload, the checksum needs 32-bit alignment
-                let padding = (4 - (image.len() % 24) % 4) % 4;
-                buf[2] += padding as u8;
-                offset += padding;
-            }
-
-            // compute checksum
-            let data = &buf[3..offset + 2];
-            let crc = checksum(data);
-            buf[offset..offset + 4].copy_from_slice(&crc);
-
-            // send payload and read response
-            let res = self.execute(buf)?;
-            if res[1] != 1 || res[2] != 1 {
-                return Err(BoardError::CommandFailed("device rejected command"));
-            }
-        }
+        let retries = self.upload_retries;
+        send_chunks(image, channel, retries, |payload| self.execute(payload), cb)?;
 
+        // The firmware acks each chunk, but has no command to read back the stored media's length
+        // or checksum, so uploads can't be verified beyond this point
         let res = self.execute(abi::upload_end())?;
         if res[1] != 1 || res[2] != 1 {
             return Err(BoardError::CommandFailed("device rejected command"));
@@ -322,18 +404,14 @@ impl Zoom65v3 {
     #[inline(always)]
     pub fn upload_image(&mut self, buf: impl AsRef<[u8]>, mut cb: impl FnMut(usize)) -> Result<()> {
         let buf = buf.as_ref();
-        if buf.len() != 36300 {
-            return Err(BoardError::MediaTooLarge(
-                "image must be exactly 36300 bytes",
-            ));
-        }
+        validate_image(buf)?;
         self.upload_media(buf, UploadChannel::Image, &mut cb)
     }
 
     /// Upload a gif to the keyboard. Must be 111x111.
     #[inline(always)]
     pub fn upload_gif(&mut self, buf: impl AsRef<[u8]>, mut cb: impl FnMut(usize)) -> Result<()> {
-        if buf.as_ref().len() >= 1013808 {
+        if buf.as_ref().len() >= GIF_MAX_SIZE {
             return Err(BoardError::MediaTooLarge("gif exceeds device limit"));
         }
         self.upload_media(buf, UploadChannel::Gif, &mut cb)
@@ -342,6 +420,7 @@ impl Zoom65v3 {
     /// Clear the image slot
     #[inline(always)]
     pub fn clear_image(&mut self) -> Result<()> {
+        self.screen = None;
         let res = self.execute(abi::delete_image())?;
         (res[1] == 1 && res[2] == 1)
             .then_some(())
@@ -351,6 +430,7 @@ impl Zoom65v3 {
     /// Clear the gif slot
     #[inline(always)]
     pub fn clear_gif(&mut self) -> Result<()> {
+        self.screen = None;
         let res = self.execute(abi::delete_gif())?;
         (res[1] == 1 && res[2] == 1)
             .then_some(())
@@ -360,11 +440,53 @@ impl Zoom65v3 {
 
 // === Trait Implementations ===
 
+/// Check an encoded image matches the screen size, and is fully opaque as the firmware requires
+fn validate_image(buf: &[u8]) -> Result<()> {
+    if buf.len() != IMAGE_SIZE {
+        let pixels = buf.len() / 3;
+        let side = pixels.isqrt();
+        let actual = if buf.len().is_multiple_of(3) && side * side == pixels {
+            format!("{side}x{side}")
+        } else {
+            "a non-square image".into()
+        };
+        return Err(BoardError::InvalidImage(format!(
+            "expected {SCREEN_WIDTH}x{SCREEN_HEIGHT} ({IMAGE_SIZE} bytes), got {} bytes ({actual})",
+            buf.len()
+        )));
+    }
+    if let Some(i) = buf.chunks_exact(3).position(|px| px[2] != 0xff) {
+        return Err(BoardError::InvalidImage(format!(
+            "pixel {i} has alpha {:#04x}, all pixels must be opaque (0xff)",
+            buf[i * 3 + 2]
+        )));
+    }
+    Ok(())
+}
+
+/// The screen brightness command of the zoom65v3 firmware isn't known, so `as_brightness` keeps
+/// the default and brightness control is reported as unsupported.
 impl Board for Zoom65v3 {
     fn info(&self) -> &'static BoardInfo {
         &INFO
     }
 
+    fn reconnect(&mut self) -> Result<()> {
+        Zoom65v3::reconnect(self)
+    }
+
+    fn set_upload_retries(&mut self, retries: u8) {
+        self.upload_retries = retries;
+    }
+
+    fn set_report_id(&mut self, id: u8) {
+        Zoom65v3::set_report_id(self, id)
+    }
+
+    fn execute_raw(&mut self, payload: &[u8]) -> Result<Vec<u8>> {
+        Zoom65v3::execute_raw(self, payload)
+    }
+
     fn as_time(&mut self) -> Option<&mut dyn HasTime> {
         Some(self)
     }
@@ -381,6 +503,10 @@ impl Board for Zoom65v3 {
         Some(self)
     }
 
+    fn as_theme(&mut self) -> Option<&mut dyn HasTheme> {
+        Some(self)
+    }
+
     fn as_screen_size(&self) -> Option<(u32, u32)> {
         Some((SCREEN_WIDTH, SCREEN_HEIGHT))
     }
@@ -392,6 +518,10 @@ impl Board for Zoom65v3 {
     fn as_gif(&mut self) -> Option<&mut dyn HasGif> {
         Some(self)
     }
+
+    fn as_battery(&mut self) -> Option<&mut dyn HasBattery> {
+        Some(self)
+    }
 }
 
 impl HasTime for Zoom65v3 {
@@ -401,9 +531,19 @@ impl HasTime for Zoom65v3 {
 }
 
 impl HasWeather for Zoom65v3 {
-    fn set_weather(&mut self, wmo: u8, is_day: bool, current: u8, low: u8, high: u8) -> Result<()> {
-        let icon =
-            Icon::from_wmo(wmo, is_day).ok_or(BoardError::CommandFailed("unknown WMO code"))?;
+    fn set_weather(
+        &mut self,
+        wmo: u8,
+        is_day: bool,
+        current: i16,
+        low: i16,
+        high: i16,
+    ) -> Result<()> {
+        // A new code from the weather provider shouldn't take the whole weather screen down
+        let icon = Icon::from_wmo(wmo, is_day).unwrap_or_else(|| {
+            log::warn!("unknown WMO code {wmo}, showing it as cloudy");
+            Icon::Cloudy
+        });
         Zoom65v3::set_weather(self, icon, current, low, high)
     }
 }
@@ -412,6 +552,28 @@ impl HasSystemInfo for Zoom65v3 {
     fn set_system_info(&mut self, cpu: u8, gpu: u8, download: f32) -> Result<()> {
         Zoom65v3::set_system_info(self, cpu, gpu, download)
     }
+    fn max_download(&self) -> Option<f32> {
+        // The screen has no unit indicator, so larger rates can't be shown in GB/s
+        Some(DumbFloat16::MAX_F32)
+    }
+}
+
+impl HasTheme for Zoom65v3 {
+    fn available_themes(&self) -> &'static [(u8, &'static str)] {
+        ScreenTheme::ALL
+    }
+
+    fn set_theme(&mut self, theme_id: u8) -> Result<()> {
+        Zoom65v3::screen_theme(self, theme_id.try_into().map_err(BoardError::InvalidTheme)?)
+    }
+
+    fn set_theme_color(&mut self, rgb: [u8; 3]) -> Result<()> {
+        Zoom65v3::screen_theme(self, ScreenTheme::nearest(rgb))
+    }
+
+    fn default_theme(&self) -> Option<u8> {
+        Some(ScreenTheme::default() as u8)
+    }
 }
 
 impl HasScreen for Zoom65v3 {
@@ -440,6 +602,13 @@ impl HasScreen for Zoom65v3 {
     }
 }
 
+impl HasBattery for Zoom65v3 {
+    fn battery_percent(&mut self) -> Result<Option<u8>> {
+        // The battery screen is drawn by the firmware, no command to read the level is known
+        Ok(None)
+    }
+}
+
 impl HasScreenSize for Zoom65v3 {
     fn screen_size(&self) -> (u32, u32) {
         (SCREEN_WIDTH, SCREEN_HEIGHT)
@@ -451,6 +620,10 @@ impl HasImage for Zoom65v3 {
         Zoom65v3::upload_image(self, data, progress)
     }
 
+    fn chunk_size(&self) -> usize {
+        UPLOAD_CHUNK_SIZE
+    }
+
     fn clear_image(&mut self) -> Result<()> {
         Zoom65v3::clear_image(self)
     }
@@ -461,7 +634,375 @@ impl HasGif for Zoom65v3 {
         Zoom65v3::upload_gif(self, data, progress)
     }
 
+    fn chunk_size(&self) -> usize {
+        UPLOAD_CHUNK_SIZE
+    }
+
+    fn max_size(&self) -> Option<usize> {
+        Some(GIF_MAX_SIZE)
+    }
+
     fn clear_gif(&mut self) -> Result<()> {
         Zoom65v3::clear_gif(self)
     }
 }
+
+/// Send every chunk of an upload, resending rejected chunks up to `retries` times. `cb` is called
+/// once per chunk after it's accepted.
+fn send_chunks(
+    image: &[u8],
+    channel: UploadChannel,
+    retries: u8,
+    mut execute: impl FnMut([u8; 33]) -> Result<Vec<u8>>,
+    cb: &mut dyn FnMut(usize),
+) -> Result<()> {
+    let last = image.len().div_ceil(UPLOAD_CHUNK_SIZE).saturating_sub(1);
+    for (i, chunk) in image.chunks(UPLOAD_CHUNK_SIZE).enumerate() {
+        // Images are always aligned, but we need to manually align the last chunk of gifs
+        let pad = channel == UploadChannel::Gif && i == last;
+        let payload = chunk_payload(i, chunk, pad);
+
+        // send payload and read response, resending the same chunk index if it was rejected
+        let mut attempts = 0;
+        loop {
+            let res = execute(payload)?;
+            if res[1] == 1 && res[2] == 1 {
+                break;
+            }
+            if attempts == retries {
+                return Err(BoardError::CommandFailed("device rejected command"));
+            }
+            attempts += 1;
+        }
+
+        cb(i + 1);
+    }
+    Ok(())
+}
+
+/// Build the set time payload, with the hour in 12 or 24 hour format
+fn time_payload<Tz: TimeZone>(time: &DateTime<Tz>, use_12hr: bool) -> [u8; 33] {
+    let hour = if use_12hr {
+        time.hour12().1
+    } else {
+        time.hour()
+    };
+    abi::set_time(
+        // Provide the current year without the century.
+        // This prevents overflows on the year 2256 (meletrix web ui just subtracts 2000)
+        (time.year() % 100) as u8,
+        time.month() as u8,
+        time.day() as u8,
+        hour as u8,
+        time.minute() as u8,
+        time.second() as u8,
+    )
+}
+
+/// Build the upload payload for a chunk of media. With `pad`, the chunk is zero padded so the
+/// checksum stays 32-bit aligned, which the final chunk of a gif needs.
+fn chunk_payload(index: usize, chunk: &[u8], pad: bool) -> [u8; 33] {
+    let chunk_len = chunk.len();
+    let mut buf = [0u8; 33];
+
+    // command prefix
+    buf[0] = 0x0;
+    buf[1] = 88;
+    buf[2] = 2 + chunk_len as u8 + 4;
+
+    // chunk index and data
+    buf[3] = (index >> 8) as u8;
+    buf[4] = (index & 255) as u8;
+    buf[5..5 + chunk_len].copy_from_slice(chunk);
+
+    let mut offset = 3 + 2 + chunk_len;
+
+    if pad {
+        let padding = (4 - chunk_len % 4) % 4;
+        buf[2] += padding as u8;
+        offset += padding;
+    }
+
+    // compute checksum
+    let data = &buf[3..offset + 2];
+    let crc = checksum(data);
+    buf[offset..offset + 4].copy_from_slice(&crc);
+    buf
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{LogoOffset, SystemOffset, TimeOffset};
+
+    /// Chunk payloads for a gif of `len` bytes, as sent by `upload_media`
+    fn gif_payloads(len: usize) -> Vec<[u8; 33]> {
+        let gif = vec![0xaa; len];
+        let last = len.div_ceil(UPLOAD_CHUNK_SIZE) - 1;
+        gif.chunks(UPLOAD_CHUNK_SIZE)
+            .enumerate()
+            .map(|(i, chunk)| chunk_payload(i, chunk, i == last))
+            .collect()
+    }
+
+    #[test]
+    fn afternoon_hours_are_sent_in_the_time_format() {
+        let time = Local.with_ymd_and_hms(2025, 1, 1, 13, 0, 0).unwrap();
+        // year, month, day, hour, minute, second
+        assert_eq!(time_payload(&time, true)[6..12], [25, 1, 1, 1, 0, 0]);
+        assert_eq!(time_payload(&time, false)[6..12], [25, 1, 1, 13, 0, 0]);
+        // midnight is 12 on a 12 hour clock
+        let time = Local.with_ymd_and_hms(2025, 1, 1, 0, 30, 0).unwrap();
+        assert_eq!(time_payload(&time, true)[9], 12);
+        assert_eq!(time_payload(&time, false)[9], 0);
+    }
+
+    #[test]
+    fn gif_chunks_are_aligned() {
+        // Both exact multiples of the chunk size and every possible remainder
+        for len in (UPLOAD_CHUNK_SIZE * 3)..=(UPLOAD_CHUNK_SIZE * 4) {
+            let payloads = gif_payloads(len);
+            assert_eq!(payloads.len(), len.div_ceil(UPLOAD_CHUNK_SIZE));
+            for payload in &payloads {
+                // length byte covers the 2 byte index, data, padding, and 4 byte checksum
+                let data_len = payload[2] as usize - 6;
+                assert_eq!(data_len % 4, 0, "unaligned chunk for a {len} byte gif");
+            }
+        }
+    }
+
+    #[test]
+    fn final_gif_chunk_is_padded() {
+        let payloads = gif_payloads(UPLOAD_CHUNK_SIZE + 5);
+        let last = payloads.last().unwrap();
+        // 5 bytes of data padded to 8, with the checksum right after the padding
+        assert_eq!(last[2], 2 + 8 + 4);
+        assert_eq!(&last[10..13], &[0, 0, 0]);
+        // the checksummed region is the index, data, and padding, extended with two zero bytes
+        let mut region = last[3..13].to_vec();
+        region.extend([0, 0]);
+        assert_eq!(last[13..17], checksum(&region));
+    }
+
+    /// Mock device that rejects the second chunk once, recording the chunk index of each write
+    fn nak_once(sent: &mut Vec<u16>) -> impl FnMut([u8; 33]) -> Result<Vec<u8>> + '_ {
+        let mut rejected = false;
+        move |payload| {
+            let index = u16::from_be_bytes([payload[3], payload[4]]);
+            sent.push(index);
+            let ok = index != 1 || std::mem::replace(&mut rejected, true);
+            Ok(vec![payload[1], ok as u8, ok as u8])
+        }
+    }
+
+    #[test]
+    fn rejected_chunk_is_resent() {
+        let mut sent = Vec::new();
+        let mut progress = Vec::new();
+        let image = vec![0xaa; UPLOAD_CHUNK_SIZE * 3];
+        send_chunks(
+            &image,
+            UploadChannel::Image,
+            DEFAULT_UPLOAD_RETRIES,
+            nak_once(&mut sent),
+            &mut |i| progress.push(i),
+        )
+        .unwrap();
+        assert_eq!(sent, [0, 1, 1, 2]);
+        assert_eq!(progress, [1, 2, 3]);
+    }
+
+    #[test]
+    fn rejected_chunk_fails_without_retries() {
+        let mut sent = Vec::new();
+        let image = vec![0xaa; UPLOAD_CHUNK_SIZE * 3];
+        let res = send_chunks(
+            &image,
+            UploadChannel::Image,
+            0,
+            nak_once(&mut sent),
+            &mut |_| {},
+        );
+        assert!(matches!(res, Err(BoardError::CommandFailed(_))));
+        assert_eq!(sent, [0, 1]);
+    }
+
+    /// Transport that acks every command and records each written report
+    #[derive(Clone, Default)]
+    struct Recorder {
+        writes: std::sync::Arc<std::sync::Mutex<Vec<Vec<u8>>>>,
+    }
+
+    impl HidTransport for Recorder {
+        fn write(&mut self, data: &[u8]) -> Result<usize> {
+            self.writes.lock().unwrap().push(data.to_vec());
+            Ok(data.len())
+        }
+
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+            let command = self.writes.lock().unwrap().last().unwrap()[1];
+            buf[..3].copy_from_slice(&[command, 1, 1]);
+            Ok(3)
+        }
+
+        fn read_timeout(&mut self, buf: &mut [u8], _timeout_ms: i32) -> Result<usize> {
+            self.read(buf)
+        }
+    }
+
+    #[test]
+    fn upload_image_writes_expected_reports() {
+        let recorder = Recorder::default();
+        let mut board = Zoom65v3::with_transport(recorder.clone());
+        let image = [0x12, 0x34, 0xff].repeat(IMAGE_SIZE / 3);
+        board.upload_image(&image, |_| {}).unwrap();
+
+        let writes = recorder.writes.lock().unwrap();
+        let chunks = IMAGE_SIZE.div_ceil(UPLOAD_CHUNK_SIZE);
+        // start, length, every chunk, end, and the screen reset
+        assert_eq!(writes.len(), 2 + chunks + 2);
+        assert!(writes.iter().all(|report| report.len() == 33));
+        assert_eq!(
+            writes[0].as_slice(),
+            abi::upload_start(UploadChannel::Image)
+        );
+        assert_eq!(writes[1].as_slice(), abi::upload_length(IMAGE_SIZE as u32));
+
+        let first = &writes[2];
+        // command, length byte (index + data + checksum), big endian chunk index
+        assert_eq!(&first[1..5], &[88, 2 + 24 + 4, 0, 0]);
+        assert_eq!(&first[5..29], &image[..24]);
+        assert_eq!(&first[29..33], &[0x5e, 0xc0, 0x01, 0x86]);
+
+        // the image doesn't divide into whole chunks, so the last one is shorter
+        let last = &writes[2 + chunks - 1];
+        let rest = IMAGE_SIZE % UPLOAD_CHUNK_SIZE;
+        let index = (chunks - 1) as u16;
+        assert_eq!(last[2] as usize, 2 + rest + 4);
+        assert_eq!(&last[3..5], &index.to_be_bytes());
+        let mut region = last[3..5 + rest].to_vec();
+        region.extend([0, 0]);
+        assert_eq!(last[5 + rest..9 + rest], checksum(&region));
+
+        assert_eq!(writes[2 + chunks].as_slice(), abi::upload_end());
+        assert_eq!(writes[3 + chunks].as_slice(), abi::reset_screen());
+    }
+
+    #[test]
+    fn reselecting_the_screen_is_skipped() {
+        let recorder = Recorder::default();
+        let mut board = Zoom65v3::with_transport(recorder.clone());
+        let writes = || recorder.writes.lock().unwrap().len();
+        let weather = ScreenPosition::Time(TimeOffset::Weather);
+
+        // reset, up once, switch once
+        board.set_screen(weather).unwrap();
+        assert_eq!(writes(), 3);
+        board.set_screen(weather).unwrap();
+        assert_eq!(writes(), 3);
+
+        // any other movement means the screen has to be set again
+        board.screen_up().unwrap();
+        board.set_screen(weather).unwrap();
+        assert_eq!(writes(), 7);
+        board
+            .set_screen(ScreenPosition::Logo(LogoOffset::Meletrix))
+            .unwrap();
+        assert_eq!(writes(), 8);
+    }
+
+    #[test]
+    fn screen_moves_relative_to_the_last_position() {
+        let recorder = Recorder::default();
+        let mut board = Zoom65v3::with_transport(recorder.clone());
+        let sent = |from: usize| recorder.writes.lock().unwrap()[from..].to_vec();
+
+        // weather to time wraps around the row instead of resetting and walking up
+        board.set_screen(TimeOffset::Weather.pos()).unwrap();
+        board.set_screen(TimeOffset::Time.pos()).unwrap();
+        assert_eq!(sent(3), [abi::screen_switch()]);
+
+        // time to gpu goes up a row and over
+        board.set_screen(SystemOffset::GpuTemp.pos()).unwrap();
+        assert_eq!(sent(4), [abi::screen_up(), abi::screen_switch()]);
+
+        // leaving gpu for the logo row resets, as where the row change lands isn't known
+        board.set_screen(LogoOffset::Meletrix.pos()).unwrap();
+        assert_eq!(sent(6), [abi::reset_screen()]);
+    }
+
+    #[test]
+    fn info_capabilities_match_board() {
+        let mut board = Zoom65v3::with_transport(Recorder::default());
+        // the battery is queried, but no level is ever reported, so it isn't advertised
+        let capabilities = Capabilities {
+            battery: false,
+            ..Capabilities::of(&mut board)
+        };
+        assert_eq!(capabilities, INFO.capabilities);
+        assert_eq!(board.battery_percent().unwrap(), None);
+    }
+
+    #[test]
+    fn sub_zero_weather_survives_encoding() {
+        let recorder = Recorder::default();
+        let mut board = Zoom65v3::with_transport(recorder.clone());
+        HasWeather::set_weather(&mut board, 71, true, -5, -12, 0).unwrap();
+
+        let writes = recorder.writes.lock().unwrap();
+        let expected = abi::set_weather(
+            Icon::Snowfall,
+            Temperature::new(-5),
+            Temperature::new(-12),
+            Temperature::new(0),
+        );
+        assert_eq!(writes.as_slice(), [expected.to_vec()]);
+        assert_eq!(writes[0][7..10], [(-5i8) as u8, (-12i8) as u8, 0]);
+    }
+
+    #[test]
+    fn open_errors_explain_how_to_get_access() {
+        let path = c"/dev/hidraw3";
+        let denied = hidapi::HidError::HidApiError {
+            message: "Failed to open a device with path '/dev/hidraw3': Permission denied".into(),
+        };
+        let err = BoardError::open_failed(denied, path, &INFO);
+        assert!(matches!(err, BoardError::PermissionDenied { .. }));
+        let message = err.to_string();
+        assert!(message.starts_with("permission denied opening /dev/hidraw3"));
+        if cfg!(target_os = "linux") {
+            assert!(message.contains(r#"ATTRS{idVendor}=="36b5", ATTRS{idProduct}=="287f""#));
+        }
+
+        let busy = hidapi::HidError::IoError {
+            error: std::io::ErrorKind::ResourceBusy.into(),
+        };
+        let err = BoardError::open_failed(busy, path, &INFO);
+        assert!(matches!(err, BoardError::DeviceBusy { path } if path == "/dev/hidraw3"));
+
+        let other = BoardError::open_failed(hidapi::HidError::HidApiErrorEmpty, path, &INFO);
+        assert!(matches!(other, BoardError::Hid(_)));
+    }
+
+    #[test]
+    fn validate_image_accepts_encoded_size() {
+        assert!(validate_image(&[0, 0, 0xff].repeat(IMAGE_SIZE / 3)).is_ok());
+    }
+
+    #[test]
+    fn validate_image_rejects_wrong_size() {
+        let err = validate_image(&[0, 0, 0xff].repeat(100 * 100)).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid image: expected 110x110 (36300 bytes), got 30000 bytes (100x100)"
+        );
+    }
+
+    #[test]
+    fn validate_image_rejects_transparent_pixels() {
+        let mut buf = [0, 0, 0xff].repeat(IMAGE_SIZE / 3);
+        buf[5] = 0x80;
+        let err = validate_image(&buf).unwrap_err();
+        assert!(matches!(err, BoardError::InvalidImage(_)));
+    }
+}
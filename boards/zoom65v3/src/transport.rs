@@ -0,0 +1,28 @@
+//! Raw report io, abstracted so the command framing can be tested without a device
+
+use hidapi::HidDevice;
+use zoom_sync_core::Result;
+
+/// Writes hid reports to the screen module and reads its responses
+pub trait HidTransport: Send {
+    /// Write a single report, including the leading report id
+    fn write(&mut self, data: &[u8]) -> Result<usize>;
+    /// Block until a response report is read into `buf`, returning its length
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize>;
+    /// Read a response report, giving up after `timeout_ms` with a length of zero
+    fn read_timeout(&mut self, buf: &mut [u8], timeout_ms: i32) -> Result<usize>;
+}
+
+impl HidTransport for HidDevice {
+    fn write(&mut self, data: &[u8]) -> Result<usize> {
+        Ok(HidDevice::write(self, data)?)
+    }
+
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        Ok(HidDevice::read(self, buf)?)
+    }
+
+    fn read_timeout(&mut self, buf: &mut [u8], timeout_ms: i32) -> Result<usize> {
+        Ok(HidDevice::read_timeout(self, buf, timeout_ms)?)
+    }
+}
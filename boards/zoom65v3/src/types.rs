@@ -9,6 +9,66 @@ pub enum ScreenTheme {
     Pink = 2,
 }
 
+impl ScreenTheme {
+    /// All themes supported by the firmware, as `(id, name)` pairs
+    pub const ALL: &'static [(u8, &'static str)] = &[
+        (ScreenTheme::Blue as u8, "blue"),
+        (ScreenTheme::Pink as u8, "pink"),
+    ];
+
+    /// Approximate hue of the theme's accent color, in degrees
+    const fn hue(&self) -> f32 {
+        match self {
+            ScreenTheme::Blue => 210.,
+            ScreenTheme::Pink => 330.,
+        }
+    }
+
+    /// Find the theme closest in hue to an rgb color. Greys have no hue and use the default theme.
+    pub fn nearest(rgb: [u8; 3]) -> Self {
+        let Some(hue) = hue(rgb) else {
+            return Self::default();
+        };
+        let distance = |theme: &Self| {
+            let d = (theme.hue() - hue).abs();
+            d.min(360. - d)
+        };
+        [Self::Blue, Self::Pink]
+            .into_iter()
+            .min_by(|a, b| distance(a).total_cmp(&distance(b)))
+            .unwrap()
+    }
+}
+
+/// Compute the hue of an rgb color in degrees, or `None` for greys
+fn hue([r, g, b]: [u8; 3]) -> Option<f32> {
+    let [r, g, b] = [r, g, b].map(|c| c as f32 / 255.);
+    let max = r.max(g).max(b);
+    let delta = max - r.min(g).min(b);
+    if delta == 0. {
+        return None;
+    }
+    let hue = if max == r {
+        ((g - b) / delta).rem_euclid(6.)
+    } else if max == g {
+        (b - r) / delta + 2.
+    } else {
+        (r - g) / delta + 4.
+    };
+    Some(hue * 60.)
+}
+
+impl TryFrom<u8> for ScreenTheme {
+    type Error = u8;
+    fn try_from(id: u8) -> Result<Self, Self::Error> {
+        match id {
+            1 => Ok(Self::Blue),
+            2 => Ok(Self::Pink),
+            id => Err(id),
+        }
+    }
+}
+
 impl Arg for ScreenTheme {
     const SIZE: usize = 1;
     fn to_bytes(&self) -> Vec<u8> {
@@ -16,6 +76,29 @@ impl Arg for ScreenTheme {
     }
 }
 
+/// Temperature in whole degrees, sent to the keyboard as a single two's complement byte.
+///
+/// The firmware renders the byte as a signed value, so sub-zero readings survive as long as they
+/// are sign-encoded rather than clamped to zero. The effective range is `-128..=127`, which covers
+/// any realistic celsius or fahrenheit reading (including 100F+ summer highs).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Temperature(i8);
+
+impl Temperature {
+    /// Create a new temperature, clamping at the device's minimum and maximum values
+    pub fn new(degrees: i16) -> Self {
+        Self(degrees.clamp(i8::MIN as i16, i8::MAX as i16) as i8)
+    }
+}
+
+impl Arg for Temperature {
+    const SIZE: usize = 1;
+    #[inline(always)]
+    fn to_bytes(&self) -> Vec<u8> {
+        self.0.to_be_bytes().to_vec()
+    }
+}
+
 /// Channel to start uploading to
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 #[repr(u8)]
@@ -32,7 +115,7 @@ impl Arg for UploadChannel {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 #[repr(u8)]
 pub enum Icon {
     DayClear = 0,
@@ -74,9 +157,10 @@ impl Icon {
             // rain showers
             80..=82 => Some(if is_day { Icon::DayPartlyRainy } else { Icon::Rainy }),
 
-
             // snowfall
-            | 71 | 73 | 75 | 77
+            71 | 73 | 75
+            // snow grains
+            | 77
             // snow showers
             | 85 | 86 => Some(Icon::Snowfall),
 
@@ -124,6 +208,32 @@ impl ScreenPosition {
             ScreenPosition::Battery => (1, 0),
         }
     }
+
+    /// Number of offsets switching cycles through on this position's row
+    pub fn row_len(&self) -> usize {
+        match self {
+            ScreenPosition::System(_) => 3,
+            ScreenPosition::Time(_) => 2,
+            ScreenPosition::Logo(_) => 4,
+            ScreenPosition::Battery => 1,
+        }
+    }
+
+    /// Directions from this position straight to `target` as `[up/down, shift]`, without a reset.
+    /// Switching wraps around within a row, and moving up or down lands on the first offset of
+    /// the new row. `None` when leaving a row from any other offset, since where that lands
+    /// isn't known.
+    pub fn directions_to(&self, target: &ScreenPosition) -> Option<(isize, usize)> {
+        let (y, x) = self.to_directions();
+        let (target_y, target_x) = target.to_directions();
+        if y == target_y {
+            Some((0, (target_x + self.row_len() - x) % self.row_len()))
+        } else if x == 0 {
+            Some((target_y - y, target_x))
+        } else {
+            None
+        }
+    }
 }
 
 impl FromStr for ScreenPosition {
@@ -193,3 +303,68 @@ impl LogoOffset {
         ScreenPosition::Logo(*self)
     }
 }
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn relative_directions() {
+        let pos = |s: &str| s.parse::<ScreenPosition>().unwrap();
+        let directions = |from: &str, to: &str| pos(from).directions_to(&pos(to));
+        // switching within a row, wrapping around at the end
+        assert_eq!(directions("weather", "time"), Some((0, 1)));
+        assert_eq!(directions("cpu", "download"), Some((0, 2)));
+        assert_eq!(directions("gif", "zoom65"), Some((0, 2)));
+        assert_eq!(directions("battery", "battery"), Some((0, 0)));
+        // moving rows from the first offset
+        assert_eq!(directions("cpu", "weather"), Some((1, 1)));
+        assert_eq!(directions("time", "battery"), Some((2, 0)));
+        assert_eq!(directions("battery", "gpu"), Some((-3, 1)));
+        assert_eq!(directions("meletrix", "image"), Some((0, 2)));
+        // leaving a row from another offset needs a reset
+        assert_eq!(directions("weather", "cpu"), None);
+        assert_eq!(directions("gif", "battery"), None);
+    }
+
+    /// Every weather code documented at the bottom of <https://open-meteo.com/en/docs>
+    const WMO_CODES: [u8; 28] = [
+        0, 1, 2, 3, 45, 48, 51, 53, 55, 56, 57, 61, 63, 65, 66, 67, 71, 73, 75, 77, 80, 81, 82, 85,
+        86, 95, 96, 99,
+    ];
+
+    #[test]
+    fn every_documented_wmo_code_has_an_icon() {
+        for code in WMO_CODES {
+            for is_day in [true, false] {
+                assert!(
+                    Icon::from_wmo(code, is_day).is_some(),
+                    "wmo {code} (day: {is_day})"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn undocumented_wmo_codes_have_no_icon() {
+        assert_eq!(Icon::from_wmo(4, true), None);
+        assert_eq!(Icon::from_wmo(100, false), None);
+    }
+
+    #[test]
+    fn screen_theme_from_id() {
+        assert_eq!(ScreenTheme::try_from(1), Ok(ScreenTheme::Blue));
+        assert_eq!(ScreenTheme::try_from(2), Ok(ScreenTheme::Pink));
+        assert_eq!(ScreenTheme::try_from(3), Err(3));
+    }
+
+    #[test]
+    fn screen_theme_nearest_hue() {
+        assert_eq!(ScreenTheme::nearest([0, 0, 255]), ScreenTheme::Blue);
+        assert_eq!(ScreenTheme::nearest([0, 255, 255]), ScreenTheme::Blue);
+        assert_eq!(ScreenTheme::nearest([255, 105, 180]), ScreenTheme::Pink);
+        assert_eq!(ScreenTheme::nearest([255, 0, 0]), ScreenTheme::Pink);
+        assert_eq!(ScreenTheme::nearest([128, 128, 128]), ScreenTheme::Blue);
+    }
+}
@@ -1,28 +1,67 @@
 //! Configuration file handling for tray mode
 
+use std::collections::BTreeMap;
 use std::error::Error;
 use std::fs;
 use std::path::PathBuf;
+use std::sync::OnceLock;
 use std::time::Duration;
 
 use directories::ProjectDirs;
+use log::{info, warn};
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+use crate::media::{ColorCorrection, ResizeMode};
+use crate::weather::{GeoSource, Provider};
+
+/// Current config schema version, bumped whenever keys are renamed or change meaning
+pub const CONFIG_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(default)]
-#[derive(Default)]
 pub struct Config {
+    /// Schema version, used to migrate older config files on load
+    pub version: u32,
     pub general: GeneralConfig,
     pub refresh: RefreshConfig,
     pub weather: WeatherConfig,
     pub system_info: SystemInfoConfig,
     pub media: MediaConfig,
+    pub display: DisplayConfig,
+    /// Overrides for a single board, keyed by its cli name, eg `[boards.zoom65v3]`
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub boards: BTreeMap<String, BoardConfig>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            version: CONFIG_VERSION,
+            general: Default::default(),
+            refresh: Default::default(),
+            weather: Default::default(),
+            system_info: Default::default(),
+            media: Default::default(),
+            display: Default::default(),
+            boards: Default::default(),
+        }
+    }
 }
 
+static PATH_OVERRIDE: OnceLock<PathBuf> = OnceLock::new();
+
 impl Config {
-    /// Get the config file path for this platform
+    /// Use `path` as the config file for the rest of the process instead of the platform
+    /// default. Only the first call has any effect.
+    pub fn set_path(path: PathBuf) {
+        let _ = PATH_OVERRIDE.set(path);
+    }
+
+    /// Get the config file path, either the override or the default for this platform
     pub fn path() -> Option<PathBuf> {
-        ProjectDirs::from("", "", "zoom-sync").map(|dirs| dirs.config_dir().join("config.toml"))
+        PATH_OVERRIDE.get().cloned().or_else(|| {
+            ProjectDirs::from("", "", "zoom-sync").map(|dirs| dirs.config_dir().join("config.toml"))
+        })
     }
 
     /// Load config from file, or create default if it doesn't exist
@@ -31,12 +70,19 @@ impl Config {
 
         if path.exists() {
             let contents = fs::read_to_string(&path)?;
-            let config: Config = toml::from_str(&contents)?;
+            let (config, migrated) = Self::parse(&contents)?;
+            if migrated {
+                config.save()?;
+                info!(
+                    "migrated config at {} to version {CONFIG_VERSION}",
+                    path.display()
+                );
+            }
             Ok(config)
         } else {
             let config = Config::default();
             config.save_with_header()?;
-            println!("created default config at {}", path.display());
+            info!("created default config at {}", path.display());
             Ok(config)
         }
     }
@@ -75,20 +121,164 @@ impl Config {
     pub fn reload(&mut self) -> Result<(), Box<dyn Error>> {
         let path = Self::path().ok_or("could not determine config directory")?;
         let contents = fs::read_to_string(&path)?;
-        *self = toml::from_str(&contents)?;
+        let (config, migrated) = Self::parse(&contents)?;
+        if migrated {
+            config.save()?;
+        }
+        *self = config;
         Ok(())
     }
+
+    /// General settings for the board named `cli_name`, with its `[boards.<cli_name>]` section
+    /// layered on top
+    pub fn general_for(&self, cli_name: &str) -> GeneralConfig {
+        let mut general = self.general.clone();
+        if let Some(board) = self.boards.get(cli_name) {
+            if let Some(screen) = &board.initial_screen {
+                general.initial_screen.clone_from(screen);
+            }
+            if let Some(device) = &board.reactive_device {
+                general.reactive_device = Some(device.clone());
+            }
+        }
+        general
+    }
+
+    /// Remember `id` as the initial screen for the board named `cli_name`. Boards with their own
+    /// initial screen keep it in their section, everything else shares the general one.
+    pub fn set_initial_screen(&mut self, cli_name: &str, id: &str) {
+        match self
+            .boards
+            .get_mut(cli_name)
+            .and_then(|board| board.initial_screen.as_mut())
+        {
+            Some(screen) => *screen = id.to_string(),
+            None => self.general.initial_screen = id.to_string(),
+        }
+    }
+
+    /// Screen that `set screen --next` and `--prev` step from for the board named `cli_name`:
+    /// the last one set from the cli, or the initial screen before that
+    pub fn current_screen(&self, cli_name: &str) -> String {
+        self.boards
+            .get(cli_name)
+            .and_then(|board| board.current_screen.clone())
+            .unwrap_or_else(|| self.general_for(cli_name).initial_screen)
+    }
+
+    /// Remember `id` as the screen the board named `cli_name` is currently showing
+    pub fn set_current_screen(&mut self, cli_name: &str, id: &str) {
+        self.boards
+            .entry(cli_name.to_string())
+            .or_default()
+            .current_screen = Some(id.to_string());
+    }
+
+    /// Config as pretty printed json, for `zoom-sync config export`
+    pub fn to_json(&self) -> Result<String, Box<dyn Error>> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Parse a json export, upgrading older versions. Unknown keys fail the import when
+    /// `strict`, and are only warned about otherwise.
+    pub fn from_json(contents: &str, strict: bool) -> Result<Self, Box<dyn Error>> {
+        let mut value: serde_json::Value = serde_json::from_str(contents)?;
+        // toml has no null, unset options are left out instead
+        strip_nulls(&mut value);
+        let table = toml::Table::deserialize(value)?;
+        Ok(Self::from_table(table, strict)?.0)
+    }
+
+    /// Parse a config file, upgrading older versions. Also returns whether it was migrated and
+    /// should be written back.
+    fn parse(contents: &str) -> Result<(Self, bool), Box<dyn Error>> {
+        Self::from_table(toml::from_str(contents)?, false)
+    }
+
+    /// Deserialize a raw config table after migrating it, checking for unknown keys
+    fn from_table(mut table: toml::Table, strict: bool) -> Result<(Self, bool), Box<dyn Error>> {
+        let migrated = migrate(&mut table);
+        let config: Self = table.clone().try_into()?;
+
+        // Unknown keys would otherwise be dropped silently by `serde(default)`. Anything that
+        // doesn't survive a round trip wasn't used.
+        let unknown = unknown_keys(&table, &toml::Table::try_from(&config)?, "");
+        if strict && !unknown.is_empty() {
+            return Err(format!("unknown config keys: {}", unknown.join(", ")).into());
+        }
+        for key in unknown {
+            warn!("ignoring unknown config key '{key}'");
+        }
+        Ok((config, migrated))
+    }
+}
+
+/// Dotted paths of the keys in `table` missing from `known`, looking into nested tables
+fn unknown_keys(table: &toml::Table, known: &toml::Table, prefix: &str) -> Vec<String> {
+    table
+        .iter()
+        .flat_map(|(key, value)| match (value, known.get(key)) {
+            (_, None) => vec![format!("{prefix}{key}")],
+            (toml::Value::Table(table), Some(toml::Value::Table(known))) => {
+                unknown_keys(table, known, &format!("{prefix}{key}."))
+            },
+            _ => Vec::new(),
+        })
+        .collect()
+}
+
+/// Remove null values from json objects, recursively
+fn strip_nulls(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::Object(map) => {
+            map.retain(|_, v| !v.is_null());
+            map.values_mut().for_each(strip_nulls);
+        },
+        serde_json::Value::Array(values) => values.iter_mut().for_each(strip_nulls),
+        _ => {},
+    }
+}
+
+/// Upgrade a raw config table to [`CONFIG_VERSION`], returning whether anything changed
+fn migrate(table: &mut toml::Table) -> bool {
+    let version = table
+        .get("version")
+        .and_then(|v| v.as_integer())
+        .unwrap_or(0);
+    if version > CONFIG_VERSION as i64 {
+        warn!("config version {version} is newer than this release supports ({CONFIG_VERSION})");
+        return false;
+    }
+    if version == CONFIG_VERSION as i64 {
+        return false;
+    }
+
+    // v0 -> v1: reactive mode moved from a bool into the "reactive" initial screen
+    if let Some(general) = table.get_mut("general").and_then(|v| v.as_table_mut()) {
+        if let Some(toml::Value::Boolean(reactive)) = general.remove("reactive_mode") {
+            if reactive {
+                general.insert("initial_screen".into(), "reactive".into());
+            }
+        }
+    }
+
+    table.insert("version".into(), (CONFIG_VERSION as i64).into());
+    true
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(default)]
 pub struct GeneralConfig {
     /// Use fahrenheit instead of celsius
     pub fahrenheit: bool,
     /// Use 12-hour time format
     pub use_12hr_time: bool,
-    /// Initial screen position on connect (use "reactive" for reactive mode on Linux)
+    /// Initial screen position on connect (use "reactive" for reactive mode).
+    /// Falls back to the board's logo screen if the board has no such position.
     pub initial_screen: String,
+    /// Input device for reactive mode on Linux, as a path or name substring.
+    /// Detected from the board's usb ids when unset.
+    pub reactive_device: Option<String>,
 }
 
 impl Default for GeneralConfig {
@@ -97,11 +287,24 @@ impl Default for GeneralConfig {
             fahrenheit: false,
             use_12hr_time: false,
             initial_screen: "meletrix".into(),
+            reactive_device: None,
         }
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Per board overrides of [`GeneralConfig`], unset keys fall back to the general value
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BoardConfig {
+    /// Initial screen position when this board connects
+    pub initial_screen: Option<String>,
+    /// Input device for reactive mode with this board
+    pub reactive_device: Option<String>,
+    /// Last screen set from the cli, kept up to date by `zoom-sync set screen`
+    pub current_screen: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(default)]
 pub struct RefreshConfig {
     /// System info refresh interval
@@ -110,7 +313,7 @@ pub struct RefreshConfig {
     /// Weather refresh interval
     #[serde(with = "humantime_serde")]
     pub weather: Duration,
-    /// Keyboard reconnection retry interval
+    /// Initial keyboard reconnection retry interval, doubled after each failed attempt up to 60s
     #[serde(with = "humantime_serde")]
     pub retry: Duration,
 }
@@ -125,7 +328,7 @@ impl Default for RefreshConfig {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(default)]
 pub struct WeatherConfig {
     /// Enable weather updates
@@ -134,6 +337,33 @@ pub struct WeatherConfig {
     pub latitude: Option<f64>,
     /// Manual longitude (optional)
     pub longitude: Option<f64>,
+    /// City name to geocode when no coordinates are set (optional).
+    /// The resolved coordinates are saved back to latitude/longitude.
+    pub city: Option<String>,
+    /// ipinfo access token for geolocation, falls back to the IPINFO_TOKEN env var (optional)
+    pub ipinfo_token: Option<String>,
+    /// Geolocation sources tried in order when neither coordinates nor a city are set:
+    /// "ipinfo", "ip-api", or `{ static = [latitude, longitude] }`. Leave a source out to
+    /// disable it.
+    pub geolocation: Vec<GeoSource>,
+    /// Weather source: "open-meteo", or `{ command = ["program", "args"...] }` to run a program
+    /// that prints a json report
+    pub provider: Provider,
+    /// Maximum age of the last successful report to show when fetching fails
+    #[serde(with = "humantime_serde")]
+    pub max_cache_age: Duration,
+    /// Time limit for each geolocation and weather request
+    #[serde(with = "humantime_serde")]
+    pub request_timeout: Duration,
+    /// Fixed WMO code to show instead of fetching weather, set together with the other
+    /// manual_* fields (optional)
+    pub manual_wmo: Option<u8>,
+    /// Fixed current temperature, may be negative (optional)
+    pub manual_current: Option<i16>,
+    /// Fixed minimum temperature (optional)
+    pub manual_min: Option<i16>,
+    /// Fixed maximum temperature (optional)
+    pub manual_max: Option<i16>,
 }
 
 impl Default for WeatherConfig {
@@ -142,11 +372,21 @@ impl Default for WeatherConfig {
             enabled: true,
             latitude: None,
             longitude: None,
+            city: None,
+            ipinfo_token: None,
+            geolocation: GeoSource::DEFAULT.to_vec(),
+            provider: Provider::OpenMeteo,
+            max_cache_age: Duration::from_secs(3 * 60 * 60),
+            request_timeout: crate::weather::DEFAULT_REQUEST_TIMEOUT,
+            manual_wmo: None,
+            manual_current: None,
+            manual_min: None,
+            manual_max: None,
         }
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(default)]
 pub struct SystemInfoConfig {
     /// Enable system info updates
@@ -155,6 +395,8 @@ pub struct SystemInfoConfig {
     pub cpu_source: String,
     /// GPU device index
     pub gpu_device: u32,
+    /// Show the average of this many readings to smooth out jumpy temperatures (1 is raw)
+    pub smoothing: usize,
 }
 
 impl Default for SystemInfoConfig {
@@ -163,17 +405,32 @@ impl Default for SystemInfoConfig {
             enabled: true,
             cpu_source: "Package".into(),
             gpu_device: 0,
+            smoothing: 1,
         }
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(default)]
 pub struct MediaConfig {
     /// Background color for transparent images (hex)
     pub background_color: String,
     /// Use nearest neighbor interpolation
     pub use_nearest_neighbor: bool,
+    /// Cache encoded media on disk to speed up repeated uploads
+    pub use_cache: bool,
+    /// Dither images when converting to 16 bit color, reducing banding on gradients
+    pub dither: bool,
+    /// How media is scaled onto the screen: "fill" (crop), "fit" (letterbox), or "stretch"
+    pub resize_mode: ResizeMode,
+    /// Gamma applied to media to calibrate the panel: below 1 brightens midtones, above 1 darkens
+    pub gamma: f32,
+    /// Red, green and blue multipliers applied to media to calibrate the panel
+    pub color_scale: [f32; 3],
+    /// Times a rejected upload chunk is resent before the upload fails
+    pub upload_retries: u8,
+    /// Threads used to encode animations: 0 for all cores, 1 to encode serially
+    pub encode_threads: usize,
     /// Last uploaded image path
     pub last_image: Option<PathBuf>,
     /// Last uploaded GIF path
@@ -185,8 +442,193 @@ impl Default for MediaConfig {
         Self {
             background_color: "#000000".into(),
             use_nearest_neighbor: false,
+            use_cache: true,
+            dither: false,
+            resize_mode: ResizeMode::default(),
+            gamma: 1.,
+            color_scale: [1.; 3],
+            upload_retries: zoom_sync_core::DEFAULT_UPLOAD_RETRIES,
+            encode_threads: 0,
             last_image: None,
             last_gif: None,
         }
     }
 }
+
+impl MediaConfig {
+    /// Color correction from `gamma` and `color_scale`, `None` if uncalibrated or invalid
+    pub fn color_correction(&self) -> Option<ColorCorrection> {
+        let valid_gamma = self.gamma.is_finite() && self.gamma > 0.;
+        let valid_scale = self.color_scale.iter().all(|s| s.is_finite() && *s >= 0.);
+        if !(valid_gamma && valid_scale) {
+            warn!("ignoring media color correction, gamma must be positive and scales at least 0");
+            return None;
+        }
+        ColorCorrection::new(self.gamma, self.color_scale)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DisplayConfig {
+    /// Switch between the day and night brightness on the hour, for boards with brightness
+    /// control
+    pub auto_dim: bool,
+    /// Brightness in percent outside of the night hours
+    pub day_brightness: u8,
+    /// Brightness in percent during the night hours
+    pub night_brightness: u8,
+    /// Hour of the day (0-23) the night brightness starts
+    pub night_start: u8,
+    /// Hour of the day (0-23) the day brightness resumes
+    pub night_end: u8,
+}
+
+impl DisplayConfig {
+    /// Brightness the schedule asks for at `hour` of the day. Night hours may wrap past midnight.
+    pub fn scheduled_brightness(&self, hour: u32) -> u8 {
+        let (start, end) = (self.night_start as u32, self.night_end as u32);
+        let night = if start <= end {
+            (start..end).contains(&hour)
+        } else {
+            hour >= start || hour < end
+        };
+        if night {
+            self.night_brightness
+        } else {
+            self.day_brightness
+        }
+    }
+}
+
+impl Default for DisplayConfig {
+    fn default() -> Self {
+        Self {
+            auto_dim: false,
+            day_brightness: 100,
+            night_brightness: 30,
+            night_start: 22,
+            night_end: 7,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reactive_mode_is_migrated() {
+        let (config, migrated) =
+            Config::parse("[general]\nreactive_mode = true\nfahrenheit = true\n").unwrap();
+        assert!(migrated);
+        assert_eq!(config.version, CONFIG_VERSION);
+        assert_eq!(config.general.initial_screen, "reactive");
+        assert!(config.general.fahrenheit);
+    }
+
+    #[test]
+    fn current_version_is_not_migrated() {
+        let contents = toml::to_string_pretty(&Config::default()).unwrap();
+        let (config, migrated) = Config::parse(&contents).unwrap();
+        assert!(!migrated);
+        assert_eq!(config.general.initial_screen, "meletrix");
+    }
+
+    #[test]
+    fn geolocation_sources_can_be_reordered() {
+        let (config, _) =
+            Config::parse("[weather]\ngeolocation = [\"ip-api\", { static = [52.52, 13.41] }]\n")
+                .unwrap();
+        assert_eq!(
+            config.weather.geolocation,
+            [GeoSource::IpApi, GeoSource::Static(52.52, 13.41)]
+        );
+        assert_eq!(Config::default().weather.geolocation, GeoSource::DEFAULT);
+    }
+
+    #[test]
+    fn media_color_correction() {
+        assert_eq!(Config::default().media.color_correction(), None);
+        let (config, _) =
+            Config::parse("[media]\ngamma = 1.2\ncolor_scale = [1.0, 0.9, 0.8]\n").unwrap();
+        assert_eq!(
+            config.media.color_correction(),
+            ColorCorrection::new(1.2, [1., 0.9, 0.8])
+        );
+        let (config, _) = Config::parse("[media]\ngamma = 0.0\n").unwrap();
+        assert_eq!(config.media.color_correction(), None);
+    }
+
+    #[test]
+    fn json_export_round_trips() {
+        let mut config = Config::default();
+        config.weather.city = Some("Berlin".into());
+        config.refresh.weather = Duration::from_secs(30 * 60);
+        let json = config.to_json().unwrap();
+        assert!(json.contains("\"latitude\": null"));
+
+        let imported = Config::from_json(&json, true).unwrap();
+        assert_eq!(imported.to_json().unwrap(), json);
+    }
+
+    #[test]
+    fn strict_import_rejects_unknown_keys() {
+        let json = r#"{"general": {"fahrenheit": true, "fahrenhiet": true}, "extra": 1}"#;
+        let err = Config::from_json(json, true).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "unknown config keys: extra, general.fahrenhiet"
+        );
+        let config = Config::from_json(json, false).unwrap();
+        assert!(config.general.fahrenheit);
+        // options that are set aren't mistaken for unknown keys
+        assert!(Config::from_json(r#"{"weather": {"latitude": 52.5}}"#, true).is_ok());
+    }
+
+    #[test]
+    fn board_sections_override_general() {
+        let (mut config, _) = Config::parse(
+            "[general]\ninitial_screen = \"time\"\n[boards.zoom65v3]\ninitial_screen = \"cpu\"\n",
+        )
+        .unwrap();
+        assert_eq!(config.general_for("zoom65v3").initial_screen, "cpu");
+        assert_eq!(config.general_for("other").initial_screen, "time");
+
+        config.set_initial_screen("zoom65v3", "gpu");
+        config.set_initial_screen("other", "weather");
+        assert_eq!(config.general_for("zoom65v3").initial_screen, "gpu");
+        assert_eq!(config.general.initial_screen, "weather");
+
+        // sections survive a save without being flagged as unknown
+        let contents = toml::to_string_pretty(&config).unwrap();
+        assert!(Config::from_table(toml::from_str(&contents).unwrap(), true).is_ok());
+    }
+
+    #[test]
+    fn night_hours_wrap_around_midnight() {
+        let display = DisplayConfig {
+            night_start: 22,
+            night_end: 7,
+            ..Default::default()
+        };
+        assert_eq!(display.scheduled_brightness(21), display.day_brightness);
+        assert_eq!(display.scheduled_brightness(22), display.night_brightness);
+        assert_eq!(display.scheduled_brightness(3), display.night_brightness);
+        assert_eq!(display.scheduled_brightness(7), display.day_brightness);
+
+        let daytime_nap = DisplayConfig {
+            night_start: 13,
+            night_end: 15,
+            ..Default::default()
+        };
+        assert_eq!(
+            daytime_nap.scheduled_brightness(14),
+            daytime_nap.night_brightness
+        );
+        assert_eq!(
+            daytime_nap.scheduled_brightness(23),
+            daytime_nap.day_brightness
+        );
+    }
+}
@@ -1,32 +1,75 @@
 //! Board detection and selection logic.
 
+use std::ffi::{CStr, CString};
 use std::str::FromStr;
 
-use bpaf::Bpaf;
-use hidapi::HidApi;
+use bpaf::Parser;
+use hidapi::{DeviceInfo, HidApi};
 use zoom65v3::{Zoom65v3, INFO as ZOOM65V3_INFO};
-use zoom_sync_core::{Board, BoardError, BoardInfo};
+use zoom_sync_core::{Board, BoardError, BoardInfo, ByteOrder, Capabilities};
+
+/// Everything needed to find, open, and encode media for one supported board
+#[derive(Debug)]
+pub struct BoardRegistration {
+    pub info: &'static BoardInfo,
+    /// Whether a hid interface belongs to this board
+    pub matches: fn(&DeviceInfo) -> bool,
+    /// Find and open the board
+    pub open: fn() -> Result<Box<dyn Board>, BoardError>,
+    /// Open the board at a specific hid path
+    pub open_path: fn(&CStr) -> Result<Box<dyn Board>, BoardError>,
+    /// Screen size, for encoding media without a connected keyboard
+    pub screen_size: Option<(u32, u32)>,
+    /// Gif size limit, for encoding media without a connected keyboard
+    pub gif_max_size: Option<usize>,
+    /// Image pixel byte order, for encoding media without a connected keyboard
+    pub byte_order: ByteOrder,
+}
+
+/// Every supported board, in the order they're listed. Adding a board only takes an entry here.
+pub static BOARDS: &[BoardRegistration] = &[BoardRegistration {
+    info: &ZOOM65V3_INFO,
+    matches: |device| matches(device, &ZOOM65V3_INFO),
+    open: || Ok(Box::new(Zoom65v3::open()?)),
+    open_path: |path| Ok(Box::new(Zoom65v3::open_path(path)?)),
+    screen_size: Some((zoom65v3::SCREEN_WIDTH, zoom65v3::SCREEN_HEIGHT)),
+    gif_max_size: Some(zoom65v3::GIF_MAX_SIZE),
+    byte_order: ByteOrder::BigEndian,
+}];
 
 /// Supported board types
-#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Bpaf)]
-#[bpaf(fallback(BoardKind::Auto), group_help("Board selection:"))]
+#[derive(Clone, Copy, Debug, Default)]
 pub enum BoardKind {
     /// Auto-detect connected board (default)
     #[default]
     Auto,
-    /// Zoom65 V3
-    Zoom65v3,
+    /// A specific registered board
+    Board(&'static BoardRegistration),
 }
 
+impl PartialEq for BoardKind {
+    fn eq(&self, other: &Self) -> bool {
+        self.info().map(|i| i.cli_name) == other.info().map(|i| i.cli_name)
+    }
+}
+
+impl Eq for BoardKind {}
+
 impl FromStr for BoardKind {
     type Err = String;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s.to_lowercase().as_str() {
-            "auto" => Ok(Self::Auto),
-            "zoom65v3" => Ok(Self::Zoom65v3),
-            _ => Err(format!("unknown board: {s}. Available: auto, zoom65v3")),
+        if s.eq_ignore_ascii_case("auto") {
+            return Ok(Self::Auto);
         }
+        BOARDS
+            .iter()
+            .find(|board| board.info.cli_name.eq_ignore_ascii_case(s))
+            .map(Self::Board)
+            .ok_or_else(|| {
+                let available = Self::supported_boards().join(", ");
+                format!("unknown board: {s}. Available: {available}")
+            })
     }
 }
 
@@ -34,13 +77,48 @@ impl std::fmt::Display for BoardKind {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::Auto => write!(f, "auto"),
-            Self::Zoom65v3 => write!(f, "zoom65v3"),
+            Self::Board(board) => write!(f, "{}", board.info.cli_name),
         }
     }
 }
 
+/// Board selection, by name with `--board`. The older `--auto` and per board flags like
+/// `--zoom65v3` still work.
+pub fn board_kind() -> impl Parser<BoardKind> {
+    let named = bpaf::long("board")
+        .help(
+            "Board to use, or auto to detect the connected board (default). \
+See `zoom-sync boards` for the names.",
+        )
+        .argument::<BoardKind>("NAME");
+    let flags = std::iter::once(bpaf::long("auto").req_flag(BoardKind::Auto).hide().boxed()).chain(
+        BOARDS.iter().map(|board| {
+            bpaf::long(board.info.cli_name)
+                .req_flag(BoardKind::Board(board))
+                .hide()
+                .boxed()
+        }),
+    );
+    let flags = bpaf::choice(flags);
+    bpaf::construct!([named, flags])
+        .fallback(BoardKind::Auto)
+        .group_help("Board selection:")
+}
+
+/// Open a specific hid device path instead of searching for the board
+pub fn device_path() -> impl Parser<Option<CString>> {
+    bpaf::long("device-path")
+        .help(
+            "Open the board at a specific hid device path, bypassing usage matching. \
+Useful with multiple identical keyboards.",
+        )
+        .argument::<String>("PATH")
+        .parse(CString::new)
+        .optional()
+}
+
 /// Check if a HID device matches the board info
-fn matches(device: &hidapi::DeviceInfo, info: &BoardInfo) -> bool {
+fn matches(device: &DeviceInfo, info: &BoardInfo) -> bool {
     device.vendor_id() == info.vendor_id
         && device.product_id() == info.product_id
         && info.usage_page.is_none_or(|up| device.usage_page() == up)
@@ -48,27 +126,218 @@ fn matches(device: &hidapi::DeviceInfo, info: &BoardInfo) -> bool {
 }
 
 impl BoardKind {
-    /// Open the specified board, or auto-detect if Auto
-    pub fn as_board(&self) -> Result<Box<dyn Board>, BoardError> {
+    /// Open the specified board, or auto-detect if Auto.
+    /// An explicit device path is opened directly instead of searching the device list.
+    pub fn as_board(&self, device_path: Option<&CStr>) -> Result<Box<dyn Board>, BoardError> {
+        if let Some(path) = device_path {
+            return self.open_path(path);
+        }
+        match self {
+            // Only open a board when the choice is unambiguous, hid enumeration order isn't stable
+            BoardKind::Auto => match Self::detect_all()?.as_slice() {
+                [] => Err(BoardError::DeviceNotFound),
+                [kind] => kind.as_board(None),
+                found => {
+                    let names: Vec<_> = found.iter().map(ToString::to_string).collect();
+                    Err(BoardError::MultipleBoards(names.join(", ")))
+                },
+            },
+            BoardKind::Board(board) => (board.open)(),
+        }
+    }
+
+    /// Detect every connected supported board, with one entry per physical keyboard
+    pub fn detect_all() -> Result<Vec<BoardKind>, BoardError> {
+        // Single HID iteration, checking each device against every board
+        let api = HidApi::new()?;
+        let found = api
+            .device_list()
+            .filter_map(|device| BOARDS.iter().find(|board| (board.matches)(device)))
+            .map(BoardKind::Board)
+            .collect();
+        Ok(found)
+    }
+
+    /// Open the board at a hid path. Auto identifies the board by vendor/product id alone.
+    fn open_path(&self, path: &CStr) -> Result<Box<dyn Board>, BoardError> {
         match self {
             BoardKind::Auto => {
-                // Single HID iteration, check each board's INFO
                 let api = HidApi::new()?;
-                for device in api.device_list() {
-                    if matches(device, &ZOOM65V3_INFO) {
-                        return Ok(Box::new(Zoom65v3::open()?));
-                    }
-                    // Add more boards here as they're implemented
-                }
-                Err(BoardError::DeviceNotFound)
+                let device = api
+                    .device_list()
+                    .find(|d| d.path() == path)
+                    .ok_or(BoardError::DeviceNotFound)?;
+                let board = BOARDS
+                    .iter()
+                    .find(|board| {
+                        device.vendor_id() == board.info.vendor_id
+                            && device.product_id() == board.info.product_id
+                    })
+                    .ok_or(BoardError::DeviceNotFound)?;
+                (board.open_path)(path)
             },
-            BoardKind::Zoom65v3 => Ok(Box::new(Zoom65v3::open()?)),
+            BoardKind::Board(board) => (board.open_path)(path),
+        }
+    }
+
+    /// Registration of the board type, or the first board with a screen for Auto
+    fn media_board(&self) -> Option<&'static BoardRegistration> {
+        match self {
+            BoardKind::Auto => BOARDS.iter().find(|board| board.screen_size.is_some()),
+            BoardKind::Board(board) => Some(board),
+        }
+    }
+
+    /// Screen size of the board type, for encoding media without a connected keyboard.
+    /// Auto uses the first supported board with a screen.
+    pub fn screen_size(&self) -> Option<(u32, u32)> {
+        self.media_board()?.screen_size
+    }
+
+    /// Gif size limit of the board type, for encoding media without a connected keyboard
+    pub fn gif_max_size(&self) -> Option<usize> {
+        self.media_board()?.gif_max_size
+    }
+
+    /// Image pixel byte order of the board type, for encoding media without a connected keyboard
+    pub fn byte_order(&self) -> ByteOrder {
+        self.media_board()
+            .map(|board| board.byte_order)
+            .unwrap_or_default()
+    }
+
+    /// Static info of the board type, `None` for auto detection
+    pub fn info(&self) -> Option<&'static BoardInfo> {
+        match self {
+            BoardKind::Auto => None,
+            BoardKind::Board(board) => Some(board.info),
+        }
+    }
+
+    /// Capabilities of the board type. Auto may open any supported board, so it gets the union
+    /// of all of them.
+    pub fn capabilities(&self) -> Capabilities {
+        match self.info() {
+            Some(info) => info.capabilities,
+            None => BOARDS.iter().fold(Capabilities::default(), |caps, board| {
+                caps.union(board.info.capabilities)
+            }),
         }
     }
 
     /// List all supported board CLI names
-    #[allow(dead_code)]
-    pub fn supported_boards() -> &'static [&'static str] {
-        &["auto", "zoom65v3"]
+    pub fn supported_boards() -> Vec<&'static str> {
+        std::iter::once("auto")
+            .chain(BOARDS.iter().map(|board| board.info.cli_name))
+            .collect()
+    }
+}
+
+/// Where `zoom-sync udev --install` writes its rules
+pub const UDEV_RULES_PATH: &str = "/etc/udev/rules.d/99-zoom-sync.rules";
+
+/// udev rules file giving logged in users access to each of the boards
+pub fn udev_rules(boards: &[&BoardInfo]) -> String {
+    let mut rules = String::from(
+        "# Generated by `zoom-sync udev`, gives logged in users access to keyboards\n",
+    );
+    for info in boards {
+        rules += &format!("\n# {}\n{}\n", info.name, info.udev_rule());
+    }
+    rules
+}
+
+/// Render every supported board and its capabilities as a table, with how many of each are
+/// connected
+pub fn capability_table(connected: &[BoardKind]) -> String {
+    let mut header = vec!["BOARD", "NAME", "CONNECTED"];
+    header.extend(Capabilities::default().list().map(|(name, _)| name));
+    let mut rows = vec![header
+        .into_iter()
+        .map(str::to_uppercase)
+        .collect::<Vec<_>>()];
+    for info in BOARDS.iter().map(|board| board.info) {
+        let count = connected
+            .iter()
+            .filter(|kind| kind.info().is_some_and(|i| i.cli_name == info.cli_name))
+            .count();
+        let mut row = vec![
+            info.cli_name.to_string(),
+            info.name.to_string(),
+            count.to_string(),
+        ];
+        row.extend(
+            info.capabilities
+                .list()
+                .map(|(_, supported)| if supported { "yes" } else { "-" }.to_string()),
+        );
+        rows.push(row);
+    }
+
+    let widths: Vec<_> = (0..rows[0].len())
+        .map(|col| rows.iter().map(|row| row[col].len()).max().unwrap_or(0))
+        .collect();
+    rows.iter()
+        .map(|row| {
+            let cells: Vec<_> = row
+                .iter()
+                .zip(&widths)
+                .map(|(cell, width)| format!("{cell:width$}"))
+                .collect();
+            cells.join("  ").trim_end().to_string()
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn board_names_parse() {
+        assert_eq!("auto".parse(), Ok(BoardKind::Auto));
+        let zoom: BoardKind = "Zoom65v3".parse().unwrap();
+        assert_eq!(zoom.info().map(|i| i.name), Some("Zoom65 V3"));
+        assert_eq!(zoom.to_string(), "zoom65v3");
+        let err = "nope".parse::<BoardKind>().unwrap_err();
+        assert_eq!(err, "unknown board: nope. Available: auto, zoom65v3");
+    }
+
+    #[test]
+    fn udev_rules_cover_every_board() {
+        let boards: Vec<_> = BOARDS.iter().map(|board| board.info).collect();
+        let rules = udev_rules(&boards);
+        assert_eq!(
+            rules.lines().filter(|l| l.starts_with("SUBSYSTEM")).count(),
+            BOARDS.len()
+        );
+        assert!(rules.contains(
+            "# Zoom65 V3\nSUBSYSTEM==\"hidraw\", ATTRS{idVendor}==\"36b5\", \
+             ATTRS{idProduct}==\"287f\", MODE=\"0660\", TAG+=\"uaccess\"\n"
+        ));
+    }
+
+    #[test]
+    fn capability_table_lists_every_board() {
+        let table = capability_table(&["zoom65v3".parse().unwrap()]);
+        let rows: Vec<Vec<_>> = table
+            .lines()
+            .map(|line| {
+                line.split("  ")
+                    .map(str::trim)
+                    .filter(|c| !c.is_empty())
+                    .collect()
+            })
+            .collect();
+        assert_eq!(rows.len(), 1 + BOARDS.len());
+        assert_eq!(rows[0][..4], ["BOARD", "NAME", "CONNECTED", "TIME"]);
+        assert_eq!(rows[1][..4], ["zoom65v3", "Zoom65 V3", "1", "yes"]);
+        assert_eq!(
+            rows[1].last(),
+            Some(&"-"),
+            "zoom65v3 has no brightness control"
+        );
+        assert!(rows.iter().all(|row| row.len() == rows[0].len()));
     }
 }
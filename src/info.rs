@@ -1,14 +1,27 @@
 //! Utilities for getting system info
 
+use std::collections::VecDeque;
 use std::error::Error;
 use std::sync::LazyLock;
 
 use either::Either;
+use log::{info, warn};
 use nvml_wrapper::enum_wrappers::device::TemperatureSensor;
 use nvml_wrapper::{Device, Nvml};
 use sysinfo::{Component, Components};
 use zoom_sync_core::Board;
 
+#[cfg(windows)]
+mod lhm;
+#[cfg(target_os = "macos")]
+mod smc;
+
+/// Platform cpu sensor, for when sysinfo doesn't expose one
+#[cfg(windows)]
+use lhm::LhmSensor as NativeSensor;
+#[cfg(target_os = "macos")]
+use smc::SmcSensor as NativeSensor;
+
 #[derive(Clone, Debug, bpaf::Bpaf)]
 pub enum CpuMode {
     Label(
@@ -55,9 +68,43 @@ impl GpuMode {
     }
 }
 
+/// Moving average over the last few readings, to keep the display from flickering
+struct Smoothing {
+    samples: usize,
+    window: VecDeque<f32>,
+}
+
+impl Smoothing {
+    /// Average over `samples` readings. A single sample passes readings through unchanged.
+    fn new(samples: usize) -> Self {
+        let samples = samples.max(1);
+        Self {
+            samples,
+            window: VecDeque::with_capacity(samples),
+        }
+    }
+
+    /// Add a reading and return the current average
+    fn push(&mut self, value: f32) -> f32 {
+        if self.window.len() == self.samples {
+            self.window.pop_front();
+        }
+        self.window.push_back(value);
+        self.window.iter().sum::<f32>() / self.window.len() as f32
+    }
+}
+
+fn to_farenheit(celsius: f32) -> f32 {
+    celsius * 9. / 5. + 32.
+}
+
 /// Helper struct to track gpu temperature
 pub struct GpuTemp {
     maybe_device: Option<Device<'static>>,
+    /// SMC sensor on macs, which have no nvidia gpu
+    #[cfg(target_os = "macos")]
+    maybe_smc: Option<smc::SmcSensor>,
+    smoothing: Smoothing,
 }
 
 impl GpuTemp {
@@ -65,8 +112,8 @@ impl GpuTemp {
     pub fn new(index: u32) -> Self {
         static NVML: LazyLock<Option<Nvml>> = LazyLock::new(|| {
             let nvml = Nvml::init().ok();
-            if nvml.is_none() {
-                eprintln!("warning: nvml not found (nvidia gpu temp unavailable)");
+            if nvml.is_none() && !cfg!(target_os = "macos") {
+                warn!("nvml not found (nvidia gpu temp unavailable)");
             }
             nvml
         });
@@ -74,31 +121,58 @@ impl GpuTemp {
         let maybe_device = NVML.as_ref().and_then(|nvml| {
             let device = nvml.device_by_index(index).ok();
             if device.is_none() {
-                eprintln!("warning: gpu device {index} not found")
+                warn!("gpu device {index} not found")
             }
             device
         });
 
-        Self { maybe_device }
+        #[cfg(target_os = "macos")]
+        let maybe_smc = maybe_device.is_none().then(smc::SmcSensor::gpu).flatten();
+
+        Self {
+            maybe_device,
+            #[cfg(target_os = "macos")]
+            maybe_smc,
+            smoothing: Smoothing::new(1),
+        }
+    }
+
+    /// Report the average of the last `samples` readings instead of the latest one
+    pub fn with_smoothing(mut self, samples: usize) -> Self {
+        self.smoothing = Smoothing::new(samples);
+        self
     }
 
-    // Refresh and poll the current temperature
-    pub fn get_temp(&self, farenheit: bool) -> Option<u8> {
-        self.maybe_device
+    /// Read the sensor in celsius
+    fn read(&self) -> Option<f32> {
+        #[cfg(target_os = "macos")]
+        if let Some(smc) = &self.maybe_smc {
+            return smc.temperature();
+        }
+        let celsius = self
+            .maybe_device
             .as_ref()
-            .and_then(|d| d.temperature(TemperatureSensor::Gpu).ok())
-            .map(|v| {
-                if farenheit {
-                    (v as f64 * 9. / 5. + 32.) as u8
-                } else {
-                    v as u8
-                }
-            })
+            .and_then(|d| d.temperature(TemperatureSensor::Gpu).ok())?;
+        Some(celsius as f32)
+    }
+
+    // Refresh and poll the current temperature
+    pub fn get_temp(&mut self, farenheit: bool) -> Option<u8> {
+        let celsius = self.read()?;
+        let temp = self.smoothing.push(celsius);
+        Some(if farenheit { to_farenheit(temp) } else { temp } as u8)
     }
 }
 
+/// Cpu sensor fallbacks, in order of preference, when the searched label isn't found
+const CPU_FALLBACKS: [&str; 3] = ["Tctl", "Package", "CPU"];
+
 pub struct CpuTemp {
     maybe_cpu: Option<Component>,
+    /// LibreHardwareMonitor or SMC sensor, used when sysinfo doesn't expose one
+    #[cfg(any(windows, target_os = "macos"))]
+    maybe_native: Option<NativeSensor>,
+    smoothing: Smoothing,
 }
 
 impl CpuTemp {
@@ -107,7 +181,7 @@ impl CpuTemp {
         let comps: Vec<_> = Components::new_with_refreshed_list().into();
 
         // Try to find the specified sensor, or fall back to common alternatives
-        let fallbacks = ["Tctl", "Package", "CPU"];
+        let fallbacks = CPU_FALLBACKS;
         let mut matched_fallback = None;
 
         let maybe_cpu = comps
@@ -137,43 +211,82 @@ impl CpuTemp {
                 None
             });
 
-        if maybe_cpu.is_none() {
+        #[cfg(any(windows, target_os = "macos"))]
+        let maybe_native = maybe_cpu
+            .is_none()
+            .then(|| NativeSensor::cpu(search_label))
+            .flatten();
+        #[cfg(any(windows, target_os = "macos"))]
+        let found = maybe_cpu.is_some() || maybe_native.is_some();
+        #[cfg(not(any(windows, target_os = "macos")))]
+        let found = maybe_cpu.is_some();
+
+        if !found {
             let comps: Vec<_> = Components::new_with_refreshed_list().into();
-            eprintln!("warning: no cpu temp sensor found");
-            if !comps.is_empty() {
-                eprintln!("  available sensors:");
-                for c in &comps {
-                    eprintln!("    - {}", c.label());
-                }
+            if comps.is_empty() && cfg!(windows) {
+                warn!("no cpu temp sensor found, is LibreHardwareMonitor running?");
+            } else if comps.is_empty() {
+                warn!("no cpu temp sensor found");
+            } else {
+                let labels: Vec<_> = comps.iter().map(|c| c.label()).collect();
+                warn!(
+                    "no cpu temp sensor found, available sensors: {}",
+                    labels.join(", ")
+                );
             }
         }
-        Self { maybe_cpu }
+        Self {
+            maybe_cpu,
+            #[cfg(any(windows, target_os = "macos"))]
+            maybe_native,
+            smoothing: Smoothing::new(1),
+        }
+    }
+
+    /// Report the average of the last `samples` readings instead of the latest one
+    pub fn with_smoothing(mut self, samples: usize) -> Self {
+        self.smoothing = Smoothing::new(samples);
+        self
+    }
+
+    /// Refresh and read the sensor in celsius. The inner value is empty when the sensor exists
+    /// but has no reading.
+    fn read(&mut self) -> Option<Option<f32>> {
+        #[cfg(any(windows, target_os = "macos"))]
+        if let Some(native) = &self.maybe_native {
+            return Some(native.temperature());
+        }
+        let cpu = self.maybe_cpu.as_mut()?;
+        cpu.refresh();
+        Some(cpu.temperature())
     }
 
     // Refresh and poll the current temperature
     pub fn get_temp(&mut self, farenheit: bool) -> Option<u8> {
-        self.maybe_cpu.as_mut().map(|cpu| {
-            cpu.refresh();
-            match cpu.temperature() {
-                Some(mut temp) => {
-                    if farenheit {
-                        temp = temp * 9. / 5. + 32.;
-                    }
-                    temp as u8
-                },
-                None => 0,
-            }
-        })
+        let Some(celsius) = self.read()? else {
+            return Some(0);
+        };
+        let temp = self.smoothing.push(celsius);
+        Some(if farenheit { to_farenheit(temp) } else { temp } as u8)
     }
 }
 
+/// System info last sent to the board
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SystemInfo {
+    pub cpu_temp: u8,
+    pub gpu_temp: u8,
+    /// Download rate in MB/s
+    pub download: f32,
+}
+
 pub fn apply_system(
     board: &mut dyn Board,
     farenheit: bool,
     cpu: &mut Either<CpuTemp, u8>,
-    gpu: &Either<GpuTemp, u8>,
+    gpu: &mut Either<GpuTemp, u8>,
     download: Option<f32>,
-) -> Result<(), Box<dyn Error>> {
+) -> Result<SystemInfo, Box<dyn Error>> {
     let system_info = board
         .as_system_info()
         .ok_or("board does not support system info")?;
@@ -184,28 +297,57 @@ pub fn apply_system(
         .map_right(|v| *v)
         .into_inner();
     if cpu_temp >= 100 {
-        eprintln!("warning: actual cpu temperature at {cpu_temp}, clamping to 99");
+        warn!("actual cpu temperature at {cpu_temp}, clamping to 99");
         cpu_temp = 99;
     }
 
     let mut gpu_temp = gpu
-        .as_ref()
+        .as_mut()
         .map_left(|g| g.get_temp(farenheit).unwrap_or_default())
         .map_right(|v| *v)
         .into_inner();
     if gpu_temp >= 100 {
-        eprintln!("warning: actual gpu temerature at {gpu_temp}. clamping to 99");
+        warn!("actual gpu temerature at {gpu_temp}. clamping to 99");
         gpu_temp = 99;
     }
 
-    let download = download.unwrap_or_default();
+    let mut download = download.unwrap_or_default();
+    if let Some(max) = system_info.max_download() {
+        if download > max {
+            warn!("download rate at {download} MB/s, clamping to {max}");
+            download = max;
+        }
+    }
 
     system_info
         .set_system_info(cpu_temp, gpu_temp, download)
         .map_err(|e| format!("failed to set system info: {e}"))?;
-    println!(
-        "updated system info {{ cpu_temp: {cpu_temp}, gpu_temp: {gpu_temp}, download: {download} }}"
+    info!(
+        "updated system info {{ cpu_temp: {cpu_temp}, gpu_temp: {gpu_temp}, download: {download} MB/s }}"
     );
 
-    Ok(())
+    Ok(SystemInfo {
+        cpu_temp,
+        gpu_temp,
+        download,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn smoothing_averages_recent_readings() {
+        let mut raw = Smoothing::new(1);
+        assert_eq!(raw.push(40.), 40.);
+        assert_eq!(raw.push(50.), 50.);
+
+        let mut smooth = Smoothing::new(3);
+        assert_eq!(smooth.push(40.), 40.);
+        assert_eq!(smooth.push(50.), 45.);
+        assert_eq!(smooth.push(60.), 50.);
+        // the first reading drops out of the window
+        assert_eq!(smooth.push(70.), 60.);
+    }
 }
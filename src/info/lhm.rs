@@ -0,0 +1,99 @@
+//! Cpu temperature on windows from LibreHardwareMonitor (or OpenHardwareMonitor), which
+//! publishes its sensors over WMI while it's running
+
+use std::sync::mpsc;
+use std::thread;
+
+use log::{debug, info};
+use serde::Deserialize;
+use wmi::{COMLibrary, WMIConnection};
+
+use super::CPU_FALLBACKS;
+
+const NAMESPACES: [&str; 2] = ["root\\LibreHardwareMonitor", "root\\OpenHardwareMonitor"];
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Sensor {
+    identifier: String,
+    name: String,
+    value: f32,
+}
+
+/// Temperature sensors of the cpu, ie `/amdcpu/0/temperature/2`
+fn cpu_sensors(wmi: &WMIConnection) -> Vec<Sensor> {
+    wmi.raw_query::<Sensor>(
+        "SELECT Identifier, Name, Value FROM Sensor WHERE SensorType = 'Temperature'",
+    )
+    .unwrap_or_default()
+    .into_iter()
+    .filter(|s| s.identifier.contains("cpu"))
+    .collect()
+}
+
+/// Connect to the first namespace that has cpu sensors
+fn connect() -> Option<WMIConnection> {
+    let com = COMLibrary::new().ok()?;
+    NAMESPACES.iter().find_map(|namespace| {
+        let wmi = WMIConnection::with_namespace_path(namespace, com).ok()?;
+        (!cpu_sensors(&wmi).is_empty()).then_some(wmi)
+    })
+}
+
+/// A cpu sensor, read on a dedicated thread since WMI connections can't leave the thread
+/// they were made on
+pub struct LhmSensor {
+    requests: mpsc::Sender<mpsc::Sender<Option<f32>>>,
+}
+
+impl LhmSensor {
+    /// Find a sensor by label, falling back to common cpu sensor names
+    pub fn cpu(search_label: &str) -> Option<Self> {
+        let search_label = search_label.to_string();
+        let (found_tx, found_rx) = mpsc::channel();
+        let (requests, request_rx) = mpsc::channel::<mpsc::Sender<Option<f32>>>();
+        thread::Builder::new()
+            .name("lhm sensors".into())
+            .spawn(move || {
+                let Some(wmi) = connect() else {
+                    let _ = found_tx.send(None);
+                    return;
+                };
+                let sensors = cpu_sensors(&wmi);
+                let sensor = std::iter::once(search_label.as_str())
+                    .chain(CPU_FALLBACKS)
+                    .find_map(|label| sensors.iter().find(|s| s.name.contains(label)))
+                    .or(sensors.first());
+                let Some(Sensor {
+                    identifier, name, ..
+                }) = sensor
+                else {
+                    let _ = found_tx.send(None);
+                    return;
+                };
+                let _ = found_tx.send(Some(name.clone()));
+                for reply in request_rx {
+                    let value = cpu_sensors(&wmi)
+                        .into_iter()
+                        .find(|s| &s.identifier == identifier)
+                        .map(|s| s.value);
+                    let _ = reply.send(value);
+                }
+            })
+            .ok()?;
+
+        let Ok(Some(name)) = found_rx.recv() else {
+            debug!("no LibreHardwareMonitor cpu sensors found");
+            return None;
+        };
+        info!("reading cpu temperature from LibreHardwareMonitor sensor '{name}'");
+        Some(Self { requests })
+    }
+
+    /// Current reading in celsius
+    pub fn temperature(&self) -> Option<f32> {
+        let (reply, value) = mpsc::channel();
+        self.requests.send(reply).ok()?;
+        value.recv().ok().flatten()
+    }
+}
@@ -0,0 +1,245 @@
+//! Cpu and gpu temperatures on macos from the System Management Controller, through a minimal
+//! IOKit binding. Intel and Apple Silicon macs name their sensors differently, so both sets of
+//! keys are probed.
+
+use std::ffi::{c_char, c_void};
+
+use log::{debug, info};
+
+type KernReturn = i32;
+type MachPort = u32;
+
+extern "C" {
+    static mach_task_self_: MachPort;
+}
+
+#[link(name = "IOKit", kind = "framework")]
+extern "C" {
+    fn IOServiceMatching(name: *const c_char) -> *mut c_void;
+    fn IOServiceGetMatchingService(main_port: MachPort, matching: *mut c_void) -> MachPort;
+    fn IOServiceOpen(
+        service: MachPort,
+        owning_task: MachPort,
+        kind: u32,
+        connect: *mut MachPort,
+    ) -> KernReturn;
+    fn IOServiceClose(connect: MachPort) -> KernReturn;
+    fn IOObjectRelease(object: MachPort) -> KernReturn;
+    fn IOConnectCallStructMethod(
+        connection: MachPort,
+        selector: u32,
+        input: *const c_void,
+        input_size: usize,
+        output: *mut c_void,
+        output_size: *mut usize,
+    ) -> KernReturn;
+}
+
+/// Struct method of the AppleSMC user client that runs a command
+const KERNEL_INDEX_SMC: u32 = 2;
+const CMD_READ_BYTES: u8 = 5;
+const CMD_READ_KEY_INFO: u8 = 9;
+
+/// Intel cpu keys, the first one found is used: proximity, then die temperatures
+const INTEL_CPU: [&str; 4] = ["TC0P", "TC0D", "TC0E", "TC0F"];
+/// Intel gpu keys: proximity, then die temperature
+const INTEL_GPU: [&str; 2] = ["TG0P", "TG0D"];
+/// Apple Silicon cpu core clusters across the M1 to M3 generations, all found ones are averaged
+const APPLE_CPU: [&str; 14] = [
+    "Tp01", "Tp05", "Tp09", "Tp0D", "Tp0H", "Tp0L", "Tp0P", "Tp0T", "Tp0X", "Tp0b", "Te05", "Te0L",
+    "Tf04", "Tf09",
+];
+/// Apple Silicon gpu clusters, all found ones are averaged
+const APPLE_GPU: [&str; 6] = ["Tg05", "Tg0D", "Tg0L", "Tg0T", "Tg0f", "Tg0j"];
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct KeyInfo {
+    size: u32,
+    kind: u32,
+    _attributes: u8,
+}
+
+/// Parameters of an SMC command, laid out like the kernel's `SMCParamStruct`
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct Params {
+    key: u32,
+    /// Firmware version, unused for reading keys
+    _version: [u16; 3],
+    /// Power limits, unused for reading keys
+    _limits: [u32; 4],
+    info: KeyInfo,
+    result: u8,
+    _status: u8,
+    command: u8,
+    _data: u32,
+    bytes: [u8; 32],
+}
+
+/// Big endian four character code, as used for keys and data types
+fn fourcc(code: &str) -> u32 {
+    u32::from_be_bytes(code.as_bytes().try_into().unwrap_or_default())
+}
+
+/// Decode a temperature in celsius from its SMC data type
+fn decode(kind: u32, bytes: &[u8]) -> Option<f32> {
+    let value = if kind == fourcc("sp78") {
+        // signed fixed point, 8 fractional bits
+        i16::from_be_bytes(bytes.get(..2)?.try_into().ok()?) as f32 / 256.
+    } else if kind == fourcc("flt ") {
+        f32::from_le_bytes(bytes.get(..4)?.try_into().ok()?)
+    } else {
+        return None;
+    };
+    // unpopulated sensors read as zero or garbage
+    (value > 0. && value < 150.).then_some(value)
+}
+
+/// Open connection to the AppleSMC service
+struct Connection(MachPort);
+
+impl Connection {
+    fn open() -> Option<Self> {
+        // SAFETY: the matching dictionary is consumed by IOServiceGetMatchingService, and the
+        // service is released once the connection is opened
+        unsafe {
+            let matching = IOServiceMatching(c"AppleSMC".as_ptr());
+            if matching.is_null() {
+                return None;
+            }
+            let service = IOServiceGetMatchingService(0, matching);
+            if service == 0 {
+                return None;
+            }
+            let mut connection = 0;
+            let result = IOServiceOpen(service, mach_task_self_, 0, &mut connection);
+            IOObjectRelease(service);
+            (result == 0).then_some(Self(connection))
+        }
+    }
+
+    fn call(&self, input: &Params) -> Option<Params> {
+        let mut output = Params::default();
+        let mut size = size_of::<Params>();
+        // SAFETY: both buffers are valid for the size of the kernel's struct
+        let result = unsafe {
+            IOConnectCallStructMethod(
+                self.0,
+                KERNEL_INDEX_SMC,
+                (input as *const Params).cast(),
+                size_of::<Params>(),
+                (&mut output as *mut Params).cast(),
+                &mut size,
+            )
+        };
+        (result == 0 && output.result == 0).then_some(output)
+    }
+
+    /// Read a temperature key in celsius
+    fn read(&self, key: &str) -> Option<f32> {
+        let mut input = Params {
+            key: fourcc(key),
+            command: CMD_READ_KEY_INFO,
+            ..Default::default()
+        };
+        let info = self.call(&input)?.info;
+        input.info.size = info.size;
+        input.command = CMD_READ_BYTES;
+        let output = self.call(&input)?;
+        decode(info.kind, output.bytes.get(..info.size as usize)?)
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        // SAFETY: the connection was opened by IOServiceOpen and is closed once
+        unsafe { IOServiceClose(self.0) };
+    }
+}
+
+/// One or more SMC keys, averaged when reading
+pub struct SmcSensor {
+    connection: Connection,
+    keys: Vec<&'static str>,
+}
+
+impl SmcSensor {
+    /// Probe for the cpu sensor. `search_label` can name a specific SMC key, ie `TC0D`.
+    pub fn cpu(search_label: &str) -> Option<Self> {
+        let connection = Connection::open()?;
+        let custom = INTEL_CPU
+            .iter()
+            .chain(&APPLE_CPU)
+            .find(|&&key| key == search_label && connection.read(key).is_some());
+        let keys = match custom {
+            Some(key) => vec![*key],
+            None => probe(&connection, &INTEL_CPU, &APPLE_CPU),
+        };
+        Self::new(connection, keys, "cpu")
+    }
+
+    /// Probe for the gpu sensor
+    pub fn gpu() -> Option<Self> {
+        let connection = Connection::open()?;
+        let keys = probe(&connection, &INTEL_GPU, &APPLE_GPU);
+        Self::new(connection, keys, "gpu")
+    }
+
+    fn new(connection: Connection, keys: Vec<&'static str>, kind: &str) -> Option<Self> {
+        if keys.is_empty() {
+            debug!("no smc {kind} temperature keys found");
+            return None;
+        }
+        info!(
+            "reading {kind} temperature from smc keys {}",
+            keys.join(", ")
+        );
+        Some(Self { connection, keys })
+    }
+
+    /// Current reading in celsius, averaged over all keys that could be read
+    pub fn temperature(&self) -> Option<f32> {
+        let values: Vec<_> = self
+            .keys
+            .iter()
+            .filter_map(|key| self.connection.read(key))
+            .collect();
+        (!values.is_empty()).then(|| values.iter().sum::<f32>() / values.len() as f32)
+    }
+}
+
+/// First intel key that can be read, or every apple silicon key that can
+fn probe(
+    connection: &Connection,
+    intel: &[&'static str],
+    apple: &[&'static str],
+) -> Vec<&'static str> {
+    if let Some(key) = intel.iter().find(|key| connection.read(key).is_some()) {
+        return vec![*key];
+    }
+    apple
+        .iter()
+        .copied()
+        .filter(|key| connection.read(key).is_some())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn params_match_the_kernel_layout() {
+        assert_eq!(size_of::<Params>(), 80);
+    }
+
+    #[test]
+    fn temperatures_are_decoded() {
+        assert_eq!(decode(fourcc("sp78"), &[0x2d, 0x80]), Some(45.5));
+        assert_eq!(decode(fourcc("flt "), &51.25f32.to_le_bytes()), Some(51.25));
+        // unpopulated sensors
+        assert_eq!(decode(fourcc("sp78"), &[0, 0]), None);
+        assert_eq!(decode(fourcc("ui8 "), &[40]), None);
+    }
+}
@@ -0,0 +1,198 @@
+//! Local socket for forwarding `set` commands to a running tray.
+//!
+//! Requests and responses are single lines of json, with media payloads as base64 strings. The
+//! response is sent once the tray has handled the command. The socket is a unix socket next to
+//! the lock file on linux and macos, and a named pipe on windows.
+
+use std::error::Error;
+use std::io;
+
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::sync::{mpsc, oneshot};
+
+use crate::screen::ScreenStep;
+
+/// Commands accepted by the running tray
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum IpcCommand {
+    /// Set the screen position by id
+    SetScreen(String),
+    /// Move the screen relative to the current position
+    StepScreen(ScreenStep),
+    /// Upload pre-encoded image data, reading it back afterwards with `verify`. Rejected chunks
+    /// are resent up to `upload_retries` times, or as often as the tray is configured to.
+    UploadImage {
+        #[serde(with = "base64_bytes")]
+        data: Vec<u8>,
+        verify: bool,
+        upload_retries: Option<u8>,
+    },
+    /// Upload pre-encoded gif data, reading it back afterwards with `verify`. Rejected chunks
+    /// are resent up to `upload_retries` times, or as often as the tray is configured to.
+    UploadGif {
+        #[serde(with = "base64_bytes")]
+        data: Vec<u8>,
+        verify: bool,
+        upload_retries: Option<u8>,
+    },
+    ClearImage,
+    ClearGif,
+    ClearAllMedia,
+}
+
+/// Longest request line accepted, far above any board's media limit once base64 encoded
+const MAX_REQUEST_LEN: u64 = 16 * 1024 * 1024;
+
+/// Media payloads as base64 strings, rather than json arrays of numbers
+mod base64_bytes {
+    use base64::prelude::{Engine, BASE64_STANDARD};
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&BASE64_STANDARD.encode(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let encoded = String::deserialize(deserializer)?;
+        BASE64_STANDARD
+            .decode(encoded)
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// Response to a command, with an error message on failure
+pub type Response = Result<(), String>;
+
+/// A received command and the channel to send its response on
+pub type IpcRequest = (IpcCommand, oneshot::Sender<Response>);
+
+#[cfg(unix)]
+fn socket_path() -> io::Result<std::path::PathBuf> {
+    directories::ProjectDirs::from("", "", "zoom-sync")
+        .map(|dirs| dirs.config_dir().join("zoom-sync.sock"))
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "could not determine socket path"))
+}
+
+#[cfg(windows)]
+const PIPE_NAME: &str = r"\\.\pipe\zoom-sync";
+
+/// Accept connections forever, sending each received command to `tx`.
+/// Must only be called while holding the process lock.
+#[cfg(unix)]
+pub async fn serve(tx: mpsc::UnboundedSender<IpcRequest>) -> io::Result<()> {
+    let path = socket_path()?;
+    // We hold the lock, so any existing socket was left behind by a previous instance
+    let _ = std::fs::remove_file(&path);
+    let listener = tokio::net::UnixListener::bind(&path)?;
+    loop {
+        let (stream, _) = listener.accept().await?;
+        tokio::spawn(handle_client(stream, tx.clone()));
+    }
+}
+
+/// Accept connections forever, sending each received command to `tx`.
+/// Must only be called while holding the process lock.
+#[cfg(windows)]
+pub async fn serve(tx: mpsc::UnboundedSender<IpcRequest>) -> io::Result<()> {
+    use tokio::net::windows::named_pipe::ServerOptions;
+
+    let mut server = ServerOptions::new()
+        .first_pipe_instance(true)
+        .create(PIPE_NAME)?;
+    loop {
+        server.connect().await?;
+        // Create the next instance before handing off the connected one, so clients never
+        // find the pipe missing
+        let client = std::mem::replace(&mut server, ServerOptions::new().create(PIPE_NAME)?);
+        tokio::spawn(handle_client(client, tx.clone()));
+    }
+}
+
+/// Read a single command from a client and reply with the outcome
+async fn handle_client<S: AsyncRead + AsyncWrite>(
+    stream: S,
+    tx: mpsc::UnboundedSender<IpcRequest>,
+) {
+    let (read, mut write) = tokio::io::split(stream);
+    let mut line = String::new();
+    let mut reader = BufReader::new(read.take(MAX_REQUEST_LEN));
+    if reader.read_line(&mut line).await.is_err() {
+        return;
+    }
+
+    // A line cut off by the limit fails to parse anyway, but deserves a clearer error
+    let request = if line.len() as u64 >= MAX_REQUEST_LEN {
+        Err("request too large".into())
+    } else {
+        serde_json::from_str::<IpcCommand>(&line).map_err(|e| format!("invalid request: {e}"))
+    };
+    let response = match request {
+        Ok(cmd) => {
+            let (reply_tx, reply_rx) = oneshot::channel();
+            if tx.send((cmd, reply_tx)).is_err() {
+                Err("tray is shutting down".into())
+            } else {
+                reply_rx
+                    .await
+                    .unwrap_or_else(|_| Err("tray dropped the command".into()))
+            }
+        },
+        Err(e) => Err(e),
+    };
+
+    let mut buf = serde_json::to_vec(&response).expect("response is always serializable");
+    buf.push(b'\n');
+    let _ = write.write_all(&buf).await;
+}
+
+/// Send a command to the running tray and wait for it to be handled
+pub async fn send(cmd: &IpcCommand) -> Result<(), Box<dyn Error>> {
+    #[cfg(unix)]
+    let stream = tokio::net::UnixStream::connect(socket_path()?).await;
+    #[cfg(windows)]
+    let stream = tokio::net::windows::named_pipe::ClientOptions::new().open(PIPE_NAME);
+    let stream = stream.map_err(|e| format!("failed to connect to the running tray: {e}"))?;
+
+    let (read, mut write) = tokio::io::split(stream);
+    let mut buf = serde_json::to_vec(cmd)?;
+    buf.push(b'\n');
+    write.write_all(&buf).await?;
+
+    let mut line = String::new();
+    BufReader::new(read).read_line(&mut line).await?;
+    let response: Response = serde_json::from_str(&line)?;
+    Ok(response?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn media_is_sent_as_base64() {
+        let cmd = IpcCommand::UploadGif {
+            data: vec![0, 1, 254, 255],
+            verify: true,
+            upload_retries: Some(5),
+        };
+        let json = serde_json::to_string(&cmd).unwrap();
+        assert_eq!(
+            json,
+            r#"{"upload-gif":{"data":"AAH+/w==","verify":true,"upload_retries":5}}"#
+        );
+        match serde_json::from_str(&json).unwrap() {
+            IpcCommand::UploadGif {
+                data,
+                verify,
+                upload_retries,
+            } => {
+                assert_eq!(data, [0, 1, 254, 255]);
+                assert!(verify);
+                assert_eq!(upload_retries, Some(5));
+            },
+            cmd => panic!("unexpected command {cmd:?}"),
+        }
+    }
+}
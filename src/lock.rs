@@ -1,15 +1,23 @@
 //! Process lock to prevent multiple daemon instances
 
 use std::fs::{File, OpenOptions};
-use std::io;
-use std::path::PathBuf;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 use directories::ProjectDirs;
+use log::warn;
+use sysinfo::{Pid, ProcessRefreshKind, ProcessesToUpdate, System};
+
+/// How long to wait before retrying a lock whose recorded owner has exited
+const STALE_RETRY_DELAY: Duration = Duration::from_millis(500);
 
 /// Guard that holds the lock file open. Lock is released when dropped.
+///
+/// The file itself is left in place, since deleting it could let a second instance lock a fresh
+/// file while a third still holds the old, unlinked one.
 pub struct Lock {
     _file: File,
-    path: PathBuf,
 }
 
 impl Lock {
@@ -28,26 +36,68 @@ impl Lock {
             std::fs::create_dir_all(parent)?;
         }
 
-        let file = OpenOptions::new()
-            .write(true)
-            .create(true)
-            .truncate(true)
-            .open(&path)?;
+        // Don't truncate before locking, the running instance's pid is needed below
+        let mut file = Self::open(&path)?;
 
         // Try to acquire exclusive lock (non-blocking)
         if file.try_lock().is_err() {
-            return Err(io::Error::new(
-                io::ErrorKind::AlreadyExists,
-                "another instance of zoom-sync is already running",
-            ));
+            // The OS releases a lock once its owner exits, so a held lock means a live owner.
+            // That owner may not have written its pid yet, so a dead pid is only a reason to try
+            // again on the same file, in case the filesystem is slow to release a crashed
+            // owner's lock. The file is never replaced.
+            if !Self::is_stale(&path) {
+                return Err(already_running());
+            }
+            std::thread::sleep(STALE_RETRY_DELAY);
+            if file.try_lock().is_err() {
+                return Err(already_running());
+            }
+            warn!(
+                "took over the lock file {} from an exited instance",
+                path.display()
+            );
         }
 
-        // Write PID to lock file for debugging
-        use std::io::Write;
-        let mut file = file;
+        // Write PID to lock file, used to detect stale locks
+        file.set_len(0)?;
         writeln!(file, "{}", std::process::id())?;
 
-        Ok(Self { _file: file, path })
+        Ok(Self { _file: file })
+    }
+
+    /// Check if another instance currently holds the lock, without disturbing it
+    pub fn is_held() -> bool {
+        Self::path()
+            .is_some_and(|path| File::open(&path).is_ok_and(|file| file.try_lock().is_err()))
+    }
+
+    /// Open the lock file, creating it if needed
+    fn open(path: &Path) -> io::Result<File> {
+        OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)
+    }
+
+    /// Whether the lock file names a process that is no longer running. Unreadable files are
+    /// assumed to belong to a live instance.
+    fn is_stale(path: &Path) -> bool {
+        let Some(pid) = std::fs::read_to_string(path)
+            .ok()
+            .and_then(|contents| contents.trim().parse::<u32>().ok())
+        else {
+            return false;
+        };
+        let pid = Pid::from_u32(pid);
+        let mut system = System::new();
+        system.refresh_processes_specifics(
+            ProcessesToUpdate::Some(&[pid]),
+            true,
+            ProcessRefreshKind::nothing(),
+        );
+        system.process(pid).is_none()
     }
 
     /// Get the lock file path
@@ -56,10 +106,9 @@ impl Lock {
     }
 }
 
-impl Drop for Lock {
-    fn drop(&mut self) {
-        // Lock is automatically released when file is closed
-        // Optionally remove the lock file
-        let _ = std::fs::remove_file(&self.path);
-    }
+fn already_running() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::AlreadyExists,
+        "another instance of zoom-sync is already running",
+    )
 }
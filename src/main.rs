@@ -1,28 +1,39 @@
 use std::error::Error;
+use std::ffi::{CStr, CString};
 use std::fmt::{Debug, Display};
-use std::io::{stdout, Seek, Write};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::time::Duration;
 
 use bpaf::{Bpaf, Parser};
-use image::codecs::gif::GifDecoder;
-use image::codecs::png::PngDecoder;
-use image::codecs::webp::WebPDecoder;
-use image::AnimationDecoder;
-use zoom_sync_core::Board;
+use log::{debug, error, info, warn};
+use zoom_sync_core::{Board, BoardError, ByteOrder, Capabilities};
 
-use crate::detection::{board_kind, BoardKind};
+use crate::detection::{board_kind, device_path, BoardKind};
 use crate::info::{apply_system, cpu_mode, gpu_mode, CpuMode, GpuMode};
-use crate::media::{encode_gif, encode_image};
-use crate::screen::{apply_screen, screen_args, ScreenArgs};
-use crate::weather::{apply_weather, weather_args, WeatherArgs};
+use crate::ipc::IpcCommand;
+use crate::media::{
+    decode_animation, encode_gif, encode_image, generate_image, override_encode_threads,
+    parse_hex_color, render_text, resize_mode, set_encode_threads, ColorCorrection, Crop,
+    GifOptions, ImageOptions, MediaCache, MediaKind, Pattern, ResizeMode, TextOptions,
+    MIN_FRAME_DELAY,
+};
+use crate::progress::ProgressFormat;
+use crate::screen::{apply_screen, screen_args, ScreenArgs, ScreenStep};
+use crate::theme::{apply_theme, theme_args, ThemeArgs};
+use crate::weather::{
+    apply_weather, weather_args, GeoSource, OpenMeteoProvider, WeatherArgs, DEFAULT_REQUEST_TIMEOUT,
+};
 
 mod config;
 mod detection;
 mod info;
+mod ipc;
 mod lock;
 mod media;
+mod progress;
 mod screen;
+mod theme;
 mod tray;
 mod weather;
 
@@ -31,7 +42,7 @@ fn farenheit() -> impl Parser<bool> {
         .long("farenheit")
         .help(
             "Use farenheit for all fetched temperatures. \
-May cause clamping for anything greater than 99F. \
+Temperatures outside of -128F to 127F are clamped on the zoom65v3. \
 No effect on any manually provided data.",
         )
         .switch()
@@ -47,6 +58,9 @@ enum SetCommand {
     Weather {
         #[bpaf(external)]
         farenheit: bool,
+        /// Give up on each network request after this many seconds
+        #[bpaf(long, argument("SECS"), fallback(DEFAULT_REQUEST_TIMEOUT.as_secs()), display_fallback)]
+        timeout: u64,
         #[bpaf(external)]
         weather_args: WeatherArgs,
     },
@@ -59,25 +73,70 @@ enum SetCommand {
         cpu_mode: CpuMode,
         #[bpaf(external)]
         gpu_mode: GpuMode,
-        /// Manually set download speed
+        /// Manually set download speed in MB/s
         #[bpaf(short, long)]
         download: Option<f32>,
     },
     /// Change current screen
     #[bpaf(command, fallback_to_usage)]
-    Screen(#[bpaf(external(screen_args))] ScreenArgs),
+    Screen {
+        #[bpaf(external(screen_args))]
+        args: ScreenArgs,
+        /// Save the new screen in the config as the one to show on connect. The running tray
+        /// always remembers its screen
+        #[bpaf(long)]
+        save: bool,
+    },
+    /// Change the screen theme
+    #[bpaf(command, fallback_to_usage)]
+    Theme(#[bpaf(external(theme_args))] ThemeArgs),
+    /// Set the screen brightness, on boards that support it
+    // hidden until a board implements brightness control
+    #[bpaf(command, hide)]
+    Brightness(
+        /// Brightness in percent, 0-100
+        #[bpaf(
+            positional("PERCENT"),
+            guard(valid_percent, "brightness must be between 0 and 100")
+        )]
+        u8,
+    ),
     /// Upload static image
     #[bpaf(command, fallback_to_usage)]
-    Image(#[bpaf(external(set_media_args))] SetMediaArgs),
+    Image(#[bpaf(external(image_args))] ImageArgs),
     /// Upload animated image (gif/webp/apng)
     #[bpaf(command, fallback_to_usage)]
     Gif(#[bpaf(external(set_media_args))] SetMediaArgs),
     /// Clear all media files
     #[bpaf(command)]
-    Clear,
+    Clear {
+        /// Also reset the theme and screen back to the defaults, where supported
+        #[bpaf(long)]
+        all: bool,
+    },
+}
+
+impl SetCommand {
+    /// Whether a board with these capabilities can run the command
+    fn supported_by(&self, caps: &Capabilities) -> bool {
+        match self {
+            SetCommand::Time => caps.time,
+            SetCommand::Weather { .. } => caps.weather,
+            SetCommand::System { .. } => caps.system_info,
+            SetCommand::Screen { .. } => caps.screen,
+            SetCommand::Theme(_) => caps.theme,
+            SetCommand::Brightness(_) => caps.brightness,
+            SetCommand::Image(_) => caps.image,
+            SetCommand::Gif(_) => caps.gif,
+            // clears whatever the board has
+            SetCommand::Clear { .. } => true,
+        }
+    }
 }
 
 #[derive(Clone, Debug, Bpaf)]
+// parsed once at startup, so the color correction table isn't worth boxing
+#[allow(clippy::large_enum_variant)]
 enum SetMediaArgs {
     Set {
         /// Use nearest neighbor interpolation when resizing, otherwise uses gaussian
@@ -91,6 +150,60 @@ enum SetMediaArgs {
             display_fallback,
         )]
         bg: Color,
+        /// Skip the encoded media cache and always re-encode the file
+        #[bpaf(long("no-cache"))]
+        no_cache: bool,
+        /// Images only: dither the conversion to 16 bit color to reduce banding on gradients
+        #[bpaf(long)]
+        dither: bool,
+        #[bpaf(external, fallback(ResizeMode::Fill))]
+        resize_mode: ResizeMode,
+        #[bpaf(external)]
+        color_correction: Option<ColorCorrection>,
+        /// Crop the source to the W by H rectangle at X,Y (in source pixels) before resizing
+        #[bpaf(long, argument("X,Y,W,H"))]
+        crop: Option<Crop>,
+        /// Write the encoded media to a file instead of uploading it, no keyboard required
+        #[bpaf(long, argument("PATH"))]
+        out: Option<PathBuf>,
+        /// Check that the keyboard stored the media correctly after uploading, if supported
+        #[bpaf(long)]
+        verify: bool,
+        /// Resend a rejected upload chunk up to N times before giving up
+        #[bpaf(
+            long("upload-retries"),
+            argument("N"),
+            fallback(zoom_sync_core::DEFAULT_UPLOAD_RETRIES),
+            display_fallback
+        )]
+        upload_retries: u8,
+        /// Animations only: keep at most N frames, dropping frames evenly
+        #[bpaf(long("max-frames"), argument("N"))]
+        max_frames: Option<usize>,
+        /// Animations only: drop frames until the encoded gif fits the board's size limit
+        #[bpaf(long("fit-device"))]
+        fit_device: bool,
+        /// Animations only: play every frame for a constant 1/N seconds, ignoring source timing
+        #[bpaf(
+            long,
+            argument::<u16>("N"),
+            guard(valid_fps, "fps must be between 1 and 50, gif frames last at least 2/100s"),
+            optional
+        )]
+        fps: Option<u16>,
+        /// Animations only: play N times and stop on the last frame, instead of looping forever
+        #[bpaf(
+            long("loop-count"),
+            long("loop"),
+            argument::<u16>("N"),
+            guard(|n: &u16| *n > 0, "loop count must be at least 1"),
+            optional
+        )]
+        loop_count: Option<u16>,
+        /// Animations only: clear the gif "user input" flag on every frame, for firmware that
+        /// waits for a keypress before advancing frames that have it
+        #[bpaf(long("no-user-input"))]
+        no_user_input: bool,
         /// Path to image to re-encode and upload
         #[bpaf(positional("PATH"), guard(|p| p.exists(), "file not found"))]
         path: PathBuf,
@@ -100,8 +213,121 @@ enum SetMediaArgs {
     Clear,
 }
 
+#[derive(Clone, Debug, Bpaf)]
+#[allow(clippy::large_enum_variant)]
+enum ImageArgs {
+    /// Generate the image at the board's screen size instead of reading a file
+    Generated(#[bpaf(external(generated))] Generated),
+    Media(#[bpaf(external(set_media_args))] SetMediaArgs),
+}
+
+/// Image content synthesized at the screen size
+#[derive(Clone, Debug, Bpaf)]
+enum Generated {
+    Pattern(#[bpaf(external(pattern))] Pattern),
+    Text {
+        /// Render text instead of reading a file, a literal \n starts a new line
+        #[bpaf(
+            long,
+            argument("TEXT"),
+            map(|text: String| text.replace("\\n", "\n")),
+            guard(|text: &String| !text.trim().is_empty(), "text must not be empty")
+        )]
+        text: String,
+        /// Font size in pixels, otherwise the largest size that fits
+        #[bpaf(
+            long("font-size"),
+            argument("PX"),
+            guard(|size: &f32| *size > 0., "font size must be positive"),
+            optional
+        )]
+        font_size: Option<f32>,
+        /// Text color
+        #[bpaf(
+            long("text-color"),
+            argument("HEX"),
+            fallback(Color([255; 3])),
+            display_fallback
+        )]
+        color: Color,
+        /// Background color
+        #[bpaf(
+            short,
+            long,
+            fallback(Color([0; 3])),
+            display_fallback,
+        )]
+        bg: Color,
+    },
+}
+
+impl Generated {
+    /// Encode the content at the screen size
+    fn render(&self, width: u32, height: u32, byte_order: ByteOrder) -> Vec<u8> {
+        match self {
+            Generated::Pattern(pattern) => generate_image(*pattern, width, height, byte_order),
+            Generated::Text {
+                text,
+                font_size,
+                color,
+                bg,
+            } => {
+                let options = TextOptions {
+                    size: *font_size,
+                    color: color.0,
+                    background: bg.0,
+                    byte_order,
+                };
+                render_text(text, width, height, options)
+            },
+        }
+    }
+}
+
+fn pattern() -> impl Parser<Pattern> {
+    let color = bpaf::long("color")
+        .help("Fill the screen with a solid color, ie #ff0000")
+        .argument::<Color>("HEX")
+        .map(|color| Pattern::Color(color.0));
+    let test_pattern = bpaf::long("test-pattern")
+        .help("Show a test pattern: bars, gradient or checkerboard")
+        .argument::<Pattern>("PATTERN");
+    bpaf::construct!([color, test_pattern])
+}
+
+fn color_correction() -> impl Parser<Option<ColorCorrection>> {
+    let gamma = bpaf::long("gamma")
+        .help("Calibrate the panel's gamma: below 1 brightens midtones, above 1 darkens them")
+        .argument::<f32>("GAMMA")
+        .guard(|g| g.is_finite() && *g > 0., "gamma must be positive")
+        .fallback(1.);
+    let scale = bpaf::long("color-scale")
+        .help("Multiply the red, green and blue channels, ie 1,0.9,0.8 to tone down a blue tint")
+        .argument::<ColorScale>("R,G,B")
+        .fallback(ColorScale([1.; 3]));
+    bpaf::construct!(gamma, scale).map(|(gamma, scale)| ColorCorrection::new(gamma, scale.0))
+}
+
+/// Red, green and blue multipliers, parsed from `R,G,B`
+#[derive(Debug, Clone, PartialEq)]
+struct ColorScale([f32; 3]);
+impl FromStr for ColorScale {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts = s
+            .split(',')
+            .map(|part| part.trim().parse::<f32>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| e.to_string())?;
+        match <[f32; 3]>::try_from(parts) {
+            Ok(scale) if scale.iter().all(|s| s.is_finite() && *s >= 0.) => Ok(Self(scale)),
+            _ => Err("expected R,G,B multipliers of at least 0".into()),
+        }
+    }
+}
+
 /// Utility for easily parsing hex colors from bpaf
-#[derive(Debug, Clone, Hash)]
+#[derive(Debug, Clone, Hash, PartialEq, Eq)]
 struct Color(pub [u8; 3]);
 impl Display for Color {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -112,24 +338,7 @@ impl Display for Color {
 impl FromStr for Color {
     type Err = String;
     fn from_str(code: &str) -> Result<Self, Self::Err> {
-        // parse hex string into rgb
-        let mut hex = (*code).trim_start_matches('#').to_string();
-        match hex.len() {
-            3 => {
-                // Extend 3 character hex colors
-                hex = hex.chars().flat_map(|a| [a, a]).collect();
-            },
-            6 => {},
-            l => return Err(format!("Invalid hex length for {code}: {l}")),
-        }
-        if let Ok(channel_bytes) = u32::from_str_radix(&hex, 16) {
-            let r = ((channel_bytes >> 16) & 0xFF) as u8;
-            let g = ((channel_bytes >> 8) & 0xFF) as u8;
-            let b = (channel_bytes & 0xFF) as u8;
-            Ok(Self([r, g, b]))
-        } else {
-            Err(format!("Invalid hex color: {code}"))
-        }
+        parse_hex_color(code).map(Self)
     }
 }
 
@@ -138,17 +347,64 @@ impl FromStr for Color {
 struct Cli {
     #[bpaf(external(board_kind))]
     board: BoardKind,
+    #[bpaf(external(device_path))]
+    device_path: Option<CString>,
+    /// Hid report id to prefix commands with, for firmware variants of supported boards
+    #[bpaf(long("report-id"), argument("N"))]
+    report_id: Option<u8>,
+    /// Show debug logs. RUST_LOG overrides the log level when set
+    #[bpaf(short, long)]
+    verbose: bool,
+    /// Only print errors, no progress or status messages
+    #[bpaf(short, long)]
+    quiet: bool,
+    /// Don't color log output. Also disabled by setting NO_COLOR, or when stderr isn't a terminal
+    #[bpaf(long("no-color"))]
+    no_color: bool,
+    /// Progress output format: human, or json for newline delimited
+    /// `{"stage":"upload","done":1,"total":2}` events on stdout
+    #[bpaf(long, argument("FORMAT"), fallback(ProgressFormat::Human))]
+    progress: ProgressFormat,
+    /// Use this config file instead of the default location
+    #[bpaf(long("config"), argument("PATH"))]
+    config: Option<PathBuf>,
+    /// Threads used to encode animations, overriding the config: 0 for all cores, 1 to encode
+    /// serially
+    #[bpaf(long("encode-threads"), argument("N"))]
+    encode_threads: Option<usize>,
     #[bpaf(external(command))]
     command: Command,
 }
 
 #[derive(Clone, Debug)]
+#[allow(clippy::large_enum_variant)]
 enum Command {
     /// Run with a system tray menu for GUI control (default).
     Tray,
     /// Set specific options on the keyboard.
-    /// Must not be used while zoom-sync is already running.
-    Set { set_command: SetCommand },
+    /// While the tray is running, supported commands are forwarded to it.
+    /// With `repeat`, time, weather and system updates are re-sent on that interval.
+    Set {
+        repeat: Option<Duration>,
+        set_command: SetCommand,
+    },
+    /// Sync time, weather and system info once using the tray config, then exit.
+    Once,
+    /// Write a raw payload to the keyboard and print the response (hidden, for reverse engineering).
+    Raw { payload: Vec<u8> },
+    /// Print the resolved config file path.
+    ConfigPath,
+    /// Print the config as json.
+    ConfigExport,
+    /// Replace the config with a json export, failing on unknown keys with `strict`.
+    ConfigImport { path: PathBuf, strict: bool },
+    /// List every supported board and what it can do.
+    Boards,
+    /// Print udev rules for accessing the boards, or write them with `install`.
+    Udev { install: bool },
+    /// Upload an image or animation with the tray config's media settings, through the tray
+    /// when it's running.
+    Upload { path: PathBuf },
 }
 
 fn command() -> impl Parser<Command> {
@@ -158,14 +414,124 @@ fn command() -> impl Parser<Command> {
         .command("tray")
         .help("Run with a system tray menu for GUI control (default)");
 
-    let set = set_command()
-        .map(|set_command| Command::Set { set_command })
+    let repeat = bpaf::long("repeat")
+        .help("Keep re-sending a time, weather or system update on this interval, ie 30s or 5m")
+        .argument::<String>("DURATION")
+        .parse(|s| humantime::parse_duration(&s))
+        .guard(
+            |d| !d.is_zero(),
+            "repeat interval must be greater than zero",
+        )
+        .optional();
+    let set_command = set_command();
+    let set = bpaf::construct!(repeat, set_command)
+        .map(|(repeat, set_command)| Command::Set {
+            repeat,
+            set_command,
+        })
         .to_options()
         .descr("Set specific options on the keyboard")
         .command("set")
         .help("Set specific options on the keyboard");
 
-    bpaf::construct!([tray, set]).fallback(Command::Tray)
+    let once = bpaf::pure(Command::Once)
+        .to_options()
+        .descr("Sync time, weather and system info once using the tray config, then exit")
+        .command("once")
+        .help("Sync time, weather and system info once, for cron jobs and timers");
+
+    let raw = bpaf::long("hex")
+        .help("Bytes to write, including the report id, ie \"00 58 03 a5\"")
+        .argument::<String>("BYTES")
+        .parse(|hex| parse_hex_bytes(&hex))
+        .map(|payload| Command::Raw { payload })
+        .to_options()
+        .descr("Write a raw payload to the keyboard and print the response")
+        .command("raw")
+        .hide();
+
+    let config_path = bpaf::pure(Command::ConfigPath)
+        .to_options()
+        .descr("Print the resolved config file path")
+        .command("path");
+    let config_export = bpaf::pure(Command::ConfigExport)
+        .to_options()
+        .descr("Print the config as json, for backing it up or keeping it with dotfiles")
+        .command("export");
+    let strict = bpaf::long("strict")
+        .help("Fail on unknown keys instead of ignoring them")
+        .switch();
+    let path = bpaf::positional::<PathBuf>("FILE").help("Json export to read, - for stdin");
+    let config_import = bpaf::construct!(Command::ConfigImport { strict, path })
+        .to_options()
+        .descr("Validate a json export and write it over the config")
+        .command("import");
+    let config = bpaf::construct!([config_path, config_export, config_import])
+        .to_options()
+        .descr("Inspect, export or import the tray config file")
+        .command("config")
+        .help("Inspect, export or import the tray config file");
+
+    // A bare path is what dropping a file onto the binary passes
+    let upload_flag = bpaf::long("upload")
+        .help("Upload an image or animation using the tray's media settings")
+        .argument::<PathBuf>("PATH");
+    let dropped = bpaf::positional::<PathBuf>("PATH").hide();
+    let upload = bpaf::construct!([upload_flag, dropped])
+        .guard(|path| path.exists(), "no such command or file")
+        .map(|path| Command::Upload { path });
+
+    let boards = bpaf::pure(Command::Boards)
+        .to_options()
+        .descr("List every supported board, its capabilities, and how many are connected")
+        .command("boards")
+        .help("List every supported board and what it can do");
+
+    let udev = bpaf::long("install")
+        .help("Write the rules to /etc/udev/rules.d/99-zoom-sync.rules instead of printing them")
+        .switch()
+        .map(|install| Command::Udev { install })
+        .to_options()
+        .descr(
+            "Print udev rules giving logged in users access to the supported boards, or only \
+                the board picked with --board",
+        )
+        .command("udev")
+        .help("Print or install udev rules for keyboard access on linux");
+
+    bpaf::construct!([tray, set, once, config, boards, udev, raw, upload]).fallback(Command::Tray)
+}
+
+/// Open the selected board, applying the report id override if any
+fn open_board(
+    kind: &BoardKind,
+    device_path: Option<&CStr>,
+    report_id: Option<u8>,
+) -> Result<Box<dyn Board>, BoardError> {
+    let mut board = kind.as_board(device_path)?;
+    if let Some(id) = report_id {
+        board.set_report_id(id);
+    }
+    Ok(board)
+}
+
+/// Parse whitespace separated hex bytes, with or without a `0x` prefix
+fn parse_hex_bytes(hex: &str) -> Result<Vec<u8>, String> {
+    hex.split_whitespace()
+        .map(|byte| {
+            u8::from_str_radix(byte.trim_start_matches("0x"), 16)
+                .map_err(|e| format!("invalid hex byte '{byte}': {e}"))
+        })
+        .collect()
+}
+
+/// Format bytes as space separated hex, the same way `raw --hex` accepts them
+fn format_hex_bytes(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect::<Vec<_>>()
+        .join(" ")
 }
 
 pub fn apply_time(board: &mut dyn Board, _12hr: bool) -> Result<(), Box<dyn Error>> {
@@ -174,27 +540,500 @@ pub fn apply_time(board: &mut dyn Board, _12hr: bool) -> Result<(), Box<dyn Erro
         .as_time()
         .ok_or("board does not support time")?
         .set_time(time, _12hr)?;
-    println!("updated time to {time}");
+    info!("updated time to {time}");
+    Ok(())
+}
+
+/// Decode an image file and encode it for the device screen, going through the media cache
+fn encode_image_file(
+    path: &Path,
+    bg: [u8; 3],
+    nearest: bool,
+    no_cache: bool,
+    width: u32,
+    height: u32,
+    options: ImageOptions,
+) -> Result<Vec<u8>, Box<dyn Error>> {
+    let encode = || -> Result<Vec<u8>, Box<dyn Error>> {
+        let image = ::image::open(path)?;
+        Ok(encode_image(image, bg, nearest, width, height, options)?)
+    };
+    match MediaCache::open().filter(|_| !no_cache) {
+        Some(cache) => {
+            let kind = MediaKind::Image(options);
+            cache.get_or_encode(path, kind, bg, nearest, width, height, encode)
+        },
+        None => encode(),
+    }
+}
+
+/// Decode an animation file and encode it as a gif for the device screen, going through the
+/// media cache
+fn encode_gif_file(
+    path: &Path,
+    bg: [u8; 3],
+    nearest: bool,
+    no_cache: bool,
+    width: u32,
+    height: u32,
+    options: GifOptions,
+) -> Result<Vec<u8>, Box<dyn Error>> {
+    let encode = || -> Result<Vec<u8>, Box<dyn Error>> {
+        progress::start("decode", "decoding animation");
+        let frames = decode_animation(path)?;
+        progress::finish("decode");
+
+        // re-encode for the keyboard
+        Ok(encode_gif(frames, bg, nearest, width, height, options)?)
+    };
+    match MediaCache::open().filter(|_| !no_cache) {
+        Some(cache) => {
+            let kind = MediaKind::Gif(options);
+            cache.get_or_encode(path, kind, bg, nearest, width, height, encode)
+        },
+        None => encode(),
+    }
+}
+
+/// Build the `set image` or `set gif` command for a file from the tray config's media settings,
+/// picking by whether the file is animated
+fn upload_command(path: PathBuf) -> Result<SetCommand, Box<dyn Error>> {
+    let media = config::Config::load_or_create()?.media;
+    set_encode_threads(media.encode_threads);
+    let animated = media::is_animated(&path)?;
+    let args = SetMediaArgs::Set {
+        nearest: media.use_nearest_neighbor,
+        bg: Color(parse_hex_color(&media.background_color)?),
+        no_cache: !media.use_cache,
+        dither: media.dither,
+        resize_mode: media.resize_mode,
+        color_correction: media.color_correction(),
+        crop: None,
+        out: None,
+        verify: false,
+        upload_retries: media.upload_retries,
+        max_frames: None,
+        fit_device: true,
+        fps: None,
+        loop_count: None,
+        no_user_input: false,
+        path,
+    };
+    Ok(if animated {
+        SetCommand::Gif(args)
+    } else {
+        SetCommand::Image(ImageArgs::Media(args))
+    })
+}
+
+fn valid_fps(fps: &u16) -> bool {
+    // gif delays are in centiseconds, and anything shorter than the minimum is slowed down
+    (1..=100 / MIN_FRAME_DELAY).contains(fps)
+}
+
+fn valid_percent(percent: &u8) -> bool {
+    *percent <= 100
+}
+
+/// Convert a frame rate into a gif frame delay in centiseconds
+fn fps_to_delay(fps: u16) -> u16 {
+    (100 + fps / 2) / fps
+}
+
+/// Translate a set command into a command for the running tray. Media uploads are encoded
+/// beforehand and handled separately.
+fn ipc_command(set_command: &SetCommand) -> Result<IpcCommand, Box<dyn Error>> {
+    Ok(match set_command {
+        SetCommand::Screen { args, .. } => match args {
+            ScreenArgs::Screen(id) => IpcCommand::SetScreen(id.0.clone()),
+            ScreenArgs::Next => IpcCommand::StepScreen(ScreenStep::Next),
+            ScreenArgs::Prev => IpcCommand::StepScreen(ScreenStep::Prev),
+            ScreenArgs::Home => IpcCommand::StepScreen(ScreenStep::Home),
+            _ => return Err("this command is not supported while the tray is running".into()),
+        },
+        SetCommand::Image(ImageArgs::Media(SetMediaArgs::Clear)) => IpcCommand::ClearImage,
+        SetCommand::Gif(SetMediaArgs::Clear) => IpcCommand::ClearGif,
+        SetCommand::Clear { all: false } => IpcCommand::ClearAllMedia,
+        SetCommand::Time | SetCommand::Weather { .. } | SetCommand::System { .. } => {
+            return Err("the running tray already keeps this in sync".into())
+        },
+        _ => return Err("this command is not supported while the tray is running".into()),
+    })
+}
+
+/// Re-send a time, weather or system update every `period` until interrupted. Failed updates
+/// are logged and retried on the next tick, reconnecting to the keyboard first.
+async fn repeat_command(
+    mut board: Box<dyn Board>,
+    set_command: SetCommand,
+    period: Duration,
+) -> Result<(), Box<dyn Error>> {
+    let mut interval = tokio::time::interval(period);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    let recover = |board: &mut Box<dyn Board>, e: Box<dyn Error>| {
+        error!(
+            "update failed, retrying in {}: {e}",
+            humantime::format_duration(period)
+        );
+        if let Err(e) = board.reconnect() {
+            debug!("reconnect failed: {e}");
+        }
+    };
+
+    match set_command {
+        SetCommand::Time => loop {
+            interval.tick().await;
+            if let Err(e) = apply_time(board.as_mut(), false) {
+                recover(&mut board, e);
+            }
+        },
+        SetCommand::Weather {
+            farenheit,
+            timeout,
+            mut weather_args,
+        } => loop {
+            interval.tick().await;
+            if let Err(e) = apply_weather(
+                board.as_mut(),
+                &mut weather_args,
+                &OpenMeteoProvider,
+                GeoSource::DEFAULT,
+                farenheit,
+                None,
+                Duration::from_secs(timeout),
+            )
+            .await
+            {
+                recover(&mut board, e);
+            }
+        },
+        SetCommand::System {
+            farenheit,
+            cpu_mode,
+            gpu_mode,
+            download,
+        } => {
+            let (mut cpu, mut gpu) = (cpu_mode.either(), gpu_mode.either());
+            loop {
+                interval.tick().await;
+                if let Err(e) =
+                    apply_system(board.as_mut(), farenheit, &mut cpu, &mut gpu, download)
+                {
+                    recover(&mut board, e);
+                }
+            }
+        },
+        _ => unreachable!("only time, weather and system updates can repeat"),
+    }
+}
+
+/// Upload an encoded image with progress, reading it back afterwards with `verify`
+fn upload_image(board: &mut dyn Board, encoded: &[u8], verify: bool) -> Result<(), Box<dyn Error>> {
+    let image = board.as_image().ok_or("board does not support images")?;
+    let len = encoded.len();
+    let total = len.div_ceil(image.chunk_size());
+    debug!(
+        "uploading image in {total} chunks of {} bytes",
+        image.chunk_size()
+    );
+    let message = format!("uploading {len} bytes");
+    image.upload_image(encoded, &mut |i| {
+        progress::update("upload", &message, i, total);
+    })?;
+    progress::finish("upload");
+    if verify {
+        report_verify(image.verify_image(encoded))?;
+    }
+    Ok(())
+}
+
+/// Report the outcome of a post-upload verification
+fn report_verify(result: Option<zoom_sync_core::Result<()>>) -> Result<(), Box<dyn Error>> {
+    match result {
+        Some(result) => {
+            result?;
+            info!("verified uploaded media");
+        },
+        None => warn!("board firmware can't read back uploaded media, skipping verification"),
+    }
+    Ok(())
+}
+
+/// Write encoded media to a file instead of uploading it
+fn write_encoded(
+    out: &Path,
+    encoded: &[u8],
+    width: u32,
+    height: u32,
+) -> Result<(), Box<dyn Error>> {
+    std::fs::write(out, encoded)?;
+    info!(
+        "wrote {} bytes ({width}x{height}) to {}",
+        encoded.len(),
+        out.display()
+    );
     Ok(())
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
     let cli = cli().run();
-    match cli.command {
+    let level = if cli.quiet {
+        "error"
+    } else if cli.verbose {
+        "info,zoom_sync=debug"
+    } else {
+        "info"
+    };
+    let mut logger =
+        env_logger::Builder::from_env(env_logger::Env::default().default_filter_or(level));
+    if cli.no_color {
+        logger.write_style(env_logger::WriteStyle::Never);
+    }
+    logger.init();
+    progress::set_format(if cli.quiet {
+        ProgressFormat::Quiet
+    } else {
+        cli.progress
+    });
+    if let Some(path) = cli.config {
+        config::Config::set_path(path);
+    }
+    if let Some(threads) = cli.encode_threads {
+        override_encode_threads(threads);
+    }
+
+    // Uploading a file is a shorthand for the matching set command
+    let command = match cli.command {
+        Command::Upload { path } => Command::Set {
+            repeat: None,
+            set_command: upload_command(path)?,
+        },
+        command => command,
+    };
+
+    match command {
         Command::Tray => {
             let _lock = lock::Lock::acquire()?;
-            tray::run_tray_app(cli.board)
+            tray::run_tray_app(cli.board, cli.device_path, cli.report_id)
+        },
+        Command::Once => {
+            // The running tray owns the keyboard, and keeps it synced anyway
+            let _lock = lock::Lock::acquire()?;
+            tray::run_once(cli.board, cli.device_path, cli.report_id)
+        },
+        Command::Raw { payload } => {
+            // Don't interleave with the tray's own commands
+            let _lock = lock::Lock::acquire()?;
+            let mut board = open_board(&cli.board, cli.device_path.as_deref(), cli.report_id)?;
+            let response = board.execute_raw(&payload)?;
+            println!("{}", format_hex_bytes(&response));
+            Ok(())
         },
-        Command::Set { set_command } => {
+        Command::Boards => {
+            // Listing boards is still useful when hid enumeration isn't available
+            let connected = BoardKind::detect_all()
+                .inspect_err(|e| warn!("failed to detect connected boards: {e}"))
+                .unwrap_or_default();
+            println!("{}", detection::capability_table(&connected));
+            Ok(())
+        },
+        Command::Udev { install } => {
+            let boards: Vec<_> = match cli.board.info() {
+                Some(info) => vec![info],
+                None => detection::BOARDS.iter().map(|board| board.info).collect(),
+            };
+            let rules = detection::udev_rules(&boards);
+            if install {
+                let path = detection::UDEV_RULES_PATH;
+                std::fs::write(path, rules)
+                    .map_err(|e| format!("failed to write {path}, try again with sudo: {e}"))?;
+                println!(
+                    "wrote {path}, apply it with `sudo udevadm control --reload-rules && sudo \
+                     udevadm trigger`"
+                );
+            } else {
+                print!("{rules}");
+            }
+            Ok(())
+        },
+        Command::ConfigPath => {
+            let path = config::Config::path().ok_or("could not determine config directory")?;
+            println!("{}", path.display());
+            Ok(())
+        },
+        Command::ConfigExport => {
+            println!("{}", config::Config::load_or_create()?.to_json()?);
+            Ok(())
+        },
+        Command::ConfigImport { path, strict } => {
+            let contents = if path.as_os_str() == "-" {
+                std::io::read_to_string(std::io::stdin())?
+            } else {
+                std::fs::read_to_string(&path)
+                    .map_err(|e| format!("failed to read {}: {e}", path.display()))?
+            };
+            config::Config::from_json(&contents, strict)?.save()?;
+            let path = config::Config::path().ok_or("could not determine config directory")?;
+            info!("imported config to {}", path.display());
+            Ok(())
+        },
+        Command::Set {
+            repeat,
+            set_command,
+        } => {
+            // Fail before touching the keyboard or the tray when the board can't do it anyway
+            if !set_command.supported_by(&cli.board.capabilities()) {
+                let board = cli
+                    .board
+                    .info()
+                    .map_or("any supported board", |info| info.name);
+                return Err(format!("this command isn't supported by {board}").into());
+            }
             let rt = tokio::runtime::Runtime::new()?;
             rt.block_on(async {
-                let mut board = cli.board.as_board()?;
+                if let Some(period) = repeat {
+                    if !matches!(
+                        set_command,
+                        SetCommand::Time | SetCommand::Weather { .. } | SetCommand::System { .. }
+                    ) {
+                        return Err("--repeat only works with time, weather and system".into());
+                    }
+                    // Hold the lock like the tray does, the two would fight over the keyboard
+                    let _lock = lock::Lock::acquire()?;
+                    let board = open_board(&cli.board, cli.device_path.as_deref(), cli.report_id)?;
+                    return repeat_command(board, set_command, period).await;
+                }
+
+                // The running tray owns the keyboard, so commands have to go through it
+                let tray_running = lock::Lock::is_held();
+
+                // Writing encoded media to a file or the tray doesn't need a connected keyboard
+                if let SetCommand::Image(ImageArgs::Media(SetMediaArgs::Set {
+                    nearest,
+                    bg,
+                    no_cache,
+                    dither,
+                    resize_mode,
+                    color_correction,
+                    crop,
+                    path,
+                    out,
+                    max_frames,
+                    fit_device,
+                    fps,
+                    loop_count,
+                    no_user_input,
+                    verify,
+                    upload_retries,
+                    ..
+                }))
+                | SetCommand::Gif(SetMediaArgs::Set {
+                    nearest,
+                    bg,
+                    no_cache,
+                    dither,
+                    resize_mode,
+                    color_correction,
+                    crop,
+                    path,
+                    out,
+                    max_frames,
+                    fit_device,
+                    fps,
+                    loop_count,
+                    no_user_input,
+                    verify,
+                    upload_retries,
+                    ..
+                }) = &set_command
+                {
+                    if out.is_some() || tray_running {
+                        let (width, height) = cli
+                            .board
+                            .screen_size()
+                            .ok_or("board does not support images")?;
+                        let encoded = if matches!(set_command, SetCommand::Image(_)) {
+                            let options = ImageOptions {
+                                dither: *dither,
+                                resize: *resize_mode,
+                                color: *color_correction,
+                                crop: *crop,
+                                byte_order: cli.board.byte_order(),
+                            };
+                            encode_image_file(
+                                path, bg.0, *nearest, *no_cache, width, height, options,
+                            )?
+                        } else {
+                            let options = GifOptions {
+                                max_frames: *max_frames,
+                                max_size: fit_device.then(|| cli.board.gif_max_size()).flatten(),
+                                delay: fps.map(fps_to_delay),
+                                resize: *resize_mode,
+                                color: *color_correction,
+                                crop: *crop,
+                                loop_count: *loop_count,
+                                user_input: !no_user_input,
+                            };
+                            encode_gif_file(
+                                path, bg.0, *nearest, *no_cache, width, height, options,
+                            )?
+                        };
+                        return match out {
+                            Some(out) => write_encoded(out, &encoded, width, height),
+                            None if matches!(set_command, SetCommand::Image(_)) => {
+                                ipc::send(&IpcCommand::UploadImage {
+                                    data: encoded,
+                                    verify: *verify,
+                                    upload_retries: Some(*upload_retries),
+                                })
+                                .await
+                            },
+                            None => {
+                                ipc::send(&IpcCommand::UploadGif {
+                                    data: encoded,
+                                    verify: *verify,
+                                    upload_retries: Some(*upload_retries),
+                                })
+                                .await
+                            },
+                        };
+                    }
+                }
+                if tray_running {
+                    if let SetCommand::Image(ImageArgs::Generated(generated)) = &set_command {
+                        let (width, height) = cli
+                            .board
+                            .screen_size()
+                            .ok_or("board does not support images")?;
+                        let encoded = generated.render(width, height, cli.board.byte_order());
+                        return ipc::send(&IpcCommand::UploadImage {
+                            data: encoded,
+                            verify: false,
+                            upload_retries: None,
+                        })
+                        .await;
+                    }
+                    return ipc::send(&ipc_command(&set_command)?).await;
+                }
+
+                let mut board = open_board(&cli.board, cli.device_path.as_deref(), cli.report_id)?;
                 match set_command {
                     SetCommand::Time => apply_time(board.as_mut(), false),
                     SetCommand::Weather {
                         farenheit,
+                        timeout,
                         mut weather_args,
-                    } => apply_weather(board.as_mut(), &mut weather_args, farenheit).await,
+                    } => apply_weather(
+                        board.as_mut(),
+                        &mut weather_args,
+                        &OpenMeteoProvider,
+                        GeoSource::DEFAULT,
+                        farenheit,
+                        None,
+                        Duration::from_secs(timeout),
+                    )
+                    .await
+                    .map(|_| ()),
                     SetCommand::System {
                         farenheit,
                         cpu_mode,
@@ -204,30 +1043,66 @@ fn main() -> Result<(), Box<dyn Error>> {
                         board.as_mut(),
                         farenheit,
                         &mut cpu_mode.either(),
-                        &gpu_mode.either(),
+                        &mut gpu_mode.either(),
                         download,
-                    ),
-                    SetCommand::Screen(args) => apply_screen(&args, board.as_mut()),
-                    SetCommand::Image(args) => match args {
-                        SetMediaArgs::Set { nearest, path, bg } => {
+                    )
+                    .map(|_| ()),
+                    SetCommand::Screen { args, save } => {
+                        apply_screen(&args, board.as_mut(), save).await
+                    },
+                    SetCommand::Theme(args) => apply_theme(&args, board.as_mut()),
+                    SetCommand::Brightness(percent) => {
+                        board
+                            .as_brightness()
+                            .ok_or("board does not support brightness control")?
+                            .set_brightness(percent)?;
+                        info!("set brightness to {percent}%");
+                        Ok(())
+                    },
+                    SetCommand::Image(ImageArgs::Generated(generated)) => {
+                        let (width, height) = board
+                            .as_screen_size()
+                            .ok_or("board does not support images")?;
+                        let byte_order = board
+                            .as_image()
+                            .ok_or("board does not support images")?
+                            .byte_order();
+                        let encoded = generated.render(width, height, byte_order);
+                        upload_image(board.as_mut(), &encoded, false)
+                    },
+                    SetCommand::Image(ImageArgs::Media(args)) => match args {
+                        SetMediaArgs::Set {
+                            nearest,
+                            path,
+                            bg,
+                            no_cache,
+                            dither,
+                            resize_mode,
+                            color_correction,
+                            crop,
+                            verify,
+                            upload_retries,
+                            ..
+                        } => {
+                            board.set_upload_retries(upload_retries);
                             let (width, height) = board
                                 .as_screen_size()
                                 .ok_or("board does not support images")?;
-                            let image = ::image::open(path)?;
-                            // re-encode and upload to keyboard
-                            let encoded = encode_image(image, bg.0, nearest, width, height)
-                                .ok_or("failed to encode image")?;
-                            let len = encoded.len();
-                            let total = len / 24;
-                            let fmt_width = total.to_string().len();
-                            board
+                            let byte_order = board
                                 .as_image()
                                 .ok_or("board does not support images")?
-                                .upload_image(&encoded, &mut |i| {
-                                    print!("\ruploading {len} bytes ({i:fmt_width$}/{total}) ... ");
-                                    stdout().flush().unwrap();
-                                })?;
-                            Ok(())
+                                .byte_order();
+                            let options = ImageOptions {
+                                dither,
+                                resize: resize_mode,
+                                color: color_correction,
+                                crop,
+                                byte_order,
+                            };
+                            let encoded = encode_image_file(
+                                &path, bg.0, nearest, no_cache, width, height, options,
+                            )?;
+                            upload_image(board.as_mut(), &encoded, verify)
                         },
                         SetMediaArgs::Clear => {
                             board
@@ -238,59 +1113,55 @@ fn main() -> Result<(), Box<dyn Error>> {
                         },
                     },
                     SetCommand::Gif(args) => match args {
-                        SetMediaArgs::Set { nearest, path, bg } => {
+                        SetMediaArgs::Set {
+                            nearest,
+                            path,
+                            bg,
+                            no_cache,
+                            max_frames,
+                            fit_device,
+                            fps,
+                            resize_mode,
+                            color_correction,
+                            crop,
+                            loop_count,
+                            no_user_input,
+                            verify,
+                            upload_retries,
+                            ..
+                        } => {
+                            board.set_upload_retries(upload_retries);
                             let (width, height) = board
                                 .as_screen_size()
                                 .ok_or("board does not support gifs")?;
-                            print!("decoding animation ... ");
-                            stdout().flush().unwrap();
-                            let decoder = image::ImageReader::open(path)?
-                                .with_guessed_format()
-                                .unwrap();
-                            let frames = match decoder.format() {
-                                Some(image::ImageFormat::Gif) => {
-                                    // Reset reader and decode gif as an animation
-                                    let mut reader = decoder.into_inner();
-                                    reader.seek(std::io::SeekFrom::Start(0)).unwrap();
-                                    Some(GifDecoder::new(reader)?.into_frames())
-                                },
-                                Some(image::ImageFormat::Png) => {
-                                    // Reset reader
-                                    let mut reader = decoder.into_inner();
-                                    reader.seek(std::io::SeekFrom::Start(0)).unwrap();
-                                    let decoder = PngDecoder::new(reader)?;
-                                    // If the png contains an apng, decode as an animation
-                                    decoder
-                                        .is_apng()?
-                                        .then_some(decoder.apng().unwrap().into_frames())
-                                },
-                                Some(image::ImageFormat::WebP) => {
-                                    // Reset reader
-                                    let mut reader = decoder.into_inner();
-                                    reader.seek(std::io::SeekFrom::Start(0)).unwrap();
-                                    let decoder = WebPDecoder::new(reader).unwrap();
-                                    // If the webp contains an animation, decode as an animation
-                                    decoder.has_animation().then_some(decoder.into_frames())
-                                },
-                                _ => None,
-                            }
-                            .ok_or("failed to decode animation")?;
-                            println!("done");
-
-                            // re-encode and upload to keyboard
-                            let encoded = encode_gif(frames, bg.0, nearest, width, height)
-                                .ok_or("failed to encode gif image")?;
+                            let gif = board.as_gif().ok_or("board does not support gifs")?;
+                            let options = GifOptions {
+                                max_frames,
+                                max_size: fit_device.then(|| gif.max_size()).flatten(),
+                                delay: fps.map(fps_to_delay),
+                                resize: resize_mode,
+                                color: color_correction,
+                                crop,
+                                loop_count,
+                                user_input: !no_user_input,
+                            };
+                            let encoded = encode_gif_file(
+                                &path, bg.0, nearest, no_cache, width, height, options,
+                            )?;
                             let len = encoded.len();
-                            let total = len / 24;
-                            let fmt_width = total.to_string().len();
-                            board
-                                .as_gif()
-                                .ok_or("board does not support gifs")?
-                                .upload_gif(&encoded, &mut |i| {
-                                    print!("\ruploading {len} bytes ({i:fmt_width$}/{total}) ... ");
-                                    stdout().flush().unwrap();
-                                })?;
-                            println!("done");
+                            let total = len.div_ceil(gif.chunk_size());
+                            debug!(
+                                "uploading gif in {total} chunks of {} bytes",
+                                gif.chunk_size()
+                            );
+                            let message = format!("uploading {len} bytes");
+                            gif.upload_gif(&encoded, &mut |i| {
+                                progress::update("upload", &message, i, total);
+                            })?;
+                            progress::finish("upload");
+                            if verify {
+                                report_verify(gif.verify_gif(&encoded))?;
+                            }
                             Ok(())
                         },
                         SetMediaArgs::Clear => {
@@ -301,19 +1172,40 @@ fn main() -> Result<(), Box<dyn Error>> {
                             Ok(())
                         },
                     },
-                    SetCommand::Clear => {
+                    SetCommand::Clear { all } => {
+                        let mut applied = Vec::new();
                         if let Some(img) = board.as_image() {
                             img.clear_image()?;
+                            applied.push("image");
                         }
                         if let Some(gif) = board.as_gif() {
                             gif.clear_gif()?;
+                            applied.push("gif");
+                        }
+                        if all {
+                            // Setting the theme moves the screen too, so reset the screen last
+                            if let Some(theme) = board.as_theme() {
+                                if let Some(id) = theme.default_theme() {
+                                    theme.set_theme(id)?;
+                                    applied.push("theme");
+                                }
+                            }
+                            if let Some(screen) = board.as_screen() {
+                                screen.reset_screen()?;
+                                applied.push("screen");
+                            }
+                        }
+                        if applied.is_empty() {
+                            warn!("board has nothing to reset");
+                        } else {
+                            info!("reset {}", applied.join(", "));
                         }
-                        println!("cleared media");
                         Ok(())
                     },
                 }
             })
         },
+        Command::Upload { .. } => unreachable!("uploads are turned into set commands above"),
     }
 }
 
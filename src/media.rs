@@ -1,10 +1,166 @@
+use std::borrow::Cow;
 use std::cmp::max;
-use std::io::{stdout, Write};
-use std::sync::atomic::AtomicU16;
+use std::fs;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::io::{Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
 
+use ab_glyph::{point, Font, FontRef, Glyph, Point, PxScaleFont, ScaleFont};
+use directories::ProjectDirs;
+use image::codecs::gif::GifDecoder;
+use image::codecs::png::PngDecoder;
+use image::codecs::webp::WebPDecoder;
 use image::imageops::FilterType;
-use image::{imageops, DynamicImage, Frames, GenericImageView, ImageBuffer, Pixel};
-use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use image::{
+    imageops, AnimationDecoder, DynamicImage, Frames, GenericImageView, ImageBuffer, ImageFormat,
+    Pixel, RgbaImage,
+};
+use log::{debug, info, warn};
+use rayon::iter::{IndexedParallelIterator, IntoParallelRefIterator, ParallelIterator};
+use serde::{Deserialize, Serialize};
+use zoom_sync_core::ByteOrder;
+
+use crate::progress;
+
+/// How media is scaled onto the screen when the aspect ratios differ
+#[derive(Clone, Copy, Debug, Default, Hash, PartialEq, Eq, Serialize, Deserialize, bpaf::Bpaf)]
+#[serde(rename_all = "kebab-case")]
+pub enum ResizeMode {
+    /// Scale and crop to fill the whole screen (default)
+    #[default]
+    Fill,
+    /// Scale to fit inside the screen, padding the edges with the background color
+    Fit,
+    /// Stretch to the screen size, ignoring the aspect ratio
+    Stretch,
+}
+
+/// Parse a `#rrggbb` or `#rgb` hex color, with or without the leading `#`
+pub fn parse_hex_color(code: &str) -> Result<[u8; 3], String> {
+    let mut hex = code.trim_start_matches('#').to_string();
+    match hex.len() {
+        3 => {
+            // Extend 3 character hex colors
+            hex = hex.chars().flat_map(|a| [a, a]).collect();
+        },
+        6 => {},
+        l => return Err(format!("Invalid hex length for {code}: {l}")),
+    }
+    match u32::from_str_radix(&hex, 16) {
+        Ok(channel_bytes) if hex.chars().all(|c| c.is_ascii_hexdigit()) => {
+            let r = ((channel_bytes >> 16) & 0xFF) as u8;
+            let g = ((channel_bytes >> 8) & 0xFF) as u8;
+            let b = (channel_bytes & 0xFF) as u8;
+            Ok([r, g, b])
+        },
+        _ => Err(format!("Invalid hex color: {code}")),
+    }
+}
+
+/// Region of the source media to keep, cut out before resizing
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub struct Crop {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl FromStr for Crop {
+    type Err = String;
+
+    /// Parse `x,y,w,h` in source pixels
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let values = s
+            .split(',')
+            .map(|v| v.trim().parse::<u32>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("invalid crop {s}: {e}"))?;
+        let [x, y, width, height] = values[..] else {
+            return Err(format!("invalid crop {s}, expected x,y,w,h"));
+        };
+        if width == 0 || height == 0 {
+            return Err(format!(
+                "invalid crop {s}, width and height must not be zero"
+            ));
+        }
+        Ok(Self {
+            x,
+            y,
+            width,
+            height,
+        })
+    }
+}
+
+impl Crop {
+    /// Cut the region out of an image, failing if it doesn't lie within the image
+    pub fn apply(&self, image: &RgbaImage) -> Result<RgbaImage, ImageProcessingError> {
+        let fits =
+            |start: u32, len: u32, max: u32| start.checked_add(len).is_some_and(|end| end <= max);
+        if !fits(self.x, self.width, image.width()) || !fits(self.y, self.height, image.height()) {
+            return Err(ImageProcessingError::InvalidCrop(format!(
+                "{}x{} at {},{} is outside the {}x{} source",
+                self.width,
+                self.height,
+                self.x,
+                self.y,
+                image.width(),
+                image.height()
+            )));
+        }
+        Ok(imageops::crop_imm(image, self.x, self.y, self.width, self.height).to_image())
+    }
+}
+
+/// Options for encoding still images
+#[derive(Clone, Copy, Debug, Default, Hash, PartialEq, Eq)]
+pub struct ImageOptions {
+    /// Dither the conversion to rgb565 to reduce banding on gradients
+    pub dither: bool,
+    /// How the image is scaled onto the screen
+    pub resize: ResizeMode,
+    /// Region of the source to keep before resizing
+    pub crop: Option<Crop>,
+    /// Byte order of each encoded pixel, from the board
+    pub byte_order: ByteOrder,
+    /// Panel calibration applied before converting to rgb565
+    pub color: Option<ColorCorrection>,
+}
+
+/// Per channel lookup tables calibrating colors for a panel
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub struct ColorCorrection([[u8; 256]; 3]);
+
+impl ColorCorrection {
+    /// Raise each channel to `gamma`, then multiply it by its entry in `scale`. Gamma below 1
+    /// brightens midtones and above 1 darkens them. `None` for the identity, so uncalibrated
+    /// encodes skip the lookups.
+    pub fn new(gamma: f32, scale: [f32; 3]) -> Option<Self> {
+        if gamma == 1. && scale == [1.; 3] {
+            return None;
+        }
+        Some(Self(std::array::from_fn(|c| {
+            std::array::from_fn(|i| {
+                let level = (i as f32 / 255.).powf(gamma) * scale[c];
+                (level * 255.).round().clamp(0., 255.) as u8
+            })
+        })))
+    }
+
+    /// Look up the corrected color of a pixel
+    pub fn apply(&self, [r, g, b]: [u8; 3]) -> [u8; 3] {
+        [
+            self.0[0][r as usize],
+            self.0[1][g as usize],
+            self.0[2][b as usize],
+        ]
+    }
+}
 
 /// Encode an square image as rgb565 with an 8 bit alpha channel
 pub fn encode_image(
@@ -13,24 +169,19 @@ pub fn encode_image(
     nearest: bool,
     width: u32,
     height: u32,
-) -> Option<Vec<u8>> {
-    print!("resizing and encoding image ... ");
-    stdout().flush().unwrap();
+    options: ImageOptions,
+) -> Result<Vec<u8>, ImageProcessingError> {
+    let mut image = image.to_rgba8();
+    if let Some(crop) = options.crop {
+        image = crop.apply(&image)?;
+    }
+
+    progress::start("encode", "resizing and encoding image");
     let [br, bg, bb] = background;
 
-    let buf = image
-        .resize_to_fill(
-            width,
-            height,
-            if nearest {
-                FilterType::Nearest
-            } else {
-                FilterType::Gaussian
-            },
-        )
-        .to_rgba8()
+    let pixels = resize(&image, width, height, nearest, options.resize)
         .pixels()
-        .flat_map(|p| {
+        .map(|p| {
             let [mut r, mut g, mut b, a] = p.0;
 
             // Mix alpha values against black
@@ -39,18 +190,383 @@ pub fn encode_image(
             r = ((br as f64 * ba) + (r as f64 * a)) as u8;
             g = ((bg as f64 * ba) + (g as f64 * a)) as u8;
             b = ((bb as f64 * ba) + (b as f64 * a)) as u8;
-
-            // Convert into rgb565 pixel type
-            let [x, y] = rgb565::Rgb565::from_rgb888_components(r, g, b).to_rgb565_be();
-
-            // Extend with hard coded alpha channel
-            [x, y, 0xff]
+            match options.color {
+                Some(color) => color.apply([r, g, b]),
+                None => [r, g, b],
+            }
         })
         .collect::<Vec<_>>();
+    let buf = encode_rgb565(pixels, width as usize, options.dither, options.byte_order);
     debug_assert_eq!(buf.len(), (width * height * 3) as usize);
 
-    println!("done");
-    Some(buf)
+    progress::finish("encode");
+    Ok(buf)
+}
+
+/// Synthesized screen content, for checking color accuracy, pixel order and screen coverage
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Pattern {
+    /// One color across the whole screen
+    Color([u8; 3]),
+    /// Vertical bars of white, yellow, cyan, green, magenta, red, blue and black
+    Bars,
+    /// Red, green, blue and grey ramps stacked top to bottom, dark on the left
+    Gradient,
+    /// Alternating 8x8 white and black squares, white in the top left corner
+    Checkerboard,
+}
+
+impl FromStr for Pattern {
+    type Err = String;
+
+    /// Parse a test pattern name. Solid colors aren't named, they're parsed as hex colors.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "bars" => Ok(Pattern::Bars),
+            "gradient" => Ok(Pattern::Gradient),
+            "checkerboard" => Ok(Pattern::Checkerboard),
+            _ => Err(format!(
+                "unknown test pattern {s}, expected bars, gradient or checkerboard"
+            )),
+        }
+    }
+}
+
+impl Pattern {
+    /// Color of the pixel at `x`,`y` on a `width`x`height` screen
+    fn pixel(self, x: u32, y: u32, width: u32, height: u32) -> [u8; 3] {
+        const BARS: [[u8; 3]; 8] = [
+            [255, 255, 255],
+            [255, 255, 0],
+            [0, 255, 255],
+            [0, 255, 0],
+            [255, 0, 255],
+            [255, 0, 0],
+            [0, 0, 255],
+            [0, 0, 0],
+        ];
+        match self {
+            Pattern::Color(color) => color,
+            Pattern::Bars => BARS[(x * BARS.len() as u32 / width) as usize],
+            Pattern::Gradient => {
+                let level = (x * 255 / width.saturating_sub(1).max(1)) as u8;
+                match y * 4 / height {
+                    0 => [level, 0, 0],
+                    1 => [0, level, 0],
+                    2 => [0, 0, level],
+                    _ => [level; 3],
+                }
+            },
+            Pattern::Checkerboard => {
+                if (x / 8 + y / 8).is_multiple_of(2) {
+                    [255; 3]
+                } else {
+                    [0; 3]
+                }
+            },
+        }
+    }
+}
+
+/// Render a pattern at the screen size, encoded the same way as [`encode_image`] output
+pub fn generate_image(pattern: Pattern, width: u32, height: u32, byte_order: ByteOrder) -> Vec<u8> {
+    let pixels = (0..height)
+        .flat_map(|y| (0..width).map(move |x| pattern.pixel(x, y, width, height)))
+        .collect();
+    encode_rgb565(pixels, width as usize, false, byte_order)
+}
+
+/// Font text is rendered with, DejaVu Sans Mono Bold (see assets/DejaVu-LICENSE.txt)
+const FONT: &[u8] = include_bytes!("../assets/DejaVuSansMono-Bold.ttf");
+
+/// Options for rendering text onto the screen
+#[derive(Clone, Copy, Debug)]
+pub struct TextOptions {
+    /// Font size in pixels, or `None` for the largest size that fits the screen
+    pub size: Option<f32>,
+    pub color: [u8; 3],
+    pub background: [u8; 3],
+    /// Byte order of each encoded pixel, from the board
+    pub byte_order: ByteOrder,
+}
+
+/// Render lines of text centered on the screen, encoded the same way as [`encode_image`]
+/// output. Text that doesn't fit at an explicit size is clipped.
+pub fn render_text(text: &str, width: u32, height: u32, options: TextOptions) -> Vec<u8> {
+    let font = FontRef::try_from_slice(FONT).expect("bundled font is valid");
+    let lines: Vec<_> = text.split('\n').collect();
+
+    // Everything scales linearly with the font size, so measure at 1px to find the fitting size
+    let size = options.size.unwrap_or_else(|| {
+        let unit = font.as_scaled(1.);
+        let widest = lines
+            .iter()
+            .map(|line| layout_line(&unit, line, point(0., 0.)).1)
+            .fold(0., f32::max);
+        let tall = unit.height() * lines.len() as f32 + unit.line_gap() * (lines.len() - 1) as f32;
+        // leave a small margin around the edges
+        f32::min(width as f32 * 0.9 / widest, height as f32 * 0.9 / tall)
+    });
+    let font = font.as_scaled(size);
+
+    let mut pixels = vec![options.background; (width * height) as usize];
+    let line_height = font.height() + font.line_gap();
+    let block_height = line_height * lines.len() as f32 - font.line_gap();
+    let top = (height as f32 - block_height) / 2.;
+    for (i, line) in lines.iter().enumerate() {
+        let line_width = layout_line(&font, line, point(0., 0.)).1;
+        let origin = point(
+            (width as f32 - line_width) / 2.,
+            top + line_height * i as f32 + font.ascent(),
+        );
+        for glyph in layout_line(&font, line, origin).0 {
+            let Some(outline) = font.outline_glyph(glyph) else {
+                continue;
+            };
+            let bounds = outline.px_bounds();
+            outline.draw(|x, y, coverage| {
+                let x = bounds.min.x as i64 + x as i64;
+                let y = bounds.min.y as i64 + y as i64;
+                if (0..width as i64).contains(&x) && (0..height as i64).contains(&y) {
+                    let pixel = &mut pixels[(y * width as i64 + x) as usize];
+                    *pixel = std::array::from_fn(|c| {
+                        let mixed =
+                            pixel[c] as f32 * (1. - coverage) + options.color[c] as f32 * coverage;
+                        mixed.round() as u8
+                    });
+                }
+            });
+        }
+    }
+
+    encode_rgb565(pixels, width as usize, false, options.byte_order)
+}
+
+/// Position a line of glyphs starting at `origin` on the baseline, returning them with the
+/// width of the line
+fn layout_line(font: &PxScaleFont<&FontRef>, line: &str, origin: Point) -> (Vec<Glyph>, f32) {
+    let mut caret = origin.x;
+    let mut previous = None;
+    let mut glyphs = Vec::new();
+    for c in line.chars() {
+        let id = font.glyph_id(c);
+        if let Some(previous) = previous {
+            caret += font.kern(previous, id);
+        }
+        glyphs.push(id.with_scale_and_position(font.scale(), point(caret, origin.y)));
+        caret += font.h_advance(id);
+        previous = Some(id);
+    }
+    (glyphs, caret - origin.x)
+}
+
+/// Convert rgb888 pixels into rgb565 with a hard coded alpha channel.
+///
+/// With `dither`, the rounding error of each pixel is diffused into its unvisited neighbors
+/// (Floyd-Steinberg), trading banding on smooth gradients for fine noise.
+fn encode_rgb565(
+    pixels: Vec<[u8; 3]>,
+    width: usize,
+    dither: bool,
+    byte_order: ByteOrder,
+) -> Vec<u8> {
+    let encode = |[r, g, b]: [u8; 3]| {
+        let color = rgb565::Rgb565::from_rgb888_components(r, g, b);
+        let [x, y] = match byte_order {
+            ByteOrder::BigEndian => color.to_rgb565_be(),
+            ByteOrder::LittleEndian => color.to_rgb565_le(),
+        };
+        [x, y, 0xff]
+    };
+    if !dither {
+        return pixels.into_iter().flat_map(encode).collect();
+    }
+
+    // Maximum value of each channel in rgb565
+    const LEVELS: [f32; 3] = [31., 63., 31.];
+
+    let len = pixels.len();
+    let mut pixels = pixels
+        .into_iter()
+        .map(|p| p.map(f32::from))
+        .collect::<Vec<_>>();
+    let mut buf = Vec::with_capacity(len * 3);
+    for i in 0..len {
+        let old = pixels[i];
+        // Snap each channel to the closest value rgb565 can represent
+        let new: [u8; 3] = std::array::from_fn(|c| {
+            let level = (old[c].clamp(0., 255.) / 255. * LEVELS[c]).round();
+            (level / LEVELS[c] * 255.).round() as u8
+        });
+        buf.extend(encode(new));
+
+        let error: [f32; 3] = std::array::from_fn(|c| old[c] - new[c] as f32);
+        let mut spread = |j: usize, weight: f32| {
+            for (p, e) in pixels[j].iter_mut().zip(error) {
+                *p += e * weight;
+            }
+        };
+        let x = i % width;
+        if x + 1 < width {
+            spread(i + 1, 7. / 16.);
+        }
+        if i + width < len {
+            if x > 0 {
+                spread(i + width - 1, 3. / 16.);
+            }
+            spread(i + width, 5. / 16.);
+            if x + 1 < width {
+                spread(i + width + 1, 1. / 16.);
+            }
+        }
+    }
+    buf
+}
+
+/// Errors that can occur during image/gif processing
+#[derive(Debug, thiserror::Error)]
+pub enum ImageProcessingError {
+    #[error("failed to open file: {0}")]
+    OpenFile(#[from] std::io::Error),
+    #[error("failed to decode image: {0}")]
+    DecodeImage(#[from] image::ImageError),
+    #[error("invalid crop: {0}")]
+    InvalidCrop(String),
+    #[error("failed to encode gif")]
+    EncodeGif,
+    #[error("{0} file is not animated, upload it as an image instead")]
+    NotAnimated(&'static str),
+    #[error("unsupported animation format")]
+    UnsupportedFormat,
+}
+
+/// Open an animation file and decode its frames, dispatching on the detected format.
+/// Still images are rejected with [`ImageProcessingError::NotAnimated`].
+pub fn decode_animation(path: &Path) -> Result<Frames<'static>, ImageProcessingError> {
+    let decoder = image::ImageReader::open(path)?.with_guessed_format()?;
+    let format = decoder.format();
+    // Format detection peeks at the header, so rewind before handing the reader on
+    let mut reader = decoder.into_inner();
+    reader.seek(SeekFrom::Start(0))?;
+
+    match format {
+        Some(ImageFormat::Gif) => Ok(GifDecoder::new(reader)?.into_frames()),
+        Some(ImageFormat::Png) => {
+            let png = PngDecoder::new(reader)?;
+            if !png.is_apng()? {
+                return Err(ImageProcessingError::NotAnimated("png"));
+            }
+            Ok(png.apng()?.into_frames())
+        },
+        Some(ImageFormat::WebP) => {
+            let webp = WebPDecoder::new(reader)?;
+            if !webp.has_animation() {
+                return Err(ImageProcessingError::NotAnimated("webp"));
+            }
+            Ok(webp.into_frames())
+        },
+        // Still-only formats (jpeg, bmp, ...) decode fine but never animate
+        Some(format) if format.reading_enabled() => Err(ImageProcessingError::NotAnimated(
+            format.extensions_str()[0],
+        )),
+        _ => Err(ImageProcessingError::UnsupportedFormat),
+    }
+}
+
+/// Whether a file should be encoded as an animation rather than a still image
+pub fn is_animated(path: &Path) -> Result<bool, ImageProcessingError> {
+    match decode_animation(path) {
+        Ok(_) => Ok(true),
+        Err(ImageProcessingError::NotAnimated(_)) => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+/// Options for fitting animations onto a device
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub struct GifOptions {
+    /// Keep at most this many frames, dropping evenly spaced frames before encoding
+    pub max_frames: Option<usize>,
+    /// Keep dropping every other frame until the encoded gif is smaller than this many bytes
+    pub max_size: Option<usize>,
+    /// Constant delay stamped into every frame in centiseconds, ignoring the source timing
+    pub delay: Option<u16>,
+    /// How each frame is scaled onto the screen
+    pub resize: ResizeMode,
+    /// Region of each frame to keep before resizing
+    pub crop: Option<Crop>,
+    /// Play the animation this many times and stop on the last frame, `None` to loop forever
+    pub loop_count: Option<u16>,
+    /// Set the gif "user input" flag on every frame. Decoders advance such a frame on user
+    /// input or once its delay runs out, whichever comes first, so with the nonzero delays
+    /// written here frames still advance on their own. It's set by default since that's what
+    /// the zoom65v3 has been tested with.
+    pub user_input: bool,
+    /// Panel calibration applied to every frame
+    pub color: Option<ColorCorrection>,
+}
+
+impl Default for GifOptions {
+    fn default() -> Self {
+        Self {
+            max_frames: None,
+            max_size: None,
+            delay: None,
+            resize: ResizeMode::default(),
+            crop: None,
+            loop_count: None,
+            user_input: true,
+            color: None,
+        }
+    }
+}
+
+/// Encode thread count forced from the command line, taking priority over the config
+static THREADS_OVERRIDE: OnceLock<usize> = OnceLock::new();
+/// Encode thread count from the config
+static THREADS: AtomicUsize = AtomicUsize::new(0);
+
+/// Force the number of threads used to encode animations, ignoring the config. Only the first
+/// call has any effect.
+pub fn override_encode_threads(threads: usize) {
+    let _ = THREADS_OVERRIDE.set(threads);
+}
+
+/// Set the number of threads used to encode animations, zero for all cores
+pub fn set_encode_threads(threads: usize) {
+    THREADS.store(threads, Ordering::Relaxed);
+}
+
+/// Dedicated pool for a limited encode thread count, or `None` to use the global pool
+fn encode_pool() -> Option<rayon::ThreadPool> {
+    let threads = THREADS_OVERRIDE
+        .get()
+        .copied()
+        .unwrap_or_else(|| THREADS.load(Ordering::Relaxed));
+    if threads == 0 {
+        return None;
+    }
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .build()
+        .inspect_err(|e| warn!("failed to limit encoding to {threads} threads: {e}"))
+        .ok()
+}
+
+/// Shortest frame delay written to a gif in centiseconds. Zero or tiny delays make the
+/// firmware (and most viewers) play the animation as fast as it can.
+pub const MIN_FRAME_DELAY: u16 = 2;
+
+/// Convert a source frame delay to gif centiseconds, clamped to [`MIN_FRAME_DELAY`]
+fn frame_delay(delay: image::Delay) -> u16 {
+    let (numer, denom) = delay.numer_denom_ms();
+    let centis = numer.checked_div(denom).map_or(0, |ms| ms / 10);
+    centis.clamp(MIN_FRAME_DELAY as u32, u16::MAX as u32) as u16
+}
+
+/// Combined delay of consecutive frames, for the one frame kept in their place
+fn total_delay(delays: impl Iterator<Item = u16>) -> u16 {
+    delays.map(u32::from).sum::<u32>().min(u16::MAX as u32) as u16
 }
 
 /// Re-encode animation frames as a gif
@@ -60,48 +576,182 @@ pub fn encode_gif(
     nearest: bool,
     width: u32,
     height: u32,
-) -> Option<Vec<u8>> {
-    let frames = frames.collect_frames().ok()?;
+    options: GifOptions,
+) -> Result<Vec<u8>, ImageProcessingError> {
+    let frames = frames.collect_frames()?;
+    let source_len = frames.len();
+    // Every frame covers the whole canvas, so checking the first one is enough
+    if let (Some(crop), Some(first)) = (options.crop, frames.first()) {
+        crop.apply(first.buffer())?;
+    }
+
+    // Evenly drop frames down to the frame limit before doing any encoding work. The time of
+    // dropped frames goes to the kept frame before them, so the animation keeps its duration.
+    let step = options
+        .max_frames
+        .map_or(1, |max| source_len.div_ceil(max.max(1)));
+    let source_delays: Vec<_> = frames
+        .iter()
+        .map(|frame| {
+            options
+                .delay
+                .map_or_else(|| frame_delay(frame.delay()), |d| d.max(MIN_FRAME_DELAY))
+        })
+        .collect();
+    let delays: Vec<_> = source_delays
+        .chunks(step)
+        .map(|chunk| total_delay(chunk.iter().copied()))
+        .collect();
+    let frames = frames.into_iter().step_by(step).collect::<Vec<_>>();
     let len = frames.len();
     let [br, bg, bb] = background;
     // GIF dimensions need to be +1 for some reason with zoom65v3
     let gif_width = width + 1;
     let gif_height = height + 1;
 
-    let completed = AtomicU16::new(1);
-    let new_frames = frames
-        .par_iter()
-        .map(|frame| {
-            let resized = resize_to_fill(frame.buffer(), gif_width, gif_height, nearest);
-            let mut buf = image::ImageBuffer::from_fn(gif_width, gif_height, |_, _| {
-                [br, bg, bb, 0xff].into()
-            });
-            imageops::overlay(&mut buf, &resized, 0, 0);
-
-            let mut frame =
-                gif::Frame::from_rgba(gif_width as u16, gif_height as u16, &mut buf.into_vec());
-            frame.make_lzw_pre_encoded();
-            frame.needs_user_input = true;
-            let i = completed.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
-            print!("\rre-encoding frames ({i}/{len}) ... ");
-            stdout().flush().unwrap();
-            frame
-        })
-        .collect::<Vec<_>>();
+    // Resizing, quantizing and lzw compression all happen per frame here. Muxing the compressed
+    // frames afterwards is a plain copy, so there's nothing left worth parallelizing.
+    let completed = Mutex::new(0);
+    let encode_frames = || {
+        frames
+            .par_iter()
+            .zip(&delays)
+            .map(|(source, &delay)| {
+                let cropped = options
+                    .crop
+                    .and_then(|crop| crop.apply(source.buffer()).ok());
+                let resized = resize(
+                    cropped.as_ref().unwrap_or(source.buffer()),
+                    gif_width,
+                    gif_height,
+                    nearest,
+                    options.resize,
+                );
+                let mut buf = image::ImageBuffer::from_fn(gif_width, gif_height, |_, _| {
+                    [br, bg, bb, 0xff].into()
+                });
+                imageops::overlay(&mut buf, &resized, 0, 0);
+                if let Some(color) = options.color {
+                    for pixel in buf.pixels_mut() {
+                        let [r, g, b, a] = pixel.0;
+                        let [r, g, b] = color.apply([r, g, b]);
+                        pixel.0 = [r, g, b, a];
+                    }
+                }
 
+                let mut frame =
+                    gif::Frame::from_rgba(gif_width as u16, gif_height as u16, &mut buf.into_vec());
+                frame.make_lzw_pre_encoded();
+                frame.needs_user_input = options.user_input;
+                frame.delay = delay;
+                // Report while holding the lock so counts from different threads print in order
+                let mut done = completed.lock().unwrap();
+                *done += 1;
+                progress::update("encode", "re-encoding frames", *done, len);
+                drop(done);
+                frame
+            })
+            .collect::<Vec<_>>()
+    };
+    let new_frames = match encode_pool() {
+        Some(pool) => pool.install(encode_frames),
+        None => encode_frames(),
+    };
+
+    progress::finish("encode");
+
+    // Frames are compressed independently, so dropping more of them only requires re-muxing
+    let mut stride = 1;
+    let buf = loop {
+        let buf = write_gif(
+            &new_frames,
+            stride,
+            gif_width,
+            gif_height,
+            options.loop_count,
+        )
+        .ok_or(ImageProcessingError::EncodeGif)?;
+        match options.max_size {
+            Some(max) if buf.len() >= max && len.div_ceil(stride) > 1 => stride *= 2,
+            _ => break buf,
+        }
+    };
+
+    let kept = len.div_ceil(stride);
+    if kept < source_len {
+        info!(
+            "downsampled animation from {source_len} to {kept} frames ({} bytes)",
+            buf.len()
+        );
+    }
+    Ok(buf)
+}
+
+/// Write every `stride`th pre-encoded frame into a gif, played `loop_count` times or forever.
+/// Each written frame also covers the delays of the frames skipped after it.
+fn write_gif(
+    frames: &[gif::Frame],
+    stride: usize,
+    width: u32,
+    height: u32,
+    loop_count: Option<u16>,
+) -> Option<Vec<u8>> {
+    // The repeat count is how often playback restarts after the first time through
+    let repeat = match loop_count {
+        Some(plays) => gif::Repeat::Finite(plays.saturating_sub(1)),
+        None => gif::Repeat::Infinite,
+    };
     let mut buf = Vec::new();
     {
-        let mut encoder =
-            gif::Encoder::new(&mut buf, gif_width as u16, gif_height as u16, &[]).ok()?;
-        encoder.set_repeat(gif::Repeat::Infinite).ok()?;
-        for frame in new_frames {
+        let mut encoder = gif::Encoder::new(&mut buf, width as u16, height as u16, &[]).ok()?;
+        encoder.set_repeat(repeat).ok()?;
+        for chunk in frames.chunks(stride) {
+            let first = &chunk[0];
+            let frame = gif::Frame {
+                delay: total_delay(chunk.iter().map(|frame| frame.delay)),
+                palette: first.palette.clone(),
+                buffer: Cow::Borrowed(&first.buffer),
+                ..*first
+            };
             encoder.write_lzw_pre_encoded_frame(&frame).ok()?;
         }
     }
-    println!("done");
     Some(buf)
 }
 
+/// Resize an image onto a `width`x`height` canvas. Padding left by [`ResizeMode::Fit`] is
+/// transparent, so it's filled with the background color when alpha is mixed.
+pub fn resize(
+    image: &RgbaImage,
+    width: u32,
+    height: u32,
+    nearest: bool,
+    mode: ResizeMode,
+) -> RgbaImage {
+    let filter = if nearest {
+        FilterType::Nearest
+    } else {
+        FilterType::Gaussian
+    };
+    match mode {
+        ResizeMode::Fill => resize_to_fill(image, width, height, nearest),
+        ResizeMode::Stretch => imageops::resize(image, width, height, filter),
+        ResizeMode::Fit => {
+            let (fwidth, fheight) =
+                resize_dimensions(image.width(), image.height(), width, height, false);
+            let fitted = imageops::resize(image, fwidth, fheight, filter);
+            let mut canvas = RgbaImage::new(width, height);
+            imageops::overlay(
+                &mut canvas,
+                &fitted,
+                (width.saturating_sub(fwidth) / 2).into(),
+                (height.saturating_sub(fheight) / 2).into(),
+            );
+            canvas
+        },
+    }
+}
+
 pub fn resize_to_fill<I: GenericImageView>(
     image: &I,
     nwidth: u32,
@@ -173,3 +823,513 @@ pub fn resize_dimensions(
         (nw as u32, nh as u32)
     }
 }
+
+/// Kind of encoded media, used to separate image and gif entries in the cache
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub enum MediaKind {
+    Image(ImageOptions),
+    Gif(GifOptions),
+}
+
+/// On-disk cache of encoded media buffers, keyed by source content and encoding parameters.
+///
+/// Each entry is prefixed with the source file's size and mtime, so editing or touching the
+/// source invalidates the entry even if the content hash happens to collide. Entries are touched
+/// when used, and the least recently used ones are removed once the cache outgrows
+/// [`MediaCache::MAX_SIZE`].
+pub struct MediaCache {
+    dir: PathBuf,
+}
+
+impl MediaCache {
+    /// Size of the entry header: source length (u64) and mtime in nanoseconds (u128)
+    const HEADER_LEN: usize = 8 + 16;
+
+    /// Part of every key, bump it whenever the encoders' output changes so older entries are
+    /// never reused
+    const VERSION: u32 = 2;
+
+    /// Total size of the entries kept on disk
+    const MAX_SIZE: u64 = 64 * 1024 * 1024;
+
+    /// Open the cache in the default location under the config directory
+    pub fn open() -> Option<Self> {
+        ProjectDirs::from("", "", "zoom-sync").map(|dirs| Self {
+            dir: dirs.config_dir().join("cache"),
+        })
+    }
+
+    /// Encode media through the cache. On a miss, `encode` is called and the result is stored.
+    /// Cache failures are never fatal, they only cause a re-encode.
+    #[allow(clippy::too_many_arguments)]
+    pub fn get_or_encode<E>(
+        &self,
+        path: &Path,
+        kind: MediaKind,
+        background: [u8; 3],
+        nearest: bool,
+        width: u32,
+        height: u32,
+        encode: impl FnOnce() -> Result<Vec<u8>, E>,
+    ) -> Result<Vec<u8>, E> {
+        let Some((entry, header)) = self.entry(path, kind, background, nearest, width, height)
+        else {
+            return encode();
+        };
+
+        if let Ok(contents) = fs::read(&entry) {
+            if contents.len() >= Self::HEADER_LEN && contents[..Self::HEADER_LEN] == header {
+                debug!("using cached encoding from {}", entry.display());
+                // mark the entry as recently used, so pruning keeps it
+                let _ = fs::File::options()
+                    .write(true)
+                    .open(&entry)
+                    .and_then(|file| file.set_modified(SystemTime::now()));
+                return Ok(contents[Self::HEADER_LEN..].to_vec());
+            }
+            // source metadata changed, drop the stale entry
+            let _ = fs::remove_file(&entry);
+        }
+
+        let buf = encode()?;
+        if let Err(e) = fs::create_dir_all(&self.dir)
+            .and_then(|_| fs::write(&entry, [header.as_slice(), &buf].concat()))
+        {
+            warn!("failed to write media cache: {e}");
+        }
+        if let Err(e) = self.prune(Self::MAX_SIZE) {
+            warn!("failed to prune media cache: {e}");
+        }
+        Ok(buf)
+    }
+
+    /// Remove the least recently used entries until the cache fits in `max_size` bytes
+    fn prune(&self, max_size: u64) -> std::io::Result<()> {
+        let mut entries: Vec<_> = fs::read_dir(&self.dir)?
+            .filter_map(|entry| {
+                let path = entry.ok()?.path();
+                let meta = fs::metadata(&path).ok()?;
+                (path.extension()? == "bin").then_some((meta.modified().ok()?, meta.len(), path))
+            })
+            .collect();
+        let mut total: u64 = entries.iter().map(|(_, len, _)| len).sum();
+        entries.sort_unstable_by_key(|(modified, ..)| *modified);
+        for (_, len, path) in entries {
+            if total <= max_size {
+                break;
+            }
+            debug!("pruning cached encoding {}", path.display());
+            fs::remove_file(&path)?;
+            total -= len;
+        }
+        Ok(())
+    }
+
+    /// Compute the entry path and expected header for a source file
+    fn entry(
+        &self,
+        path: &Path,
+        kind: MediaKind,
+        background: [u8; 3],
+        nearest: bool,
+        width: u32,
+        height: u32,
+    ) -> Option<(PathBuf, [u8; Self::HEADER_LEN])> {
+        let meta = fs::metadata(path).ok()?;
+        let mtime = meta.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+        let contents = fs::read(path).ok()?;
+
+        let mut hasher = DefaultHasher::new();
+        contents.hash(&mut hasher);
+        (Self::VERSION, kind, background, nearest, width, height).hash(&mut hasher);
+        let key = hasher.finish();
+
+        let mut header = [0u8; Self::HEADER_LEN];
+        header[..8].copy_from_slice(&meta.len().to_be_bytes());
+        header[8..].copy_from_slice(&mtime.as_nanos().to_be_bytes());
+
+        Some((self.dir.join(format!("{key:016x}.bin")), header))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Smooth grey ramp, finer than the rgb565 step size
+    fn gradient() -> DynamicImage {
+        DynamicImage::ImageRgb8(ImageBuffer::from_fn(64, 64, |x, _| {
+            image::Rgb([64 + x as u8 / 2; 3])
+        }))
+    }
+
+    #[test]
+    fn dithering_changes_output() {
+        let encode = |dither| {
+            let options = ImageOptions {
+                dither,
+                ..Default::default()
+            };
+            encode_image(gradient(), [0; 3], true, 64, 64, options).unwrap()
+        };
+        let plain = encode(false);
+        let dithered = encode(true);
+        assert_eq!(plain.len(), dithered.len());
+        assert_ne!(plain, dithered);
+    }
+
+    #[test]
+    fn little_endian_swaps_pixel_bytes() {
+        let red = DynamicImage::ImageRgb8(ImageBuffer::from_pixel(4, 4, image::Rgb([255, 0, 0])));
+        let encode = |byte_order| {
+            let options = ImageOptions {
+                byte_order,
+                ..Default::default()
+            };
+            encode_image(red.clone(), [0; 3], true, 4, 4, options).unwrap()
+        };
+        assert_eq!(encode(ByteOrder::BigEndian)[..3], [0xf8, 0x00, 0xff]);
+        assert_eq!(encode(ByteOrder::LittleEndian)[..3], [0x00, 0xf8, 0xff]);
+        assert_eq!(
+            generate_image(Pattern::Color([0, 0, 255]), 4, 4, ByteOrder::LittleEndian)[..3],
+            [0x1f, 0x00, 0xff]
+        );
+    }
+
+    #[test]
+    fn color_correction_lookup() {
+        assert_eq!(ColorCorrection::new(1., [1.; 3]), None);
+
+        let darker = ColorCorrection::new(2.2, [1.; 3]).unwrap();
+        assert_eq!(darker.apply([0, 255, 128]), [0, 255, 56]);
+        let brighter = ColorCorrection::new(0.5, [1.; 3]).unwrap();
+        assert_eq!(brighter.apply([64, 255, 0]), [128, 255, 0]);
+
+        // scales clamp instead of wrapping around
+        let warm = ColorCorrection::new(1., [1.5, 1., 0.5]).unwrap();
+        assert_eq!(warm.apply([200, 200, 200]), [255, 200, 100]);
+
+        let red = DynamicImage::ImageRgb8(ImageBuffer::from_pixel(4, 4, image::Rgb([255, 0, 0])));
+        let options = ImageOptions {
+            color: ColorCorrection::new(1., [0., 1., 1.]),
+            ..Default::default()
+        };
+        let buf = encode_image(red, [0; 3], true, 4, 4, options).unwrap();
+        assert!(buf.chunks(3).all(|p| p == [0, 0, 0xff]));
+    }
+
+    #[test]
+    fn fit_pads_with_background() {
+        // Wide red image letterboxed onto a square blue background
+        let wide =
+            DynamicImage::ImageRgb8(ImageBuffer::from_pixel(64, 16, image::Rgb([255, 0, 0])));
+        let options = ImageOptions {
+            resize: ResizeMode::Fit,
+            ..Default::default()
+        };
+        let buf = encode_image(wide, [0, 0, 255], true, 32, 32, options).unwrap();
+        let pixel = |x: usize, y: usize| &buf[(y * 32 + x) * 3..][..2];
+        let [rx, ry] = rgb565::Rgb565::from_rgb888_components(255, 0, 0).to_rgb565_be();
+        let [bx, by] = rgb565::Rgb565::from_rgb888_components(0, 0, 255).to_rgb565_be();
+        assert_eq!(pixel(16, 0), [bx, by]);
+        assert_eq!(pixel(16, 16), [rx, ry]);
+        assert_eq!(pixel(16, 31), [bx, by]);
+    }
+
+    #[test]
+    fn crop_selects_the_region() {
+        // Red left half, green right half. Cropping the right half leaves only green.
+        let split = DynamicImage::ImageRgb8(ImageBuffer::from_fn(64, 16, |x, _| {
+            image::Rgb(if x < 32 { [255, 0, 0] } else { [0, 255, 0] })
+        }));
+        let options = ImageOptions {
+            crop: Some("32,0,16,16".parse().unwrap()),
+            ..Default::default()
+        };
+        let buf = encode_image(split.clone(), [0; 3], true, 8, 8, options).unwrap();
+        let [gx, gy] = rgb565::Rgb565::from_rgb888_components(0, 255, 0).to_rgb565_be();
+        assert!(buf.chunks(3).all(|p| p == [gx, gy, 0xff]));
+
+        let outside = ImageOptions {
+            crop: Some("60,0,16,16".parse().unwrap()),
+            ..Default::default()
+        };
+        let err = encode_image(split, [0; 3], true, 8, 8, outside).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid crop: 16x16 at 60,0 is outside the 64x16 source"
+        );
+    }
+
+    #[test]
+    fn generated_images_cover_the_screen() {
+        let encoded = |[r, g, b]: [u8; 3]| {
+            let [x, y] = rgb565::Rgb565::from_rgb888_components(r, g, b).to_rgb565_be();
+            [x, y, 0xff]
+        };
+        let pixel = |buf: &[u8], x: u32, y: u32| buf[(y * 40 + x) as usize * 3..][..3].to_vec();
+
+        let red = generate_image(Pattern::Color([255, 0, 0]), 40, 20, ByteOrder::BigEndian);
+        assert_eq!(red.len(), 40 * 20 * 3);
+        assert!(red.chunks(3).all(|p| p == encoded([255, 0, 0])));
+
+        let bars = generate_image(Pattern::Bars, 40, 20, ByteOrder::BigEndian);
+        assert_eq!(pixel(&bars, 0, 0), encoded([255; 3]));
+        assert_eq!(pixel(&bars, 5, 19), encoded([255, 255, 0]));
+        assert_eq!(pixel(&bars, 39, 0), encoded([0; 3]));
+
+        let checkers = generate_image(Pattern::Checkerboard, 40, 20, ByteOrder::BigEndian);
+        assert_eq!(pixel(&checkers, 0, 0), encoded([255; 3]));
+        assert_eq!(pixel(&checkers, 8, 0), encoded([0; 3]));
+        assert_eq!(pixel(&checkers, 8, 8), encoded([255; 3]));
+
+        let ramps = generate_image(Pattern::Gradient, 40, 20, ByteOrder::BigEndian);
+        assert_eq!(pixel(&ramps, 0, 0), encoded([0; 3]));
+        assert_eq!(pixel(&ramps, 39, 0), encoded([255, 0, 0]));
+        assert_eq!(pixel(&ramps, 39, 19), encoded([255; 3]));
+        assert_eq!("gradient".parse(), Ok(Pattern::Gradient));
+        assert!("plaid".parse::<Pattern>().is_err());
+    }
+
+    #[test]
+    fn text_is_centered_on_the_background() {
+        let options = TextOptions {
+            size: None,
+            color: [255, 255, 255],
+            background: [0, 0, 255],
+            byte_order: ByteOrder::BigEndian,
+        };
+        let buf = render_text("BRB", 110, 110, options);
+        assert_eq!(buf.len(), 110 * 110 * 3);
+        let [wx, wy] = rgb565::Rgb565::from_rgb888_components(255, 255, 255).to_rgb565_be();
+        let [bx, by] = rgb565::Rgb565::from_rgb888_components(0, 0, 255).to_rgb565_be();
+        let columns = |buf: &[u8]| {
+            let mut columns =
+                (0..110).filter(|x| (0..110).any(|y| buf[(y * 110 + x) * 3..][..2] == [wx, wy]));
+            (columns.next(), columns.next_back())
+        };
+
+        // fitted text spans most of the width with even margins
+        let (left, right) = columns(&buf);
+        let (left, right) = (left.unwrap(), right.unwrap());
+        assert!(left < 15 && right > 95, "text spans {left}..={right}");
+        assert!(left.abs_diff(109 - right) <= 2);
+        assert_eq!(buf[..2], [bx, by]);
+
+        // an explicit size is respected, and longer lines are clipped rather than shrunk
+        let small = render_text(
+            "BRB",
+            110,
+            110,
+            TextOptions {
+                size: Some(12.),
+                ..options
+            },
+        );
+        let (left, right) = columns(&small);
+        assert!(right.unwrap() - left.unwrap() < 30);
+        let long = "much too long to fit";
+        let clipped = render_text(
+            long,
+            110,
+            110,
+            TextOptions {
+                size: Some(40.),
+                ..options
+            },
+        );
+        assert_eq!(columns(&clipped), (Some(0), Some(109)));
+    }
+
+    #[test]
+    fn crop_parsing() {
+        let crop: Crop = "10, 20, 300, 200".parse().unwrap();
+        assert_eq!(
+            (crop.x, crop.y, crop.width, crop.height),
+            (10, 20, 300, 200)
+        );
+        assert!("10,20,300".parse::<Crop>().is_err());
+        assert!("10,20,0,200".parse::<Crop>().is_err());
+        assert!("-1,20,30,20".parse::<Crop>().is_err());
+    }
+
+    #[test]
+    fn short_frame_delays_are_clamped() {
+        let frame = |ms| {
+            image::Frame::from_parts(
+                ImageBuffer::from_pixel(8, 8, image::Rgba([255, 0, 0, 255])),
+                0,
+                0,
+                image::Delay::from_numer_denom_ms(ms, 1),
+            )
+        };
+        let frames = Frames::new(Box::new([0, 1, 5, 9].into_iter().map(|ms| Ok(frame(ms)))));
+        let buf = encode_gif(frames, [0; 3], true, 8, 8, GifOptions::default()).unwrap();
+
+        let mut options = gif::DecodeOptions::new();
+        options.set_color_output(gif::ColorOutput::RGBA);
+        let mut decoder = options.read_info(buf.as_slice()).unwrap();
+        let mut count = 0;
+        while let Some(frame) = decoder.read_next_frame().unwrap() {
+            assert!(frame.delay >= MIN_FRAME_DELAY);
+            count += 1;
+        }
+        assert_eq!(count, 4);
+    }
+
+    #[test]
+    fn downsampling_keeps_the_animation_duration() {
+        let frames = || {
+            Frames::new(Box::new((0..12u8).map(|i| {
+                Ok(image::Frame::from_parts(
+                    ImageBuffer::from_fn(16, 16, |x, y| {
+                        image::Rgba([x as u8 * 16, y as u8 * 16, i * 20, 255])
+                    }),
+                    0,
+                    0,
+                    image::Delay::from_numer_denom_ms(50, 1),
+                ))
+            })))
+        };
+        let decode = |buf: Vec<u8>| {
+            let mut decoder = gif::DecodeOptions::new().read_info(buf.as_slice()).unwrap();
+            let mut delays = Vec::new();
+            while let Some(frame) = decoder.read_next_frame().unwrap() {
+                delays.push(frame.delay);
+            }
+            delays
+        };
+
+        let full =
+            decode(encode_gif(frames(), [0; 3], true, 16, 16, GifOptions::default()).unwrap());
+        assert_eq!((full.len(), full.iter().sum::<u16>()), (12, 60));
+
+        // 12 frames in steps of 3
+        let options = GifOptions {
+            max_frames: Some(4),
+            ..Default::default()
+        };
+        let limited = decode(encode_gif(frames(), [0; 3], true, 16, 16, options).unwrap());
+        assert_eq!(limited, [15; 4]);
+
+        // too small to fit anything but a single frame
+        let options = GifOptions {
+            max_size: Some(1),
+            ..Default::default()
+        };
+        let shrunk = decode(encode_gif(frames(), [0; 3], true, 16, 16, options).unwrap());
+        assert_eq!(shrunk, [60]);
+    }
+
+    #[test]
+    fn loop_count_and_user_input_are_written() {
+        let frames = || {
+            Frames::new(Box::new((0..2).map(|_| {
+                Ok(image::Frame::new(ImageBuffer::from_pixel(
+                    8,
+                    8,
+                    image::Rgba([0, 255, 0, 255]),
+                )))
+            })))
+        };
+        let decode = |buf: Vec<u8>| {
+            let mut decoder = gif::DecodeOptions::new().read_info(buf.as_slice()).unwrap();
+            let mut user_input = Vec::new();
+            while let Some(frame) = decoder.read_next_frame().unwrap() {
+                user_input.push(frame.needs_user_input);
+            }
+            (decoder.repeat(), user_input)
+        };
+
+        let forever = encode_gif(frames(), [0; 3], true, 8, 8, GifOptions::default()).unwrap();
+        assert_eq!(decode(forever), (gif::Repeat::Infinite, vec![true, true]));
+
+        let options = GifOptions {
+            loop_count: Some(3),
+            user_input: false,
+            ..Default::default()
+        };
+        let thrice = encode_gif(frames(), [0; 3], true, 8, 8, options).unwrap();
+        assert_eq!(decode(thrice), (gif::Repeat::Finite(2), vec![false, false]));
+
+        let options = GifOptions {
+            loop_count: Some(1),
+            ..Default::default()
+        };
+        let once = encode_gif(frames(), [0; 3], true, 8, 8, options).unwrap();
+        assert_eq!(decode(once).0, gif::Repeat::Finite(0));
+    }
+
+    #[test]
+    fn gif_output_ignores_thread_count() {
+        let frame = |i: u32| {
+            image::Frame::from_parts(
+                ImageBuffer::from_fn(48, 32, |x, y| {
+                    image::Rgba([(x * 5 + i * 20) as u8, (y * 7) as u8, (i * 40) as u8, 255])
+                }),
+                0,
+                0,
+                image::Delay::from_numer_denom_ms(100, 1),
+            )
+        };
+        let encode = |threads| {
+            let pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()
+                .unwrap();
+            pool.install(|| {
+                let frames = Frames::new(Box::new((0..6).map(|i| Ok(frame(i)))));
+                encode_gif(frames, [0; 3], false, 24, 24, GifOptions::default()).unwrap()
+            })
+        };
+        let serial = encode(1);
+        assert_eq!(serial, encode(2));
+        assert_eq!(serial, encode(4));
+    }
+
+    #[test]
+    fn still_png_is_not_animated() {
+        let path = std::env::temp_dir().join(format!("zoom-sync-still-{}.png", std::process::id()));
+        gradient().save(&path).unwrap();
+        let result = decode_animation(&path);
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(
+            result,
+            Err(ImageProcessingError::NotAnimated("png"))
+        ));
+    }
+
+    #[test]
+    fn cache_prunes_least_recently_used_entries() {
+        let dir = std::env::temp_dir().join(format!("zoom-sync-cache-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let cache = MediaCache { dir: dir.clone() };
+        let epoch = SystemTime::UNIX_EPOCH;
+        for (name, age) in [("old", 3), ("used", 1), ("new", 2)] {
+            let path = dir.join(format!("{name}.bin"));
+            fs::write(&path, [0; 10]).unwrap();
+            let file = fs::File::options().write(true).open(&path).unwrap();
+            file.set_modified(epoch + std::time::Duration::from_secs(100 - age))
+                .unwrap();
+        }
+
+        cache.prune(20).unwrap();
+        let mut left: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        left.sort();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(left, ["new.bin", "used.bin"]);
+    }
+
+    #[test]
+    fn hex_colors() {
+        assert_eq!(parse_hex_color("#ff8000"), Ok([0xff, 0x80, 0x00]));
+        assert_eq!(parse_hex_color("fff"), Ok([0xff; 3]));
+        assert_eq!(parse_hex_color("#0a0"), Ok([0x00, 0xaa, 0x00]));
+        assert!(parse_hex_color("#ffff").is_err());
+        assert!(parse_hex_color("+fffff").is_err());
+        assert!(parse_hex_color("#gggggg").is_err());
+    }
+}
@@ -0,0 +1,118 @@
+//! Progress reporting for long running stages (decoding, encoding, uploading)
+
+use std::io::{stdout, IsTerminal, Write};
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+/// How progress is reported on stdout
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ProgressFormat {
+    /// Human readable lines, updated in place on a terminal
+    #[default]
+    Human,
+    /// Newline delimited json events
+    Json,
+    /// No progress output at all
+    Quiet,
+}
+
+impl FromStr for ProgressFormat {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "human" => Ok(Self::Human),
+            "json" => Ok(Self::Json),
+            _ => Err(format!(
+                "unknown progress format: {s}. Available: human, json"
+            )),
+        }
+    }
+}
+
+static FORMAT: OnceLock<ProgressFormat> = OnceLock::new();
+
+/// Set the progress format for the rest of the process. Only the first call has any effect.
+pub fn set_format(format: ProgressFormat) {
+    let _ = FORMAT.set(format);
+}
+
+fn format() -> ProgressFormat {
+    FORMAT.get().copied().unwrap_or_default()
+}
+
+/// Whether human progress can be redrawn in place. Redirected output gets a line per tenth of
+/// progress instead, since carriage returns make logs unreadable.
+fn in_place() -> bool {
+    static TERMINAL: OnceLock<bool> = OnceLock::new();
+    *TERMINAL.get_or_init(|| stdout().is_terminal())
+}
+
+/// Whether `done` is the first step to reach the next tenth of `total`
+fn is_tenth(done: usize, total: usize) -> bool {
+    done >= total || done * 10 / total != done.saturating_sub(1) * 10 / total
+}
+
+/// Start a stage without countable steps. `message` is only shown to humans.
+pub fn start(stage: &str, message: &str) {
+    match format() {
+        ProgressFormat::Human => {
+            print!("{message} ... ");
+            stdout().flush().unwrap();
+        },
+        ProgressFormat::Json => {
+            println!(
+                "{}",
+                serde_json::json!({ "stage": stage, "status": "started" })
+            )
+        },
+        ProgressFormat::Quiet => {},
+    }
+}
+
+/// Report `done` out of `total` steps of a stage. `message` is only shown to humans.
+pub fn update(stage: &str, message: &str, done: usize, total: usize) {
+    match format() {
+        ProgressFormat::Human if in_place() => {
+            let width = total.to_string().len();
+            print!("\r{message} ({done:width$}/{total}) ... ");
+            stdout().flush().unwrap();
+        },
+        ProgressFormat::Human => {
+            if is_tenth(done, total) {
+                println!("{message} ({done}/{total})");
+            }
+        },
+        ProgressFormat::Json => println!(
+            "{}",
+            serde_json::json!({ "stage": stage, "done": done, "total": total })
+        ),
+        ProgressFormat::Quiet => {},
+    }
+}
+
+/// Finish a stage successfully
+pub fn finish(stage: &str) {
+    match format() {
+        ProgressFormat::Human => println!("done"),
+        ProgressFormat::Json => {
+            println!(
+                "{}",
+                serde_json::json!({ "stage": stage, "status": "finished" })
+            )
+        },
+        ProgressFormat::Quiet => {},
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redirected_progress_reports_tenths() {
+        let steps: Vec<_> = (1..=25).filter(|&done| is_tenth(done, 25)).collect();
+        assert_eq!(steps, [3, 5, 8, 10, 13, 15, 18, 20, 23, 25]);
+        assert!(is_tenth(3, 3) && is_tenth(1, 3) && !is_tenth(0, 3));
+        assert!(is_tenth(0, 0));
+    }
+}
@@ -1,7 +1,12 @@
 use std::error::Error;
+use std::time::Duration;
 
 use bpaf::Bpaf;
-use zoom_sync_core::Board;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use zoom_sync_core::{Board, HasScreen, ScreenGroup, ScreenPosition};
+
+use crate::config::Config;
 
 /// Screen position ID (string-based for board independence)
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -18,20 +23,151 @@ impl std::str::FromStr for ScreenPositionId {
 #[derive(Clone, Debug, PartialEq, Eq, Bpaf)]
 pub enum ScreenArgs {
     Screen(
-        /// Reset and move the screen to a specific position.
-        /// [cpu|gpu|download|time|weather|meletrix|zoom65|image|gif|battery]
+        /// Reset and move the screen to a specific position, see --list for the board's positions
         #[bpaf(short('s'), long("screen"), argument("POSITION"))]
         ScreenPositionId,
     ),
+    List {
+        /// List the screen positions of the connected board
+        #[bpaf(long("list"))]
+        #[allow(dead_code)]
+        list: (),
+    },
     /// Move the screen up
     Up,
     /// Move the screen down
     Down,
     /// Switch the screen offset
     Switch,
+    /// Move to the next screen position, wrapping around
+    Next,
+    /// Move to the previous screen position, wrapping around
+    Prev,
+    /// Move to the home (logo) screen position
+    Home,
+    Cycle {
+        /// Walk through every screen position in a loop until interrupted
+        #[bpaf(long("cycle"))]
+        #[allow(dead_code)]
+        cycle: (),
+        /// Delay between each position
+        #[bpaf(
+            long,
+            argument("DURATION"),
+            fallback(Duration::from_secs(2).into()),
+            display_fallback
+        )]
+        delay: humantime::Duration,
+    },
+}
+
+/// Relative screen movement, resolved against the board's positions and the current screen
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ScreenStep {
+    Next,
+    Prev,
+    Home,
+}
+
+/// Home position of a board: the first logo screen, or the first position otherwise
+pub fn home_screen(positions: &'static [ScreenPosition]) -> Option<&'static ScreenPosition> {
+    positions
+        .iter()
+        .find(|p| p.group == ScreenGroup::Logo)
+        .or(positions.first())
+}
+
+/// Resolve a step from the `current` screen id to a position id, wrapping around at either end.
+/// Unknown or missing current screens (like reactive mode) resolve to home.
+pub fn step_screen(
+    positions: &'static [ScreenPosition],
+    current: Option<&str>,
+    step: ScreenStep,
+) -> Option<&'static str> {
+    let home = home_screen(positions)?;
+    let index = positions.iter().position(|p| Some(p.id) == current);
+    let len = positions.len();
+    let target = match (step, index) {
+        (ScreenStep::Home, _) | (_, None) => home,
+        (ScreenStep::Next, Some(i)) => &positions[(i + 1) % len],
+        (ScreenStep::Prev, Some(i)) => &positions[(i + len - 1) % len],
+    };
+    Some(target.id)
+}
+
+/// One line per position with its id, name and group, in the order the board lists them
+pub fn format_positions(positions: &[ScreenPosition]) -> String {
+    positions
+        .iter()
+        .map(|p| {
+            let group = match p.group {
+                ScreenGroup::System => "system",
+                ScreenGroup::Time => "time",
+                ScreenGroup::Logo => "logo",
+                ScreenGroup::Battery => "battery",
+                ScreenGroup::Custom(name) => name,
+            };
+            format!("{}: {} ({group})", p.id, p.display_name)
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Record `id` as the board's current screen, and as its initial screen with `save`
+fn record_screen(config: &mut Config, board: &str, id: &str, save: bool) {
+    config.set_current_screen(board, id);
+    if save {
+        config.set_initial_screen(board, id);
+    }
+}
+
+/// Remember the screen just set in the config, so the next step starts from it
+fn remember_screen(board: &str, id: &str, save: bool) {
+    let result = Config::load_or_create().and_then(|mut config| {
+        record_screen(&mut config, board, id, save);
+        config.save()
+    });
+    if let Err(e) = result {
+        warn!("failed to save current screen: {e}");
+    }
+}
+
+/// Move the screen relative to the current one, remembering the new screen as the initial one
+/// with `save`. Boards without positional addressing use their own up/down navigation instead.
+fn step(
+    board: &str,
+    screen: &mut dyn HasScreen,
+    step: ScreenStep,
+    save: bool,
+) -> Result<(), Box<dyn Error>> {
+    let positions = screen.screen_positions();
+    if positions.is_empty() {
+        match step {
+            ScreenStep::Next => screen.screen_down()?,
+            ScreenStep::Prev => screen.screen_up()?,
+            ScreenStep::Home => screen.reset_screen()?,
+        }
+        return Ok(());
+    }
+    let current = Config::load_or_create()
+        .map(|config| config.current_screen(board))
+        .ok();
+    let id =
+        step_screen(positions, current.as_deref(), step).ok_or("board has no screen positions")?;
+    screen.set_screen(id)?;
+    remember_screen(board, id, save);
+    info!("set screen to {id}");
+    Ok(())
 }
 
-pub fn apply_screen(args: &ScreenArgs, board: &mut dyn Board) -> Result<(), Box<dyn Error>> {
+/// Apply a screen command, remembering the new screen in the config with `save`
+pub async fn apply_screen(
+    args: &ScreenArgs,
+    board: &mut dyn Board,
+    save: bool,
+) -> Result<(), Box<dyn Error>> {
+    let name = board.info().cli_name;
     let screen = board
         .as_screen()
         .ok_or("board does not support screen control")?;
@@ -48,10 +184,116 @@ pub fn apply_screen(args: &ScreenArgs, board: &mut dyn Board) -> Result<(), Box<
                 )
             })?;
             screen.set_screen(pos.id)?;
+            remember_screen(name, pos.id, save);
+        },
+        ScreenArgs::List { .. } => {
+            let positions = screen.screen_positions();
+            if positions.is_empty() {
+                return Err("board has no screen positions, use --up and --down instead".into());
+            }
+            println!("{}", format_positions(positions));
         },
+        ScreenArgs::Next => step(name, screen, ScreenStep::Next, save)?,
+        ScreenArgs::Prev => step(name, screen, ScreenStep::Prev, save)?,
+        ScreenArgs::Home => step(name, screen, ScreenStep::Home, save)?,
         ScreenArgs::Up => screen.screen_up()?,
         ScreenArgs::Down => screen.screen_down()?,
         ScreenArgs::Switch => screen.screen_switch()?,
+        ScreenArgs::Cycle { delay, .. } => {
+            let positions = screen.screen_positions();
+            if positions.is_empty() {
+                return Err("board has no screen positions to cycle through".into());
+            }
+            info!(
+                "cycling through {} screen positions every {delay}, press ctrl-c to stop",
+                positions.len()
+            );
+            loop {
+                let mut shown = false;
+                for pos in positions {
+                    // positions like an empty image slot can be refused, move on to the next
+                    if let Err(e) = screen.set_screen(pos.id) {
+                        warn!("skipping screen {}: {e}", pos.id);
+                        continue;
+                    }
+                    shown = true;
+                    info!("set screen to {}", pos.id);
+                    tokio::time::sleep(**delay).await;
+                }
+                if !shown {
+                    return Err("board refused every screen position".into());
+                }
+            }
+        },
     };
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static POSITIONS: &[ScreenPosition] = &[
+        ScreenPosition {
+            id: "cpu",
+            display_name: "CPU",
+            group: ScreenGroup::System,
+        },
+        ScreenPosition {
+            id: "time",
+            display_name: "Time",
+            group: ScreenGroup::Time,
+        },
+        ScreenPosition {
+            id: "meletrix",
+            display_name: "Meletrix",
+            group: ScreenGroup::Logo,
+        },
+    ];
+
+    #[test]
+    fn step_wraps_around() {
+        let step = |current, step| step_screen(POSITIONS, Some(current), step);
+        assert_eq!(step("cpu", ScreenStep::Next), Some("time"));
+        assert_eq!(step("meletrix", ScreenStep::Next), Some("cpu"));
+        assert_eq!(step("cpu", ScreenStep::Prev), Some("meletrix"));
+        assert_eq!(step("time", ScreenStep::Home), Some("meletrix"));
+    }
+
+    #[test]
+    fn steps_start_from_the_last_one() {
+        let mut config = Config::default();
+        config.set_initial_screen("test", "cpu");
+        for expected in ["time", "meletrix"] {
+            let current = config.current_screen("test");
+            let id = step_screen(POSITIONS, Some(&current), ScreenStep::Next).unwrap();
+            assert_eq!(id, expected);
+            record_screen(&mut config, "test", id, false);
+        }
+        // only saving changes the screen shown on connect
+        assert_eq!(config.general_for("test").initial_screen, "cpu");
+        record_screen(&mut config, "test", "time", true);
+        assert_eq!(config.general_for("test").initial_screen, "time");
+    }
+
+    #[test]
+    fn positions_are_listed_in_order() {
+        assert_eq!(
+            format_positions(POSITIONS),
+            "cpu: CPU (system)\ntime: Time (time)\nmeletrix: Meletrix (logo)"
+        );
+    }
+
+    #[test]
+    fn step_from_unknown_goes_home() {
+        assert_eq!(
+            step_screen(POSITIONS, Some("reactive"), ScreenStep::Next),
+            Some("meletrix")
+        );
+        assert_eq!(
+            step_screen(POSITIONS, None, ScreenStep::Prev),
+            Some("meletrix")
+        );
+        assert_eq!(step_screen(&[], None, ScreenStep::Next), None);
+    }
+}
@@ -0,0 +1,57 @@
+use std::error::Error;
+
+use bpaf::Bpaf;
+use log::info;
+use zoom_sync_core::Board;
+
+use crate::Color;
+
+/// Theme options:
+#[derive(Clone, Debug, PartialEq, Eq, Bpaf)]
+pub enum ThemeArgs {
+    List {
+        /// List the theme presets supported by the board
+        #[bpaf(long("list"))]
+        #[allow(dead_code)]
+        list: (),
+    },
+    Color(
+        /// Pick the theme closest to a hex color
+        #[bpaf(long("color"), argument("HEX"))]
+        Color,
+    ),
+    Set(
+        /// Theme preset id to apply, see --list for valid ids
+        #[bpaf(positional("ID"))]
+        u8,
+    ),
+}
+
+pub fn apply_theme(args: &ThemeArgs, board: &mut dyn Board) -> Result<(), Box<dyn Error>> {
+    let theme = board
+        .as_theme()
+        .ok_or("board does not support theme customization")?;
+
+    match args {
+        ThemeArgs::List { .. } => {
+            for (id, name) in theme.available_themes() {
+                println!("{id}: {name}");
+            }
+        },
+        ThemeArgs::Set(id) => {
+            let themes = theme.available_themes();
+            let (_, name) = themes.iter().find(|(t, _)| t == id).ok_or_else(|| {
+                let valid: Vec<_> = themes.iter().map(|(t, n)| format!("{t} ({n})")).collect();
+                format!("invalid theme id '{id}'. Valid: {}", valid.join(", "))
+            })?;
+            theme.set_theme(*id)?;
+            info!("set theme to {name}");
+        },
+        ThemeArgs::Color(color) => {
+            theme.set_theme_color(color.0)?;
+            info!("set theme closest to {color}");
+        },
+    }
+
+    Ok(())
+}
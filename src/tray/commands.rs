@@ -1,9 +1,14 @@
 //! Command and state types for tray-daemon communication
 
+use tokio::sync::oneshot;
+
 use crate::config::Config;
+use crate::info::SystemInfo;
+use crate::ipc::Response;
+use crate::weather::WeatherData;
 
 /// Commands sent from tray menu to the daemon
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub enum TrayCommand {
     /// Set screen to specific position (by ID) and save as default
     SetScreen(&'static str),
@@ -15,10 +20,20 @@ pub enum TrayCommand {
     Toggle12HrTime,
     /// Toggle fahrenheit/celsius
     ToggleFahrenheit,
-    /// Upload pre-encoded image data
-    UploadImage(Vec<u8>),
-    /// Upload pre-encoded GIF data
-    UploadGif(Vec<u8>),
+    /// Upload pre-encoded image data, reading it back afterwards with `verify`. `upload_retries`
+    /// overrides the configured retries for this upload.
+    UploadImage {
+        data: Vec<u8>,
+        verify: bool,
+        upload_retries: Option<u8>,
+    },
+    /// Upload pre-encoded GIF data, reading it back afterwards with `verify`. `upload_retries`
+    /// overrides the configured retries for this upload.
+    UploadGif {
+        data: Vec<u8>,
+        verify: bool,
+        upload_retries: Option<u8>,
+    },
     /// Clear uploaded image
     ClearImage,
     /// Clear uploaded GIF
@@ -29,6 +44,8 @@ pub enum TrayCommand {
     ReloadConfig,
     /// Quit the application
     Quit,
+    /// Command forwarded from `zoom-sync set`, answered with its outcome once handled
+    Forwarded(Box<TrayCommand>, oneshot::Sender<Response>),
 }
 
 /// Connection status for keyboard
@@ -38,6 +55,8 @@ pub enum ConnectionStatus {
     Disconnected,
     Connected,
     Reconnecting,
+    /// Several boards are connected and auto detection won't guess between them
+    MultipleBoards,
 }
 
 impl ConnectionStatus {
@@ -46,16 +65,91 @@ impl ConnectionStatus {
             ConnectionStatus::Disconnected => "Disconnected",
             ConnectionStatus::Connected => "Connected",
             ConnectionStatus::Reconnecting => "Reconnecting...",
+            ConnectionStatus::MultipleBoards => "Multiple boards found, pick one with --board",
         }
     }
 }
 
+/// Consecutive failures of a periodic update, so the user is told once when it keeps failing
+/// and once when it recovers instead of on every tick
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FailureStreak(u32);
+
+/// Change worth notifying about after recording an update
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreakChange {
+    /// Just failed [`FailureStreak::THRESHOLD`] times in a row
+    Failing,
+    /// Succeeded after having been reported as failing
+    Recovered,
+}
+
+impl FailureStreak {
+    /// Failures in a row before notifying
+    pub const THRESHOLD: u32 = 3;
+
+    /// Record the outcome of an update
+    pub fn record(&mut self, ok: bool) -> Option<StreakChange> {
+        if ok {
+            let notified = std::mem::take(&mut self.0) >= Self::THRESHOLD;
+            return notified.then_some(StreakChange::Recovered);
+        }
+        self.0 = self.0.saturating_add(1);
+        (self.0 == Self::THRESHOLD).then_some(StreakChange::Failing)
+    }
+}
+
 /// State shared from daemon to tray for UI updates
 #[derive(Debug, Clone, Default)]
 pub struct TrayState {
     pub connection: ConnectionStatus,
     pub current_screen: Option<String>,
     pub config: Config,
-    /// Whether reactive mode is currently active (Linux only)
+    /// Whether reactive mode is currently active
     pub reactive_active: bool,
+    /// Brightness last set on the board in percent, unknown until set
+    pub brightness: Option<u8>,
+    /// Battery level read on the last system refresh, if the board reports one
+    pub battery: Option<u8>,
+    /// Last system info sent to the board
+    pub last_system: Option<SystemInfo>,
+    /// Last weather report sent to the board
+    pub last_weather: Option<WeatherData>,
+    /// Failed weather updates in a row
+    pub weather_failures: FailureStreak,
+    /// Failed system info updates in a row
+    pub system_failures: FailureStreak,
+}
+
+impl TrayState {
+    /// Tray tooltip with the connection status and the last synced values
+    pub fn tooltip(&self) -> String {
+        let mut text = String::from("zoom-sync");
+        if self.connection != ConnectionStatus::Connected {
+            text.push_str(&format!(": {}", self.connection.as_str()));
+            return text;
+        }
+        let unit = if self.config.general.fahrenheit {
+            "°F"
+        } else {
+            "°C"
+        };
+        if let Some(system) = self.last_system.filter(|_| self.config.system_info.enabled) {
+            text.push_str(&format!(
+                "\nCPU {}{unit}, GPU {}{unit}",
+                system.cpu_temp, system.gpu_temp
+            ));
+        }
+        if let Some(weather) = self
+            .last_weather
+            .as_ref()
+            .filter(|_| self.config.weather.enabled)
+        {
+            text.push_str(&format!(
+                "\nWeather {:.0}{unit} ({:.0}{unit} to {:.0}{unit})",
+                weather.current, weather.min, weather.max
+            ));
+        }
+        text
+    }
 }
@@ -4,7 +4,7 @@ use muda::{
     accelerator::Accelerator, AboutMetadata, CheckMenuItem, Menu, MenuEvent, MenuItem,
     PredefinedMenuItem, Submenu,
 };
-use zoom_sync_core::Board;
+use zoom_sync_core::{Board, ScreenPosition};
 
 use crate::tray::ZOOM_ICON;
 
@@ -14,18 +14,8 @@ use super::commands::{TrayCommand, TrayState};
 pub mod ids {
     pub const STATUS: &str = "status";
 
-    // Screen positions (radio group)
-    pub const SCREEN_CPU: &str = "screen_cpu";
-    pub const SCREEN_GPU: &str = "screen_gpu";
-    pub const SCREEN_DOWNLOAD: &str = "screen_download";
-    pub const SCREEN_TIME: &str = "screen_time";
-    pub const SCREEN_WEATHER: &str = "screen_weather";
-    pub const SCREEN_MELETRIX: &str = "screen_meletrix";
-    pub const SCREEN_ZOOM65: &str = "screen_zoom65";
-    pub const SCREEN_IMAGE: &str = "screen_image";
-    pub const SCREEN_GIF: &str = "screen_gif";
-    pub const SCREEN_BATTERY: &str = "screen_battery";
-    #[cfg(target_os = "linux")]
+    // Screen positions (radio group), built from the board's positions as `screen:<id>`
+    pub const SCREEN_PREFIX: &str = "screen:";
     pub const SCREEN_REACTIVE: &str = "screen_reactive";
 
     // Settings toggles
@@ -59,18 +49,8 @@ pub struct MenuItems {
     // Track which feature menus are currently shown
     screen_menu_visible: std::cell::Cell<bool>,
     media_menu_visible: std::cell::Cell<bool>,
-    // Screen position items
-    pub screen_cpu: CheckMenuItem,
-    pub screen_gpu: CheckMenuItem,
-    pub screen_download: CheckMenuItem,
-    pub screen_time: CheckMenuItem,
-    pub screen_weather: CheckMenuItem,
-    pub screen_meletrix: CheckMenuItem,
-    pub screen_zoom65: CheckMenuItem,
-    pub screen_image: CheckMenuItem,
-    pub screen_gif: CheckMenuItem,
-    pub screen_battery: CheckMenuItem,
-    #[cfg(target_os = "linux")]
+    // Screen position items for the connected board, and the position ids they select
+    screen_items: std::cell::RefCell<Vec<(CheckMenuItem, &'static str)>>,
     pub screen_reactive: CheckMenuItem,
     // Settings toggles
     pub toggle_weather: CheckMenuItem,
@@ -83,23 +63,29 @@ impl MenuItems {
     /// Update menu state based on board features
     pub fn update_from_state(&self, state: &TrayState, board: &mut Option<Box<dyn Board>>) {
         // Update connection status and check features
-        let (status_text, has_screen, has_media) = match board.as_mut() {
+        let (status_text, screen_positions, has_media) = match board.as_mut() {
             Some(b) => {
-                let has_screen = b.as_screen().is_some();
+                let screen_positions = b.as_screen().map(|s| s.screen_positions());
                 let has_media = b.as_image().is_some() || b.as_gif().is_some();
+                let battery = state
+                    .battery
+                    .map(|percent| format!(" ({percent}% battery)"))
+                    .unwrap_or_default();
                 (
-                    format!("{} Connected", b.info().name),
-                    has_screen,
+                    format!("{} Connected{battery}", b.info().name),
+                    screen_positions,
                     has_media,
                 )
             },
-            None => ("Disconnected".to_string(), false, false),
+            None => (state.connection.as_str().to_string(), None, false),
         };
         self.status.set_text(status_text);
 
-        // Add/remove screen menu based on feature
+        // Add/remove screen menu based on feature, with only the board's positions
+        let has_screen = screen_positions.is_some();
         let screen_visible = self.screen_menu_visible.get();
-        if has_screen && !screen_visible {
+        if let (Some(positions), false) = (screen_positions, screen_visible) {
+            self.rebuild_screen_submenu(positions);
             self.menu.insert(&self.screen_submenu, 2).unwrap();
             self.screen_menu_visible.set(true);
         } else if !has_screen && screen_visible {
@@ -123,31 +109,17 @@ impl MenuItems {
 
         // Update screen checkmarks to show current default
         // When reactive is active, uncheck all other screen positions
-        #[cfg(target_os = "linux")]
         let reactive_active = state.reactive_active;
-        #[cfg(not(target_os = "linux"))]
-        let reactive_active = false;
-
-        let default_screen = &state.config.general.initial_screen;
-
-        let screen_items: &[(&CheckMenuItem, &str)] = &[
-            (&self.screen_cpu, "cpu"),
-            (&self.screen_gpu, "gpu"),
-            (&self.screen_download, "download"),
-            (&self.screen_time, "time"),
-            (&self.screen_weather, "weather"),
-            (&self.screen_meletrix, "meletrix"),
-            (&self.screen_zoom65, "zoom65"),
-            (&self.screen_image, "image"),
-            (&self.screen_gif, "gif"),
-            (&self.screen_battery, "battery"),
-        ];
-
-        for (item, id) in screen_items {
-            item.set_checked(!reactive_active && *default_screen == *id);
+
+        let default_screen = match board.as_ref() {
+            Some(b) => state.config.general_for(b.info().cli_name).initial_screen,
+            None => state.config.general.initial_screen.clone(),
+        };
+
+        for (item, id) in self.screen_items.borrow().iter() {
+            item.set_checked(!reactive_active && default_screen == *id);
         }
 
-        #[cfg(target_os = "linux")]
         self.screen_reactive.set_checked(reactive_active);
 
         // Update toggles from config
@@ -160,6 +132,59 @@ impl MenuItems {
         self.toggle_fahrenheit
             .set_checked(state.config.general.fahrenheit);
     }
+
+    /// Check the selected screen item and uncheck the others right away, like a radio group.
+    /// The real state is restored by `update_from_state` if setting the screen fails.
+    pub fn select_screen(&self, id: &str) {
+        for (item, item_id) in self.screen_items.borrow().iter() {
+            item.set_checked(*item_id == id);
+        }
+        self.screen_reactive.set_checked(id == "reactive");
+    }
+
+    /// Find the screen position selected by a menu item id
+    fn screen_position(&self, menu_id: &str) -> Option<&'static str> {
+        let id = menu_id.strip_prefix(ids::SCREEN_PREFIX)?;
+        self.screen_items
+            .borrow()
+            .iter()
+            .find(|(_, item_id)| *item_id == id)
+            .map(|(_, item_id)| *item_id)
+    }
+
+    /// Rebuild the screen submenu from the board's positions, separated by group. Any group works,
+    /// including board specific custom ones, since only changes between groups matter here.
+    fn rebuild_screen_submenu(&self, positions: &'static [ScreenPosition]) {
+        while self.screen_submenu.remove_at(0).is_some() {}
+
+        let mut items = self.screen_items.borrow_mut();
+        items.clear();
+        let mut group = None;
+        for position in positions {
+            if group.is_some_and(|g| g != position.group) {
+                self.screen_submenu
+                    .append(&PredefinedMenuItem::separator())
+                    .unwrap();
+            }
+            group = Some(position.group);
+            let item = CheckMenuItem::with_id(
+                format!("{}{}", ids::SCREEN_PREFIX, position.id),
+                position.display_name,
+                true,
+                false,
+                None::<Accelerator>,
+            );
+            self.screen_submenu.append(&item).unwrap();
+            items.push((item, position.id));
+        }
+
+        if group.is_some() {
+            self.screen_submenu
+                .append(&PredefinedMenuItem::separator())
+                .unwrap();
+        }
+        self.screen_submenu.append(&self.screen_reactive).unwrap();
+    }
 }
 
 /// Build the tray menu and return items for updates (menu is inside MenuItems)
@@ -176,113 +201,15 @@ pub fn build_menu(state: &TrayState) -> MenuItems {
     menu.append(&status).unwrap();
     menu.append(&PredefinedMenuItem::separator()).unwrap();
 
-    // Screen position submenu
+    // Screen position submenu, filled from the board's positions when connected
     let screen_submenu = Submenu::new("Set Screen", true);
-
-    let screen_cpu = CheckMenuItem::with_id(
-        ids::SCREEN_CPU,
-        "CPU Temp",
-        true,
-        false,
-        None::<Accelerator>,
-    );
-    let screen_gpu = CheckMenuItem::with_id(
-        ids::SCREEN_GPU,
-        "GPU Temp",
-        true,
-        false,
-        None::<Accelerator>,
-    );
-    let screen_download = CheckMenuItem::with_id(
-        ids::SCREEN_DOWNLOAD,
-        "Download",
-        true,
-        false,
-        None::<Accelerator>,
-    );
-    screen_submenu.append(&screen_cpu).unwrap();
-    screen_submenu.append(&screen_gpu).unwrap();
-    screen_submenu.append(&screen_download).unwrap();
-    screen_submenu
-        .append(&PredefinedMenuItem::separator())
-        .unwrap();
-
-    let screen_time =
-        CheckMenuItem::with_id(ids::SCREEN_TIME, "Time", true, false, None::<Accelerator>);
-    let screen_weather = CheckMenuItem::with_id(
-        ids::SCREEN_WEATHER,
-        "Weather",
-        true,
-        false,
-        None::<Accelerator>,
-    );
-    screen_submenu.append(&screen_time).unwrap();
-    screen_submenu.append(&screen_weather).unwrap();
-    screen_submenu
-        .append(&PredefinedMenuItem::separator())
-        .unwrap();
-
-    let screen_meletrix = CheckMenuItem::with_id(
-        ids::SCREEN_MELETRIX,
-        "Meletrix Logo",
-        true,
-        false,
-        None::<Accelerator>,
-    );
-    let screen_zoom65 = CheckMenuItem::with_id(
-        ids::SCREEN_ZOOM65,
-        "Zoom65 Logo",
-        true,
-        false,
-        None::<Accelerator>,
-    );
-    let screen_image = CheckMenuItem::with_id(
-        ids::SCREEN_IMAGE,
-        "Custom Image",
-        true,
-        false,
-        None::<Accelerator>,
-    );
-    let screen_gif = CheckMenuItem::with_id(
-        ids::SCREEN_GIF,
-        "Custom GIF",
+    let screen_reactive = CheckMenuItem::with_id(
+        ids::SCREEN_REACTIVE,
+        "Reactive",
         true,
         false,
         None::<Accelerator>,
     );
-    screen_submenu.append(&screen_meletrix).unwrap();
-    screen_submenu.append(&screen_zoom65).unwrap();
-    screen_submenu.append(&screen_image).unwrap();
-    screen_submenu.append(&screen_gif).unwrap();
-    screen_submenu
-        .append(&PredefinedMenuItem::separator())
-        .unwrap();
-
-    let screen_battery = CheckMenuItem::with_id(
-        ids::SCREEN_BATTERY,
-        "Battery",
-        true,
-        false,
-        None::<Accelerator>,
-    );
-    screen_submenu.append(&screen_battery).unwrap();
-
-    // Reactive mode (Linux only)
-    #[cfg(target_os = "linux")]
-    let screen_reactive = {
-        screen_submenu
-            .append(&PredefinedMenuItem::separator())
-            .unwrap();
-        let item = CheckMenuItem::with_id(
-            ids::SCREEN_REACTIVE,
-            "Reactive",
-            true,
-            false,
-            None::<Accelerator>,
-        );
-        screen_submenu.append(&item).unwrap();
-        item
-    };
 
     // Don't append screen_submenu yet - added dynamically when connected
 
@@ -423,17 +350,7 @@ pub fn build_menu(state: &TrayState) -> MenuItems {
         media_submenu,
         screen_menu_visible: std::cell::Cell::new(false),
         media_menu_visible: std::cell::Cell::new(false),
-        screen_cpu,
-        screen_gpu,
-        screen_download,
-        screen_time,
-        screen_weather,
-        screen_meletrix,
-        screen_zoom65,
-        screen_image,
-        screen_gif,
-        screen_battery,
-        #[cfg(target_os = "linux")]
+        screen_items: std::cell::RefCell::new(Vec::new()),
         screen_reactive,
         toggle_weather,
         toggle_system,
@@ -455,21 +372,12 @@ pub enum MenuAction {
 }
 
 /// Handle a menu event and return the appropriate action
-pub fn handle_menu_event(event: MenuEvent) -> MenuAction {
+pub fn handle_menu_event(event: MenuEvent, menu_items: &MenuItems) -> MenuAction {
     let id = event.id().0.as_str();
+    if let Some(position) = menu_items.screen_position(id) {
+        return MenuAction::Command(TrayCommand::SetScreen(position));
+    }
     match id {
-        // Screen positions
-        ids::SCREEN_CPU => MenuAction::Command(TrayCommand::SetScreen("cpu")),
-        ids::SCREEN_GPU => MenuAction::Command(TrayCommand::SetScreen("gpu")),
-        ids::SCREEN_DOWNLOAD => MenuAction::Command(TrayCommand::SetScreen("download")),
-        ids::SCREEN_TIME => MenuAction::Command(TrayCommand::SetScreen("time")),
-        ids::SCREEN_WEATHER => MenuAction::Command(TrayCommand::SetScreen("weather")),
-        ids::SCREEN_MELETRIX => MenuAction::Command(TrayCommand::SetScreen("meletrix")),
-        ids::SCREEN_ZOOM65 => MenuAction::Command(TrayCommand::SetScreen("zoom65")),
-        ids::SCREEN_IMAGE => MenuAction::Command(TrayCommand::SetScreen("image")),
-        ids::SCREEN_GIF => MenuAction::Command(TrayCommand::SetScreen("gif")),
-        ids::SCREEN_BATTERY => MenuAction::Command(TrayCommand::SetScreen("battery")),
-        #[cfg(target_os = "linux")]
         ids::SCREEN_REACTIVE => MenuAction::Command(TrayCommand::SetScreen("reactive")),
 
         // Toggles
@@ -1,71 +1,135 @@
 //! System tray interface for zoom-sync
 
 use std::error::Error;
-use std::io::{stdout, Seek, Write};
+use std::ffi::CString;
 use std::time::Duration;
 
-use chrono::DurationRound;
+use chrono::{DurationRound, Timelike};
 use either::Either;
-use futures::future::OptionFuture;
-use image::codecs::gif::GifDecoder;
-use image::codecs::png::PngDecoder;
-use image::codecs::webp::WebPDecoder;
-use image::AnimationDecoder;
+use futures::future::{LocalBoxFuture, OptionFuture};
+use log::{debug, error, info, warn};
 use muda::MenuEvent;
 use notify_rust::{Notification, NotificationHandle};
 use tokio_stream::StreamExt;
 use tray_icon::TrayIconBuilder;
-use zoom_sync_core::Board;
+use zoom_sync_core::{Board, BoardError, ScreenPosition};
 
-use crate::config::Config;
+use crate::config::{Config, WeatherConfig};
 use crate::detection::BoardKind;
 use crate::info::{apply_system, CpuTemp, GpuTemp};
-use crate::media::{encode_gif, encode_image};
-use crate::weather::apply_weather;
+use crate::ipc::IpcCommand;
+use crate::media::{
+    decode_animation, encode_gif, encode_image, parse_hex_color, set_encode_threads, GifOptions,
+    ImageOptions, ImageProcessingError, MediaCache, MediaKind,
+};
+use crate::progress;
+use crate::screen::{home_screen, step_screen};
+use crate::weather::{apply_weather, fetch_weather, send_weather, WeatherArgs, WeatherData};
 
 mod commands;
 mod menu;
+mod reactive;
+mod systemd;
+mod watch;
 
 pub use commands::{ConnectionStatus, TrayCommand, TrayState};
+use commands::{FailureStreak, StreakChange};
 
 /// Icon bytes embedded at compile time
 const ZOOM_ICON: &[u8] = include_bytes!("../../assets/zoom_icon.png");
 
-/// Errors that can occur during image/gif processing
-#[derive(Debug, thiserror::Error)]
-pub enum ImageProcessingError {
-    #[error("failed to open file: {0}")]
-    OpenFile(#[from] std::io::Error),
-    #[error("failed to decode image: {0}")]
-    DecodeImage(#[from] image::ImageError),
-    #[error("failed to encode image")]
-    EncodeImage,
-    #[error("failed to encode gif")]
-    EncodeGif,
-    #[error("png is not animated")]
-    NotAnimatedPng,
-    #[error("webp is not animated")]
-    NotAnimatedWebp,
-    #[error("unsupported animation format")]
-    UnsupportedFormat,
-}
+/// Longest delay between reconnect attempts, unless the configured retry interval is longer
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
 
 /// Run the tray application
-pub fn run_tray_app(board_kind: BoardKind) -> Result<(), Box<dyn Error>> {
+pub fn run_tray_app(
+    board_kind: BoardKind,
+    device_path: Option<CString>,
+    report_id: Option<u8>,
+) -> Result<(), Box<dyn Error>> {
     let rt = tokio::runtime::Builder::new_current_thread()
         .enable_all()
         .build()?;
-    rt.block_on(async_tray_app(board_kind))
+    rt.block_on(async_tray_app(board_kind, device_path, report_id))
+}
+
+/// Connect, sync time, weather and system info once according to the config, then return.
+/// Fails if the keyboard isn't found or any enabled sync fails.
+pub fn run_once(
+    board_kind: BoardKind,
+    device_path: Option<CString>,
+    report_id: Option<u8>,
+) -> Result<(), Box<dyn Error>> {
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+    rt.block_on(async {
+        let mut config = Config::load_or_create()?;
+        let mut board = crate::open_board(&board_kind, device_path.as_deref(), report_id)?;
+        info!("connected to {}", board.info().name);
+
+        let mut failed = false;
+        if let Err(e) = crate::apply_time(board.as_mut(), config.general.use_12hr_time) {
+            error!("time sync failed: {e}");
+            failed = true;
+        }
+
+        if config.weather.enabled {
+            let mut weather_args = build_weather_args(&config);
+            match apply_weather(
+                board.as_mut(),
+                &mut weather_args,
+                &config.weather.provider,
+                &config.weather.geolocation,
+                config.general.fahrenheit,
+                Some(config.weather.max_cache_age),
+                config.weather.request_timeout,
+            )
+            .await
+            {
+                Ok(_) => save_city_coords(&mut config, &weather_args),
+                Err(e) => {
+                    error!("weather update failed: {e}");
+                    failed = true;
+                },
+            }
+        }
+
+        if config.system_info.enabled {
+            let mut cpu = Either::Left(CpuTemp::new(&config.system_info.cpu_source));
+            let mut gpu = Either::Left(GpuTemp::new(config.system_info.gpu_device));
+            if let Err(e) = apply_system(
+                board.as_mut(),
+                config.general.fahrenheit,
+                &mut cpu,
+                &mut gpu,
+                None,
+            ) {
+                error!("system update failed: {e}");
+                failed = true;
+            }
+        }
+
+        if failed {
+            return Err("some updates failed".into());
+        }
+        Ok(())
+    })
 }
 
-async fn async_tray_app(board_kind: BoardKind) -> Result<(), Box<dyn Error>> {
+async fn async_tray_app(
+    board_kind: BoardKind,
+    device_path: Option<CString>,
+    report_id: Option<u8>,
+) -> Result<(), Box<dyn Error>> {
     // Initialize GTK (required for libappindicator on Linux)
     #[cfg(target_os = "linux")]
     gtk::init()?;
 
     // Load or create config
     let config = Config::load_or_create()?;
-    println!("config loaded from {:?}", Config::path());
+    info!("config loaded from {:?}", Config::path());
+    set_encode_threads(config.media.encode_threads);
 
     // Build initial state
     let mut state = TrayState {
@@ -73,6 +137,12 @@ async fn async_tray_app(board_kind: BoardKind) -> Result<(), Box<dyn Error>> {
         current_screen: None,
         config,
         reactive_active: false,
+        brightness: None,
+        battery: None,
+        last_system: None,
+        last_weather: None,
+        weather_failures: FailureStreak::default(),
+        system_failures: FailureStreak::default(),
     };
 
     // Load icon and build menu
@@ -80,11 +150,14 @@ async fn async_tray_app(board_kind: BoardKind) -> Result<(), Box<dyn Error>> {
     let menu_items = menu::build_menu(&state);
 
     // Create tray icon
-    let _tray = TrayIconBuilder::new()
+    let tray = TrayIconBuilder::new()
         .with_menu(Box::new(menu_items.menu.clone()))
-        .with_tooltip("zoom-sync")
+        .with_tooltip(state.tooltip())
         .with_icon(icon)
         .build()?;
+    let mut tooltip = state.tooltip();
+    let mut connection = state.connection;
+    systemd::status(connection.as_str());
 
     // Process GTK events to render tray icon before entering main loop
     #[cfg(target_os = "linux")]
@@ -98,6 +171,24 @@ async fn async_tray_app(board_kind: BoardKind) -> Result<(), Box<dyn Error>> {
     // Internal command channel
     let (cmd_tx, mut cmd_rx) = tokio::sync::mpsc::unbounded_channel::<TrayCommand>();
 
+    // Pick up edits to the config file without a manual reload. The tray's own saves trigger a
+    // reload too, which just reads back what it wrote.
+    let _config_watcher = Config::path().and_then(|path| {
+        watch::watch_config(&path, cmd_tx.clone())
+            .inspect_err(|e| warn!("not watching {path:?} for changes: {e}"))
+            .ok()
+    });
+    #[cfg(unix)]
+    reload_on_hangup(cmd_tx.clone());
+
+    // Commands forwarded from `zoom-sync set`
+    let (ipc_tx, mut ipc_rx) = tokio::sync::mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        if let Err(e) = crate::ipc::serve(ipc_tx).await {
+            error!("ipc socket failed, set commands can't be forwarded: {e}");
+        }
+    });
+
     // UI polling interval
     let mut ui_interval = tokio::time::interval(Duration::from_millis(200));
     ui_interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
@@ -111,6 +202,9 @@ async fn async_tray_app(board_kind: BoardKind) -> Result<(), Box<dyn Error>> {
 
     // Weather args
     let mut weather_args = build_weather_args(&state.config);
+    // Report being fetched in the background, so a slow request doesn't hold up the menu or
+    // reactive mode
+    let mut weather_fetch: Option<WeatherFetch> = None;
 
     // Refresh intervals (skip missed ticks instead of bursting)
     let mut weather_interval = tokio::time::interval(state.config.refresh.weather);
@@ -119,22 +213,32 @@ async fn async_tray_app(board_kind: BoardKind) -> Result<(), Box<dyn Error>> {
     system_interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
     let mut retry_interval = tokio::time::interval(state.config.refresh.retry);
     retry_interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    // Reconnect attempts back off exponentially while no keyboard is found
+    let mut retry_delay = state.config.refresh.retry;
+    // Whether the current run of failed connects was logged, tracked apart from the status
+    // since the tray also starts out disconnected
+    let mut connect_failure_logged = false;
 
-    // Time sync interval (only used in 12hr mode, syncs on the hour)
+    // Hourly interval for 12hr time sync and the brightness schedule, started on connect
     let mut time_interval: Option<tokio::time::Interval> = None;
 
-    // Reactive mode (Linux only)
-    #[cfg(target_os = "linux")]
-    let mut reactive_stream: Option<
-        std::pin::Pin<Box<tokio_stream::Timeout<evdev::EventStream>>>,
-    > = None;
-    #[cfg(not(target_os = "linux"))]
-    let mut reactive_stream: Option<futures::stream::Empty<()>> = None;
+    // Reactive mode
+    let mut reactive_stream: Option<reactive::ReactiveStream> = None;
 
     let mut is_reactive_running = false;
 
+    // Exit requests from outside the menu
+    let shutdown = shutdown_signal();
+    tokio::pin!(shutdown);
+
     loop {
         tokio::select! {
+            _ = &mut shutdown => {
+                info!("shutting down");
+                reset_screen_on_exit(&mut board, &state.config);
+                return Ok(());
+            }
+
             // UI polling: GTK events + menu events
             _ = ui_interval.tick() => {
                 // Process GTK events (required for libappindicator on Linux)
@@ -145,8 +249,11 @@ async fn async_tray_app(board_kind: BoardKind) -> Result<(), Box<dyn Error>> {
 
                 // Process menu events
                 while let Ok(event) = menu_rx.try_recv() {
-                    match menu::handle_menu_event(event) {
+                    match menu::handle_menu_event(event, &menu_items) {
                         menu::MenuAction::Command(cmd) => {
+                            if let TrayCommand::SetScreen(id) = cmd {
+                                menu_items.select_screen(id);
+                            }
                             let _ = cmd_tx.send(cmd);
                         }
                         menu::MenuAction::PickImage => {
@@ -154,8 +261,16 @@ async fn async_tray_app(board_kind: BoardKind) -> Result<(), Box<dyn Error>> {
                             let screen_size = board.as_ref().and_then(|b| b.as_screen_size());
                             if let Some((width, height)) = screen_size {
                                 let tx = cmd_tx.clone();
-                                let bg = parse_hex_color(&state.config.media.background_color).unwrap_or([0, 0, 0]);
+                                let bg = background_color(&state.config);
                                 let nearest = state.config.media.use_nearest_neighbor;
+                                let cache = MediaCache::open().filter(|_| state.config.media.use_cache);
+                                let options = ImageOptions {
+                                    dither: state.config.media.dither,
+                                    resize: state.config.media.resize_mode,
+                                    crop: None,
+                                    color: state.config.media.color_correction(),
+                                    byte_order: board.as_mut().and_then(|b| b.as_image()).map(|i| i.byte_order()).unwrap_or_default(),
+                                };
                                 tokio::spawn(async move {
                                     if let Some(handle) = rfd::AsyncFileDialog::new()
                                         .add_filter("Images", &["png", "jpg", "jpeg", "bmp", "webp"])
@@ -166,25 +281,30 @@ async fn async_tray_app(board_kind: BoardKind) -> Result<(), Box<dyn Error>> {
                                         let path = handle.path().to_path_buf();
                                         // Encode in blocking thread
                                         let result = tokio::task::spawn_blocking(move || -> Result<Vec<u8>, ImageProcessingError> {
-                                            let image = image::open(&path)?;
-                                            encode_image(image, bg, nearest, width, height)
-                                                .ok_or(ImageProcessingError::EncodeImage)
+                                            let encode = || {
+                                                let image = image::open(&path)?;
+                                                encode_image(image, bg, nearest, width, height, options)
+                                            };
+                                            match cache {
+                                                Some(cache) => cache.get_or_encode(&path, MediaKind::Image(options), bg, nearest, width, height, encode),
+                                                None => encode(),
+                                            }
                                         }).await;
                                         match result {
-                                            Ok(Ok(data)) => { let _ = tx.send(TrayCommand::UploadImage(data)); }
+                                            Ok(Ok(data)) => { let _ = tx.send(TrayCommand::UploadImage { data, verify: false, upload_retries: None }); }
                                             Ok(Err(e)) => {
-                                                eprintln!("{e}");
+                                                error!("{e}");
                                                 notify_error(&e.to_string());
                                             }
                                             Err(e) => {
-                                                eprintln!("image encoding task panicked: {e}");
+                                                error!("image encoding task panicked: {e}");
                                                 notify_error(&format!("Image encoding failed: {e}"));
                                             }
                                         }
                                     }
                                 });
                             } else {
-                                eprintln!("no board connected for image upload");
+                                error!("no board connected for image upload");
                             }
                         }
                         menu::MenuAction::PickGif => {
@@ -192,8 +312,19 @@ async fn async_tray_app(board_kind: BoardKind) -> Result<(), Box<dyn Error>> {
                             let screen_size = board.as_ref().and_then(|b| b.as_screen_size());
                             if let Some((width, height)) = screen_size {
                                 let tx = cmd_tx.clone();
-                                let bg = parse_hex_color(&state.config.media.background_color).unwrap_or([0, 0, 0]);
+                                let bg = background_color(&state.config);
                                 let nearest = state.config.media.use_nearest_neighbor;
+                                let cache = MediaCache::open().filter(|_| state.config.media.use_cache);
+                                // Always fit animations to the board, there's no one to retry with flags
+                                let options = GifOptions {
+                                    max_frames: None,
+                                    max_size: board.as_mut().and_then(|b| b.as_gif()).and_then(|g| g.max_size()),
+                                    delay: None,
+                                    resize: state.config.media.resize_mode,
+                                    crop: None,
+                                    color: state.config.media.color_correction(),
+                                    ..Default::default()
+                                };
                                 tokio::spawn(async move {
                                     if let Some(handle) = rfd::AsyncFileDialog::new()
                                         .add_filter("Animations", &["gif", "webp", "png", "apng"])
@@ -204,33 +335,48 @@ async fn async_tray_app(board_kind: BoardKind) -> Result<(), Box<dyn Error>> {
                                         let path = handle.path().to_path_buf();
                                         // Decode and encode in blocking thread
                                         let result = tokio::task::spawn_blocking(move || {
-                                            decode_and_encode_gif(&path, bg, nearest, width, height)
+                                            let encode = || decode_and_encode_gif(&path, bg, nearest, width, height, options);
+                                            match cache {
+                                                Some(cache) => cache.get_or_encode(&path, MediaKind::Gif(options), bg, nearest, width, height, encode),
+                                                None => encode(),
+                                            }
                                         }).await;
                                         match result {
-                                            Ok(Ok(data)) => { let _ = tx.send(TrayCommand::UploadGif(data)); }
+                                            Ok(Ok(data)) => { let _ = tx.send(TrayCommand::UploadGif { data, verify: false, upload_retries: None }); }
                                             Ok(Err(e)) => {
-                                                eprintln!("{e}");
+                                                error!("{e}");
                                                 notify_error(&e.to_string());
                                             }
                                             Err(e) => {
-                                                eprintln!("gif encoding task panicked: {e}");
+                                                error!("gif encoding task panicked: {e}");
                                                 notify_error(&format!("GIF encoding failed: {e}"));
                                             }
                                         }
                                     }
                                 });
                             } else {
-                                eprintln!("no board connected for gif upload");
+                                error!("no board connected for gif upload");
                             }
                         }
                         menu::MenuAction::None => {}
                     }
                 }
+
+                // Only touch the tray icon when the shown values changed
+                let text = state.tooltip();
+                if text != tooltip {
+                    let _ = tray.set_tooltip(Some(&text));
+                    tooltip = text;
+                }
+                if state.connection != connection {
+                    connection = state.connection;
+                    systemd::status(connection.as_str());
+                }
             }
 
             // Process commands
             Some(cmd) = cmd_rx.recv() => {
-                match handle_command(
+                let result = handle_command(
                     cmd,
                     &mut board,
                     &mut state,
@@ -238,142 +384,198 @@ async fn async_tray_app(board_kind: BoardKind) -> Result<(), Box<dyn Error>> {
                     &mut cpu,
                     &mut gpu,
                     &mut weather_args,
-                ).await {
-                    CommandResult::Quit => return Ok(()),
+                ).await.unwrap_or_else(|e| {
+                    error!("{e}");
+                    CommandResult::Continue
+                });
+                match result {
+                    CommandResult::Quit => {
+                        reset_screen_on_exit(&mut board, &state.config);
+                        return Ok(());
+                    }
                     CommandResult::Continue => {}
-                    #[cfg(target_os = "linux")]
                     CommandResult::ToggleReactive => {
                         if state.reactive_active {
                             // Disable reactive mode
                             reactive_stream = None;
                             is_reactive_running = false;
                             state.reactive_active = false;
-                            // Restore to default screen
-                            state.config.general.initial_screen = "meletrix".into();
+                            // Restore the board's home screen. It's saved even if the board
+                            // refuses it right now, so reactive mode stays off on reconnect.
+                            state.current_screen = None;
+                            if let Some(b) = board.as_mut() {
+                                let name = b.info().cli_name;
+                                if let Some(screen) = b.as_screen() {
+                                    if let Some(home) = home_screen(screen.screen_positions()) {
+                                        state.config.set_initial_screen(name, home.id);
+                                        if screen.set_screen(home.id).is_ok() {
+                                            state.current_screen = Some(home.id.to_string());
+                                        }
+                                    }
+                                }
+                            }
                             let _ = state.config.save();
-                            println!("reactive mode disabled");
+                            info!("reactive mode disabled");
                         } else if let Some(ref mut b) = board {
                             // Enable reactive mode
                             if let Some(screen) = b.as_screen() {
                                 let _ = screen.set_screen("image");
                             }
-                            let board_name = b.info().name.to_lowercase();
-                            let search = format!("{board_name} keyboard");
-                            reactive_stream = evdev::enumerate().find_map(|(_, device)| {
-                                let name = device.name()?.to_string();
-                                let name_lower = name.to_lowercase();
-                                // Must contain board name + "keyboard" suffix
-                                if name_lower.contains(&search) {
-                                    device
-                                        .into_event_stream()
-                                        .map(|s| Box::pin(s.timeout(Duration::from_millis(500))))
-                                        .ok()
-                                } else {
-                                    None
-                                }
-                            });
+                            reactive_stream = reactive::open(b.info(), state.config.general_for(b.info().cli_name).reactive_device.as_deref());
                             if reactive_stream.is_some() {
                                 state.reactive_active = true;
-                                state.config.general.initial_screen = "reactive".into();
+                                state.config.set_initial_screen(b.info().cli_name, "reactive");
                                 let _ = state.config.save();
-                                println!("reactive mode enabled");
+                                info!("reactive mode enabled");
                             } else {
-                                eprintln!("reactive mode: no input device found (are you in the 'input' group?)");
+                                warn!("reactive mode: no input device found (are you in the 'input' group?)");
                             }
                         }
                         menu_items.update_from_state(&state, &mut board);
                     }
+                    CommandResult::StopReactive => {
+                        reactive_stream = None;
+                        is_reactive_running = false;
+                    }
+                    CommandResult::RefreshWeather => {
+                        // A fetch already running sends with the current settings anyway
+                        if weather_fetch.is_none() {
+                            weather_fetch = Some(start_weather_fetch(&weather_args, &state.config.weather));
+                        }
+                    }
+                    CommandResult::ResetIntervals => {
+                        // The next updates come a full new period from now, rather than all at once
+                        let refresh = &state.config.refresh;
+                        weather_interval = create_delayed_interval(refresh.weather);
+                        system_interval = create_delayed_interval(refresh.system);
+                        retry_delay = refresh.retry;
+                        retry_interval = tokio::time::interval(retry_delay);
+                        retry_interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+                        info!("refresh intervals updated");
+                    }
+                }
+            }
+
+            // Commands forwarded from `zoom-sync set`, queued like menu commands and answered once
+            // handled
+            Some((cmd, reply)) = ipc_rx.recv() => {
+                match tray_command(cmd, &mut board, state.current_screen.as_deref()) {
+                    Ok(cmd) => { let _ = cmd_tx.send(TrayCommand::Forwarded(Box::new(cmd), reply)); }
+                    Err(e) => { let _ = reply.send(Err(e)); }
                 }
             }
 
             // Try to connect if disconnected
             _ = retry_interval.tick(), if board.is_none() => {
-                match board_kind.as_board() {
+                match crate::open_board(&board_kind, device_path.as_deref(), report_id) {
                     Ok(mut b) => {
-                        println!("connected to {}", b.info().name);
+                        info!("connected to {}", b.info().name);
+                        b.set_upload_retries(state.config.media.upload_retries);
                         state.connection = ConnectionStatus::Connected;
 
                         // Initialize temperature monitors
                         if state.config.system_info.enabled {
-                            cpu = Some(Either::Left(CpuTemp::new(&state.config.system_info.cpu_source)));
-                            gpu = Some(Either::Left(GpuTemp::new(state.config.system_info.gpu_device)));
+                            cpu = Some(Either::Left(cpu_monitor(&state.config)));
+                            gpu = Some(Either::Left(gpu_monitor(&state.config)));
                         }
 
-                        // Initialize reactive mode if configured (Linux only)
-                        #[cfg(target_os = "linux")]
-                        if state.config.general.initial_screen == "reactive" {
-                            println!("initializing reactive mode");
+                        let general = state.config.general_for(b.info().cli_name);
+                        let initial = b.as_screen().map(|screen| {
+                            initial_screen(&general.initial_screen, screen.screen_positions()).to_string()
+                        });
+
+                        // Drop any reactive stream from the previous connection, so the state
+                        // only reflects what's opened below
+                        reactive_stream = None;
+                        is_reactive_running = false;
+                        state.reactive_active = false;
+
+                        // Initialize reactive mode if configured
+                        if initial.as_deref() == Some("reactive") {
+                            info!("initializing reactive mode");
                             if let Some(screen) = b.as_screen() {
                                 let _ = screen.set_screen("image");
                             }
-                            let board_name = b.info().name.to_lowercase();
-                            reactive_stream = evdev::enumerate().find_map(|(_, device)| {
-                                let name = device.name()?.to_string();
-                                let name_lower = name.to_lowercase();
-                                // Must contain board name + "keyboard" suffix
-                                if name_lower.contains(&format!("{board_name} keyboard")) {
-                                    device
-                                        .into_event_stream()
-                                        .map(|s| Box::pin(s.timeout(Duration::from_millis(500))))
-                                        .ok()
-                                } else {
-                                    None
-                                }
-                            });
+                            reactive_stream = reactive::open(b.info(), general.reactive_device.as_deref());
                             if reactive_stream.is_some() {
                                 state.reactive_active = true;
-                                println!("reactive mode enabled");
+                                info!("reactive mode enabled");
                             } else {
-                                eprintln!("reactive mode: no input device found (are you in the 'input' group?)");
+                                warn!("reactive mode: no input device found (are you in the 'input' group?)");
                             }
                         }
 
                         // Set initial screen if configured (skip for reactive mode)
-                        #[cfg(target_os = "linux")]
-                        let skip_initial = state.config.general.initial_screen == "reactive";
-                        #[cfg(not(target_os = "linux"))]
-                        let skip_initial = false;
-
-                        if !skip_initial {
-                            if let Some(screen) = b.as_screen() {
-                                let initial = &state.config.general.initial_screen;
-                                if screen.set_screen(initial).is_ok() {
-                                    state.current_screen = Some(initial.clone());
-                                }
+                        else if let (Some(initial), Some(screen)) = (initial, b.as_screen()) {
+                            if screen.set_screen(&initial).is_ok() {
+                                state.current_screen = Some(initial);
                             }
                         }
 
                         // Sync time immediately
                         if let Err(e) = crate::apply_time(b.as_mut(), state.config.general.use_12hr_time) {
-                            eprintln!("time sync failed: {e}");
+                            error!("time sync failed: {e}");
                         }
+                        systemd::ready();
 
-                        // Set up time interval for 12hr mode
-                        if state.config.general.use_12hr_time {
-                            time_interval = Some(create_hourly_interval());
-                        }
+                        // Set up the hourly interval, and start on the scheduled brightness.
+                        // The board may have reset its brightness while disconnected.
+                        time_interval = Some(create_hourly_interval());
+                        state.brightness = None;
+                        apply_scheduled_brightness(b.as_mut(), &mut state);
+
+                        // Reset the backoff, so a later disconnect retries right away
+                        connect_failure_logged = false;
+                        retry_delay = state.config.refresh.retry;
+                        retry_interval = tokio::time::interval(retry_delay);
+                        retry_interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
 
                         // Set board, then update menu with features
                         board = Some(b);
                         menu_items.update_from_state(&state, &mut board);
                     }
                     Err(e) => {
-                        if state.connection != ConnectionStatus::Disconnected {
-                            eprintln!("failed to connect: {e}");
-                            state.connection = ConnectionStatus::Disconnected;
+                        retry_delay = (retry_delay * 2).min(MAX_RETRY_DELAY.max(state.config.refresh.retry));
+                        retry_interval = create_delayed_interval(retry_delay);
+                        debug!("connect failed ({e}), retrying in {retry_delay:?}");
+
+                        // Show ambiguous auto detection in the status item instead of the generic state
+                        let status = match e {
+                            BoardError::MultipleBoards(_) => ConnectionStatus::MultipleBoards,
+                            _ => ConnectionStatus::Disconnected,
+                        };
+                        if state.connection != status || !connect_failure_logged {
+                            error!("failed to connect: {e}");
+                            connect_failure_logged = true;
+                            state.connection = status;
                             menu_items.update_from_state(&state, &mut board);
                         }
                     }
                 }
             }
 
-            // Weather updates (only if board connected and enabled)
-            _ = weather_interval.tick(), if board.is_some() && state.config.weather.enabled => {
-                if let Some(ref mut b) = board {
-                    match apply_weather(b.as_mut(), &mut weather_args, state.config.general.fahrenheit).await {
-                        Ok(()) => {}
+            // Weather updates (only if board connected and enabled), fetched in the background
+            _ = weather_interval.tick(), if board.is_some() && state.config.weather.enabled && weather_fetch.is_none() => {
+                weather_fetch = Some(start_weather_fetch(&weather_args, &state.config.weather));
+            }
+
+            // Send the fetched report once it arrives
+            Some((args, fetched)) = OptionFuture::from(weather_fetch.as_mut()) => {
+                weather_fetch = None;
+                adopt_coords(&mut weather_args, args);
+                if let Some(b) = board.as_mut().filter(|_| state.config.weather.enabled) {
+                    let result = fetched.and_then(|data| {
+                        send_weather(b.as_mut(), &weather_args, data, state.config.general.fahrenheit)
+                    });
+                    track_update(&mut state.weather_failures, "Weather", &result);
+                    match result {
+                        Ok(data) => {
+                            save_city_coords(&mut state.config, &weather_args);
+                            // Keep showing the last report if this update was skipped
+                            state.last_weather = data.or(state.last_weather.take());
+                        }
                         Err(e) => {
-                            eprintln!("weather update failed: {e}");
+                            error!("weather update failed: {e}");
                             // Check if board disconnected
                             if e.to_string().contains("device") {
                                 handle_disconnect(&mut board, &mut state, &menu_items);
@@ -386,50 +588,65 @@ async fn async_tray_app(board_kind: BoardKind) -> Result<(), Box<dyn Error>> {
             // System info updates (only if board connected and enabled)
             _ = system_interval.tick(), if board.is_some() && state.config.system_info.enabled => {
                 if let Some(ref mut b) = board {
-                    if let (Some(ref mut c), Some(ref g)) = (&mut cpu, &gpu) {
-                        if let Err(e) = apply_system(
+                    if let (Some(ref mut c), Some(ref mut g)) = (&mut cpu, &mut gpu) {
+                        let result = apply_system(
                             b.as_mut(),
                             state.config.general.fahrenheit,
                             c,
                             g,
                             None,
-                        ) {
-                            eprintln!("system update failed: {e}");
-                            if e.to_string().contains("device") {
-                                handle_disconnect(&mut board, &mut state, &menu_items);
+                        );
+                        track_update(&mut state.system_failures, "System info", &result);
+                        match result {
+                            Ok(info) => state.last_system = Some(info),
+                            Err(e) => {
+                                error!("system update failed: {e}");
+                                if e.to_string().contains("device") {
+                                    handle_disconnect(&mut board, &mut state, &menu_items);
+                                }
                             }
                         }
                     }
                 }
+                // Refresh the battery level in the status line, rather than on every menu update
+                if let Some(ref mut b) = board {
+                    let battery = b.as_battery().and_then(|battery| battery.battery_percent().ok().flatten());
+                    if battery != state.battery {
+                        state.battery = battery;
+                        menu_items.update_from_state(&state, &mut board);
+                    }
+                }
             }
 
-            // Time sync (12hr mode, on the hour)
+            // Time sync (12hr mode) and brightness schedule, on the hour
             Some(_) = OptionFuture::from(time_interval.as_mut().map(|i| i.tick())), if board.is_some() => {
                 if let Some(ref mut b) = board {
-                    if let Err(e) = crate::apply_time(b.as_mut(), state.config.general.use_12hr_time) {
-                        eprintln!("time sync failed: {e}");
-                        if e.to_string().contains("device") {
-                            handle_disconnect(&mut board, &mut state, &menu_items);
+                    apply_scheduled_brightness(b.as_mut(), &mut state);
+                }
+                if state.config.general.use_12hr_time {
+                    if let Some(ref mut b) = board {
+                        if let Err(e) = crate::apply_time(b.as_mut(), true) {
+                            error!("time sync failed: {e}");
+                            if e.to_string().contains("device") {
+                                handle_disconnect(&mut board, &mut state, &menu_items);
+                            }
                         }
                     }
                 }
             }
 
-            // Reactive mode keypress handling (Linux only)
+            // Reactive mode keypress handling
             Some(Some(res)) = OptionFuture::from(reactive_stream.as_mut().map(|s| s.next())), if board.is_some() => {
                 match res {
                     Ok(Err(e)) => {
-                        eprintln!("reactive stream error: {e}");
+                        error!("reactive stream error: {e}");
                         handle_disconnect(&mut board, &mut state, &menu_items);
                     }
-                    #[cfg(target_os = "linux")]
-                    Ok(Ok(ev)) if !is_reactive_running => {
-                        if matches!(ev.destructure(), evdev::EventSummary::Key(_, _, _)) {
-                            is_reactive_running = true;
-                            if let Some(ref mut b) = board {
-                                if let Some(screen) = b.as_screen() {
-                                    let _ = screen.screen_switch();
-                                }
+                    Ok(Ok(())) if !is_reactive_running => {
+                        is_reactive_running = true;
+                        if let Some(ref mut b) = board {
+                            if let Some(screen) = b.as_screen() {
+                                let _ = screen.screen_switch();
                             }
                         }
                     }
@@ -450,12 +667,89 @@ async fn async_tray_app(board_kind: BoardKind) -> Result<(), Box<dyn Error>> {
     }
 }
 
+/// Check the configured initial screen against the board's positions, plus `reactive`.
+/// Unknown screens fall back to the first logo position (or the first position) with a warning.
+fn initial_screen<'a>(configured: &'a str, positions: &'static [ScreenPosition]) -> &'a str {
+    if configured == "reactive" || positions.iter().any(|p| p.id == configured) {
+        return configured;
+    }
+    let fallback = home_screen(positions).map_or(configured, |p| p.id);
+    warn!("initial screen '{configured}' is not available on this board, using '{fallback}'");
+    fallback
+}
+
+/// Translate a forwarded command into a tray command, rejecting anything the connected board
+/// can't handle so the client gets an error. Screen steps are resolved from `current_screen`.
+fn tray_command(
+    cmd: IpcCommand,
+    board: &mut Option<Box<dyn Board>>,
+    current_screen: Option<&str>,
+) -> Result<TrayCommand, String> {
+    let board = board.as_mut().ok_or("no board connected")?;
+    Ok(match cmd {
+        IpcCommand::SetScreen(id) => {
+            let screen = board
+                .as_screen()
+                .ok_or("board does not support screen control")?;
+            let positions = screen.screen_positions();
+            let pos = positions.iter().find(|p| p.id == id).ok_or_else(|| {
+                let valid: Vec<_> = positions.iter().map(|p| p.id).collect();
+                format!(
+                    "invalid screen position '{id}'. Valid: {}",
+                    valid.join(", ")
+                )
+            })?;
+            TrayCommand::SetScreen(pos.id)
+        },
+        IpcCommand::StepScreen(step) => {
+            let screen = board
+                .as_screen()
+                .ok_or("board does not support screen control")?;
+            let id = step_screen(screen.screen_positions(), current_screen, step)
+                .ok_or("board has no screen positions to step through")?;
+            TrayCommand::SetScreen(id)
+        },
+        IpcCommand::UploadImage {
+            data,
+            verify,
+            upload_retries,
+        } => {
+            board.as_image().ok_or("board does not support images")?;
+            TrayCommand::UploadImage {
+                data,
+                verify,
+                upload_retries,
+            }
+        },
+        IpcCommand::UploadGif {
+            data,
+            verify,
+            upload_retries,
+        } => {
+            board.as_gif().ok_or("board does not support gifs")?;
+            TrayCommand::UploadGif {
+                data,
+                verify,
+                upload_retries,
+            }
+        },
+        IpcCommand::ClearImage => TrayCommand::ClearImage,
+        IpcCommand::ClearGif => TrayCommand::ClearGif,
+        IpcCommand::ClearAllMedia => TrayCommand::ClearAllMedia,
+    })
+}
+
 enum CommandResult {
     Continue,
     Quit,
-    /// Toggle reactive mode on/off (Linux only)
-    #[cfg(target_os = "linux")]
+    /// Toggle reactive mode on/off
     ToggleReactive,
+    /// Reactive mode was turned off by picking another screen, close its input stream
+    StopReactive,
+    /// Fetch and send the weather now, instead of waiting for the next update
+    RefreshWeather,
+    /// The refresh intervals changed in the config, recreate them
+    ResetIntervals,
 }
 
 async fn handle_command(
@@ -466,29 +760,60 @@ async fn handle_command(
     cpu: &mut Option<Either<CpuTemp, u8>>,
     gpu: &mut Option<Either<GpuTemp, u8>>,
     weather_args: &mut crate::weather::WeatherArgs,
-) -> CommandResult {
+) -> Result<CommandResult, String> {
     match cmd {
-        TrayCommand::Quit => return CommandResult::Quit,
+        TrayCommand::Quit => return Ok(CommandResult::Quit),
+
+        TrayCommand::Forwarded(cmd, reply) => {
+            // The board may have disconnected while the command was queued
+            let result = match board {
+                Some(_) => {
+                    Box::pin(handle_command(
+                        *cmd,
+                        board,
+                        state,
+                        menu_items,
+                        cpu,
+                        gpu,
+                        weather_args,
+                    ))
+                    .await
+                },
+                None => Err("no board connected".into()),
+            };
+            let _ = reply.send(result.as_ref().map(|_| ()).map_err(Clone::clone));
+            return result;
+        },
 
         TrayCommand::SetScreen(id) => {
-            // Handle reactive mode specially (Linux only)
-            #[cfg(target_os = "linux")]
+            // Handle reactive mode specially
             if id == "reactive" {
-                return CommandResult::ToggleReactive;
+                return Ok(CommandResult::ToggleReactive);
             }
 
             if let Some(ref mut b) = board {
+                let name = b.info().cli_name;
                 if let Some(screen) = b.as_screen() {
                     match screen.set_screen(id) {
                         Ok(()) => {
                             state.current_screen = Some(id.to_string());
                             // Also save as default
-                            state.config.general.initial_screen = id.to_string();
+                            state.config.set_initial_screen(name, id);
                             let _ = state.config.save();
+                            // Picking a regular screen leaves reactive mode
+                            let stop_reactive = std::mem::take(&mut state.reactive_active);
+                            menu_items.update_from_state(state, board);
+                            info!("set screen to {id}");
+                            if stop_reactive {
+                                info!("reactive mode disabled");
+                                return Ok(CommandResult::StopReactive);
+                            }
+                        },
+                        Err(e) => {
+                            // Restore the checkmarks changed on selection
                             menu_items.update_from_state(state, board);
-                            println!("set screen to {id}");
+                            return Err(format!("failed to set screen: {e}"));
                         },
-                        Err(e) => eprintln!("failed to set screen: {e}"),
                     }
                 }
             }
@@ -499,21 +824,17 @@ async fn handle_command(
             *weather_args = build_weather_args(&state.config);
             let _ = state.config.save();
             menu_items.update_from_state(state, board);
-            println!("weather: {}", state.config.weather.enabled);
+            info!("weather: {}", state.config.weather.enabled);
         },
         TrayCommand::ToggleSystemInfo => {
             state.config.system_info.enabled = !state.config.system_info.enabled;
             if state.config.system_info.enabled && board.is_some() {
-                *cpu = Some(Either::Left(CpuTemp::new(
-                    &state.config.system_info.cpu_source,
-                )));
-                *gpu = Some(Either::Left(GpuTemp::new(
-                    state.config.system_info.gpu_device,
-                )));
+                *cpu = Some(Either::Left(cpu_monitor(&state.config)));
+                *gpu = Some(Either::Left(gpu_monitor(&state.config)));
             }
             let _ = state.config.save();
             menu_items.update_from_state(state, board);
-            println!("system info: {}", state.config.system_info.enabled);
+            info!("system info: {}", state.config.system_info.enabled);
         },
         TrayCommand::Toggle12HrTime => {
             state.config.general.use_12hr_time = !state.config.general.use_12hr_time;
@@ -522,46 +843,48 @@ async fn handle_command(
             }
             let _ = state.config.save();
             menu_items.update_from_state(state, board);
-            println!("12hr time: {}", state.config.general.use_12hr_time);
+            info!("12hr time: {}", state.config.general.use_12hr_time);
         },
         TrayCommand::ToggleFahrenheit => {
             state.config.general.fahrenheit = !state.config.general.fahrenheit;
             let _ = state.config.save();
             menu_items.update_from_state(state, board);
-            println!("fahrenheit: {}", state.config.general.fahrenheit);
+            info!("fahrenheit: {}", state.config.general.fahrenheit);
 
-            // Immediately update displays with new temperature unit
+            // Immediately update displays with new temperature unit, the weather is refetched in
+            // the background
             if let Some(ref mut b) = board {
-                if state.config.weather.enabled {
-                    if let Err(e) =
-                        apply_weather(b.as_mut(), weather_args, state.config.general.fahrenheit)
-                            .await
-                    {
-                        eprintln!("weather update failed: {e}");
-                    }
-                }
                 if state.config.system_info.enabled {
-                    if let (Some(ref mut c), Some(ref g)) = (cpu, gpu) {
-                        if let Err(e) =
-                            apply_system(b.as_mut(), state.config.general.fahrenheit, c, g, None)
+                    if let (Some(ref mut c), Some(ref mut g)) = (cpu, gpu) {
+                        match apply_system(b.as_mut(), state.config.general.fahrenheit, c, g, None)
                         {
-                            eprintln!("system update failed: {e}");
+                            Ok(info) => state.last_system = Some(info),
+                            Err(e) => error!("system update failed: {e}"),
                         }
                     }
                 }
+                if state.config.weather.enabled {
+                    return Ok(CommandResult::RefreshWeather);
+                }
             }
         },
 
-        TrayCommand::UploadImage(encoded) => {
+        TrayCommand::UploadImage {
+            data: encoded,
+            verify,
+            upload_retries,
+        } => {
             if let Some(ref mut b) = board {
+                if let Some(retries) = upload_retries {
+                    b.set_upload_retries(retries);
+                }
                 if let Some(image_handler) = b.as_image() {
                     let len = encoded.len();
-                    let total = len / 24;
-                    let progress_width = total.to_string().len();
+                    let total = len.div_ceil(image_handler.chunk_size());
+                    let message = format!("uploading {len} bytes");
                     let mut notification = notify_progress("Image", 0.0);
                     let result = image_handler.upload_image(&encoded, &mut |i| {
-                        print!("\ruploading {len} bytes ({i:progress_width$}/{total}) ... ");
-                        stdout().flush().unwrap();
+                        progress::update("upload", &message, i, total);
                         let percent = (i as f32 * 100.0) / total as f32;
                         if let Some(ref mut n) = notification {
                             notify_update(n, "Image", percent);
@@ -571,29 +894,45 @@ async fn handle_command(
                     if let Some(n) = notification {
                         n.close();
                     }
+                    // A failed read back counts as a failed upload
+                    let result = result.map_err(Box::from).and_then(|()| {
+                        progress::finish("upload");
+                        if verify {
+                            crate::report_verify(image_handler.verify_image(&encoded))
+                        } else {
+                            Ok(())
+                        }
+                    });
+                    // Later uploads go back to the configured retries
+                    if upload_retries.is_some() {
+                        b.set_upload_retries(state.config.media.upload_retries);
+                    }
                     match result {
-                        Ok(()) => {
-                            println!("done");
-                            notify_success("Image");
-                        },
+                        Ok(()) => notify_success("Image"),
                         Err(e) => {
-                            eprintln!("failed to upload image: {e}");
                             notify_error(&format!("Failed to upload image: {e}"));
+                            return Err(format!("failed to upload image: {e}"));
                         },
                     }
                 }
             }
         },
-        TrayCommand::UploadGif(encoded) => {
+        TrayCommand::UploadGif {
+            data: encoded,
+            verify,
+            upload_retries,
+        } => {
             if let Some(ref mut b) = board {
+                if let Some(retries) = upload_retries {
+                    b.set_upload_retries(retries);
+                }
                 if let Some(gif_handler) = b.as_gif() {
                     let len = encoded.len();
-                    let total = len / 24;
-                    let progress_width = total.to_string().len();
+                    let total = len.div_ceil(gif_handler.chunk_size());
+                    let message = format!("uploading {len} bytes");
                     let mut notification = notify_progress("GIF", 0.0);
                     let result = gif_handler.upload_gif(&encoded, &mut |i| {
-                        print!("\ruploading {len} bytes ({i:progress_width$}/{total}) ... ");
-                        stdout().flush().unwrap();
+                        progress::update("upload", &message, i, total);
                         let percent = (i as f32 * 100.0) / total as f32;
                         if let Some(ref mut n) = notification {
                             notify_update(n, "GIF", percent);
@@ -603,14 +942,24 @@ async fn handle_command(
                     if let Some(n) = notification {
                         n.close();
                     }
+                    // A failed read back counts as a failed upload
+                    let result = result.map_err(Box::from).and_then(|()| {
+                        progress::finish("upload");
+                        if verify {
+                            crate::report_verify(gif_handler.verify_gif(&encoded))
+                        } else {
+                            Ok(())
+                        }
+                    });
+                    // Later uploads go back to the configured retries
+                    if upload_retries.is_some() {
+                        b.set_upload_retries(state.config.media.upload_retries);
+                    }
                     match result {
-                        Ok(()) => {
-                            println!("done");
-                            notify_success("GIF");
-                        },
+                        Ok(()) => notify_success("GIF"),
                         Err(e) => {
-                            eprintln!("failed to upload gif: {e}");
                             notify_error(&format!("Failed to upload GIF: {e}"));
+                            return Err(format!("failed to upload gif: {e}"));
                         },
                     }
                 }
@@ -619,47 +968,130 @@ async fn handle_command(
         TrayCommand::ClearImage => {
             if let Some(ref mut b) = board {
                 if let Some(image) = b.as_image() {
-                    match image.clear_image() {
-                        Ok(()) => println!("cleared image"),
-                        Err(e) => eprintln!("failed to clear image: {e}"),
-                    }
+                    image
+                        .clear_image()
+                        .map_err(|e| format!("failed to clear image: {e}"))?;
+                    info!("cleared image");
                 }
             }
         },
         TrayCommand::ClearGif => {
             if let Some(ref mut b) = board {
                 if let Some(gif) = b.as_gif() {
-                    match gif.clear_gif() {
-                        Ok(()) => println!("cleared gif"),
-                        Err(e) => eprintln!("failed to clear gif: {e}"),
-                    }
+                    gif.clear_gif()
+                        .map_err(|e| format!("failed to clear gif: {e}"))?;
+                    info!("cleared gif");
                 }
             }
         },
         TrayCommand::ClearAllMedia => {
             if let Some(ref mut b) = board {
-                if let Some(image) = b.as_image() {
-                    let _ = image.clear_image();
-                }
-                if let Some(gif) = b.as_gif() {
-                    let _ = gif.clear_gif();
-                }
-                println!("cleared all media");
+                // Try both even if one fails
+                let image = b.as_image().map_or(Ok(()), |image| image.clear_image());
+                let gif = b.as_gif().map_or(Ok(()), |gif| gif.clear_gif());
+                image
+                    .and(gif)
+                    .map_err(|e| format!("failed to clear media: {e}"))?;
+                info!("cleared all media");
             }
         },
 
         TrayCommand::ReloadConfig => {
-            if let Err(e) = state.config.reload() {
-                eprintln!("failed to reload config: {e}");
-            } else {
-                println!("config reloaded");
+            let previous = state.config.clone();
+            state
+                .config
+                .reload()
+                .map_err(|e| format!("failed to reload config: {e}"))?;
+            if state.config == previous {
+                // The watcher also sees the tray's own saves
+                debug!("config unchanged, nothing to reload");
+                return Ok(CommandResult::Continue);
+            }
+            info!("config reloaded");
+            // Rebuilding drops coordinates found by geolocation, so only do it on changes
+            if state.config.weather != previous.weather {
                 *weather_args = build_weather_args(&state.config);
             }
+            set_encode_threads(state.config.media.encode_threads);
+            if let Some(b) = board {
+                b.set_upload_retries(state.config.media.upload_retries);
+            }
             menu_items.update_from_state(state, board);
+            if state.config.refresh != previous.refresh {
+                return Ok(CommandResult::ResetIntervals);
+            }
         },
     }
 
-    CommandResult::Continue
+    Ok(CommandResult::Continue)
+}
+
+/// Resolves once the process is asked to exit with ctrl-c, or SIGTERM on unix
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut term) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => {},
+                    _ = term.recv() => {},
+                }
+            },
+            Err(e) => {
+                warn!("failed to listen for SIGTERM: {e}");
+                let _ = tokio::signal::ctrl_c().await;
+            },
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+/// Reload the config on SIGHUP, ie from `systemctl reload`
+#[cfg(unix)]
+fn reload_on_hangup(tx: tokio::sync::mpsc::UnboundedSender<TrayCommand>) {
+    use tokio::signal::unix::{signal, SignalKind};
+    let mut hangup = match signal(SignalKind::hangup()) {
+        Ok(hangup) => hangup,
+        Err(e) => {
+            warn!("failed to listen for SIGHUP: {e}");
+            return;
+        },
+    };
+    tokio::spawn(async move {
+        while hangup.recv().await.is_some() {
+            info!("received SIGHUP, reloading config");
+            if tx.send(TrayCommand::ReloadConfig).is_err() {
+                return;
+            }
+        }
+    });
+}
+
+/// Best-effort reset of the screen to the configured initial screen before exiting, so a
+/// custom image or reactive mode isn't left behind. Errors are only logged.
+fn reset_screen_on_exit(board: &mut Option<Box<dyn Board>>, config: &Config) {
+    let Some(b) = board.as_mut() else {
+        return;
+    };
+    let general = config.general_for(b.info().cli_name);
+    let Some(screen) = b.as_screen() else {
+        return;
+    };
+    let positions = screen.screen_positions();
+    let id = match initial_screen(&general.initial_screen, positions) {
+        // Reactive mode needs the running process, show the home screen instead
+        "reactive" => match home_screen(positions) {
+            Some(home) => home.id,
+            None => return,
+        },
+        id => id,
+    };
+    match screen.set_screen(id) {
+        Ok(()) => info!("reset screen to {id}"),
+        Err(e) => warn!("failed to reset screen on exit: {e}"),
+    }
 }
 
 fn handle_disconnect(
@@ -667,29 +1099,165 @@ fn handle_disconnect(
     state: &mut TrayState,
     menu_items: &menu::MenuItems,
 ) {
+    // Try to re-acquire the device in place first, so a transient usb hiccup doesn't
+    // force a full teardown and screen re-init
+    if let Some(ref mut b) = board {
+        if b.reconnect().is_ok() {
+            info!("reconnected to {}", b.info().name);
+            return;
+        }
+    }
+
     *board = None;
+    state.battery = None;
     state.connection = ConnectionStatus::Reconnecting;
     menu_items.update_from_state(state, board);
 }
 
+fn cpu_monitor(config: &Config) -> CpuTemp {
+    CpuTemp::new(&config.system_info.cpu_source).with_smoothing(config.system_info.smoothing)
+}
+
+fn gpu_monitor(config: &Config) -> GpuTemp {
+    GpuTemp::new(config.system_info.gpu_device).with_smoothing(config.system_info.smoothing)
+}
+
 fn build_weather_args(config: &Config) -> crate::weather::WeatherArgs {
     if config.weather.enabled {
-        if let (Some(lat), Some(lon)) = (config.weather.latitude, config.weather.longitude) {
-            crate::weather::WeatherArgs::Auto {
-                coords: Some(crate::weather::Coords {
-                    coords: (),
-                    lat: lat as f32,
-                    long: lon as f32,
-                }),
-            }
-        } else {
-            crate::weather::WeatherArgs::Auto { coords: None }
+        let coords = match (config.weather.latitude, config.weather.longitude) {
+            (Some(lat), Some(lon)) => crate::weather::Coords::new(lat as f32, lon as f32)
+                .inspect_err(|e| warn!("ignoring configured coordinates: {e}"))
+                .ok(),
+            _ => None,
+        };
+
+        let weather = &config.weather;
+        match (
+            weather.manual_wmo,
+            weather.manual_current,
+            weather.manual_min,
+            weather.manual_max,
+        ) {
+            (Some(wmo), Some(current), Some(min), Some(max)) => {
+                return crate::weather::WeatherArgs::Manual {
+                    weather: (),
+                    wmo,
+                    current,
+                    min,
+                    max,
+                    coords,
+                }
+            },
+            (None, None, None, None) => {},
+            _ => warn!(
+                "ignoring manual weather, manual_wmo, manual_current, manual_min and manual_max \
+                 must all be set"
+            ),
+        }
+
+        crate::weather::WeatherArgs::Auto {
+            coords,
+            city: config.weather.city.clone(),
+            ipinfo_token: config
+                .weather
+                .ipinfo_token
+                .clone()
+                .or_else(|| std::env::var("IPINFO_TOKEN").ok()),
         }
     } else {
         crate::weather::WeatherArgs::Disabled
     }
 }
 
+/// Weather report fetched in the background, with the args it used including any resolved
+/// coordinates
+type WeatherFetch =
+    LocalBoxFuture<'static, (WeatherArgs, Result<Option<WeatherData>, Box<dyn Error>>)>;
+
+/// Fetch a weather report without holding the board, see [`fetch_weather`]
+fn start_weather_fetch(args: &WeatherArgs, config: &WeatherConfig) -> WeatherFetch {
+    let mut args = args.clone();
+    let config = config.clone();
+    Box::pin(async move {
+        let fetched = fetch_weather(
+            &mut args,
+            &config.provider,
+            &config.geolocation,
+            Some(config.max_cache_age),
+            config.request_timeout,
+        )
+        .await;
+        (args, fetched)
+    })
+}
+
+/// Keep the coordinates a background fetch resolved, unless the weather settings changed while
+/// it was running
+fn adopt_coords(args: &mut WeatherArgs, fetched: WeatherArgs) {
+    if let (
+        WeatherArgs::Auto {
+            coords: coords @ None,
+            city,
+            ..
+        },
+        WeatherArgs::Auto {
+            coords: Some(resolved),
+            city: fetched_city,
+            ..
+        },
+    ) = (args, fetched)
+    {
+        if *city == fetched_city {
+            *coords = Some(resolved);
+        }
+    }
+}
+
+/// Persist coordinates geocoded from the configured city, so later runs skip geocoding
+fn save_city_coords(config: &mut Config, weather_args: &crate::weather::WeatherArgs) {
+    if config.weather.city.is_none() || config.weather.latitude.is_some() {
+        return;
+    }
+    if let crate::weather::WeatherArgs::Auto {
+        coords: Some(coords),
+        ..
+    } = weather_args
+    {
+        config.weather.latitude = Some(coords.lat as f64);
+        config.weather.longitude = Some(coords.long as f64);
+        let _ = config.save();
+    }
+}
+
+/// Create an interval whose first tick is a full period away
+fn create_delayed_interval(period: Duration) -> tokio::time::Interval {
+    let mut interval = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    interval
+}
+
+/// Set the day or night brightness from the schedule, if auto dimming is on. Boards without
+/// brightness control are skipped entirely.
+fn apply_scheduled_brightness(board: &mut dyn Board, state: &mut TrayState) {
+    let display = &state.config.display;
+    let Some(brightness) = board.as_brightness().filter(|_| display.auto_dim) else {
+        return;
+    };
+    let percent = display.scheduled_brightness(chrono::Local::now().hour());
+    if state.brightness == Some(percent) {
+        return;
+    }
+    match brightness.set_brightness(percent) {
+        Ok(()) => {
+            info!("scheduled brightness: {percent}%");
+            state.brightness = Some(percent);
+        },
+        Err(e) => {
+            error!("failed to set scheduled brightness: {e}");
+        },
+    }
+}
+
 fn create_hourly_interval() -> tokio::time::Interval {
     let now = chrono::Local::now();
     let delay = now
@@ -721,48 +1289,18 @@ fn decode_and_encode_gif(
     nearest: bool,
     width: u32,
     height: u32,
+    options: GifOptions,
 ) -> Result<Vec<u8>, ImageProcessingError> {
-    let decoder = image::ImageReader::open(path)?.with_guessed_format()?;
-
-    let frames = match decoder.format() {
-        Some(image::ImageFormat::Gif) => {
-            let mut reader = decoder.into_inner();
-            reader.seek(std::io::SeekFrom::Start(0))?;
-            GifDecoder::new(reader)?.into_frames()
-        },
-        Some(image::ImageFormat::Png) => {
-            let mut reader = decoder.into_inner();
-            reader.seek(std::io::SeekFrom::Start(0))?;
-            let png = PngDecoder::new(reader)?;
-            if !png.is_apng()? {
-                return Err(ImageProcessingError::NotAnimatedPng);
-            }
-            png.apng()?.into_frames()
-        },
-        Some(image::ImageFormat::WebP) => {
-            let mut reader = decoder.into_inner();
-            reader.seek(std::io::SeekFrom::Start(0))?;
-            let webp = WebPDecoder::new(reader)?;
-            if !webp.has_animation() {
-                return Err(ImageProcessingError::NotAnimatedWebp);
-            }
-            webp.into_frames()
-        },
-        _ => return Err(ImageProcessingError::UnsupportedFormat),
-    };
-
-    encode_gif(frames, bg, nearest, width, height).ok_or(ImageProcessingError::EncodeGif)
+    let frames = decode_animation(path)?;
+    encode_gif(frames, bg, nearest, width, height, options)
 }
 
-fn parse_hex_color(hex: &str) -> Option<[u8; 3]> {
-    let hex = hex.trim_start_matches('#');
-    if hex.len() != 6 {
-        return None;
-    }
-    let r = u8::from_str_radix(&hex[0..2], 16).ok()?;
-    let g = u8::from_str_radix(&hex[2..4], 16).ok()?;
-    let b = u8::from_str_radix(&hex[4..6], 16).ok()?;
-    Some([r, g, b])
+/// Configured media background color, falling back to black if it doesn't parse
+fn background_color(config: &Config) -> [u8; 3] {
+    parse_hex_color(&config.media.background_color).unwrap_or_else(|e| {
+        warn!("media.background_color: {e}, using black");
+        [0, 0, 0]
+    })
 }
 
 /// Show a progress notification that can be updated
@@ -799,3 +1337,69 @@ fn notify_error(message: &str) {
         .timeout(5000)
         .show();
 }
+
+/// Show a notification that updates work again after failing
+fn notify_recovered(kind: &str) {
+    let _ = Notification::new()
+        .summary("zoom-sync")
+        .body(&format!("{kind} updates recovered"))
+        .timeout(3000)
+        .show();
+}
+
+/// Count a periodic update towards its failure streak, notifying when it starts failing
+/// repeatedly and when it recovers
+fn track_update<T>(streak: &mut FailureStreak, kind: &str, result: &Result<T, Box<dyn Error>>) {
+    match (streak.record(result.is_ok()), result) {
+        (Some(StreakChange::Failing), Err(e)) => notify_error(&format!(
+            "{kind} updates failed {} times in a row: {e}",
+            FailureStreak::THRESHOLD
+        )),
+        (Some(StreakChange::Recovered), _) => notify_recovered(kind),
+        _ => {},
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use zoom_sync_core::ScreenGroup;
+
+    use super::*;
+
+    static POSITIONS: &[ScreenPosition] = &[
+        ScreenPosition {
+            id: "time",
+            display_name: "Time",
+            group: ScreenGroup::Time,
+        },
+        ScreenPosition {
+            id: "meletrix",
+            display_name: "Meletrix",
+            group: ScreenGroup::Logo,
+        },
+    ];
+
+    #[test]
+    fn initial_screen_falls_back_to_logo() {
+        assert_eq!(initial_screen("time", POSITIONS), "time");
+        assert_eq!(initial_screen("gif", POSITIONS), "meletrix");
+        assert_eq!(initial_screen("gif", &[]), "gif");
+        assert_eq!(initial_screen("reactive", POSITIONS), "reactive");
+    }
+
+    #[test]
+    fn failure_streaks_notify_on_transitions() {
+        let mut streak = FailureStreak::default();
+        // a single failure that recovers isn't worth mentioning
+        assert_eq!(streak.record(false), None);
+        assert_eq!(streak.record(true), None);
+
+        let changes: Vec<_> = (0..5).map(|_| streak.record(false)).collect();
+        assert_eq!(
+            changes,
+            [None, None, Some(StreakChange::Failing), None, None]
+        );
+        assert_eq!(streak.record(true), Some(StreakChange::Recovered));
+        assert_eq!(streak.record(true), None);
+    }
+}
@@ -0,0 +1,133 @@
+//! Keypress sources for reactive mode
+
+use std::io;
+use std::pin::Pin;
+use std::time::Duration;
+
+use tokio_stream::{Stream, StreamExt};
+use zoom_sync_core::BoardInfo;
+
+/// Idle time after the last keypress before switching back to the image
+pub const IDLE_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Stream of keypress activity, yielding an item for every key event
+pub type KeyStream = Pin<Box<dyn Stream<Item = io::Result<()>>>>;
+
+/// Key stream with the idle timeout applied. Elapsed errors signal the keyboard went idle.
+pub type ReactiveStream = Pin<Box<tokio_stream::Timeout<KeyStream>>>;
+
+/// A platform source of keypress activity
+pub trait KeyActivity {
+    /// Open a keypress stream for the given board, if an input source is available.
+    /// `device` manually selects the input device where the platform supports it.
+    fn open(info: &'static BoardInfo, device: Option<&str>) -> Option<KeyStream>;
+}
+
+/// Keypress source for the current platform
+#[cfg(target_os = "linux")]
+pub type Platform = Evdev;
+#[cfg(not(target_os = "linux"))]
+pub type Platform = KeyboardHook;
+
+/// Open the platform keypress source with the idle timeout applied
+pub fn open(info: &'static BoardInfo, device: Option<&str>) -> Option<ReactiveStream> {
+    Platform::open(info, device).map(|s| Box::pin(s.timeout(IDLE_TIMEOUT)))
+}
+
+/// Reads keypresses directly from the keyboard's evdev device.
+/// Requires read access to `/dev/input` (usually the `input` group).
+///
+/// A manual `device` is either a path like `/dev/input/event3`, or a substring of the device name.
+#[cfg(target_os = "linux")]
+pub struct Evdev;
+
+#[cfg(target_os = "linux")]
+impl KeyActivity for Evdev {
+    fn open(info: &'static BoardInfo, device: Option<&str>) -> Option<KeyStream> {
+        let device = match device {
+            Some(path) if path.starts_with('/') => evdev::Device::open(path).ok()?,
+            Some(name) => evdev::enumerate()
+                .map(|(_, d)| d)
+                .find(|d| d.name().is_some_and(|n| n.contains(name)))?,
+            None => Self::find_device(info)?,
+        };
+        let stream = device.into_event_stream().ok()?;
+        let keys = stream.filter_map(|res| match res {
+            Ok(ev) => matches!(ev.destructure(), evdev::EventSummary::Key(..)).then_some(Ok(())),
+            Err(e) => Some(Err(e)),
+        });
+        Some(Box::pin(keys) as KeyStream)
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Evdev {
+    /// Find the board's keyboard device by usb vendor/product id, falling back to the device
+    /// name when no device reports the board's ids (ie, when connected over a wireless dongle)
+    fn find_device(info: &BoardInfo) -> Option<evdev::Device> {
+        // Boards expose several input devices (consumer control, mouse, etc), only the keyboard
+        // interface reports regular keys
+        let is_keyboard = |d: &evdev::Device| {
+            d.supported_keys()
+                .is_some_and(|keys| keys.contains(evdev::KeyCode::KEY_A))
+        };
+
+        evdev::enumerate()
+            .map(|(_, d)| d)
+            .find(|d| {
+                let id = d.input_id();
+                id.vendor() == info.vendor_id && id.product() == info.product_id && is_keyboard(d)
+            })
+            .or_else(|| {
+                // Must contain board name + "keyboard" suffix
+                let search = format!("{} keyboard", info.name.to_lowercase());
+                evdev::enumerate()
+                    .map(|(_, d)| d)
+                    .find(|d| d.name().is_some_and(|n| n.to_lowercase().contains(&search)))
+            })
+    }
+}
+
+/// Reads keypresses from a global low level keyboard hook.
+///
+/// The hook can't tell keyboards apart, so any keypress counts as activity. On macOS the process
+/// must be granted accessibility permissions for the hook to receive events.
+#[cfg(not(target_os = "linux"))]
+pub struct KeyboardHook;
+
+#[cfg(not(target_os = "linux"))]
+impl KeyActivity for KeyboardHook {
+    fn open(_info: &'static BoardInfo, _device: Option<&str>) -> Option<KeyStream> {
+        use std::sync::{Mutex, Once};
+
+        use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
+
+        // The hook can't be uninstalled, so it's started once and forwards to the latest stream
+        static SENDER: Mutex<Option<UnboundedSender<io::Result<()>>>> = Mutex::new(None);
+        static HOOK: Once = Once::new();
+
+        let (tx, rx) = unbounded_channel();
+        *SENDER.lock().unwrap() = Some(tx);
+
+        HOOK.call_once(|| {
+            std::thread::spawn(|| {
+                let res = rdev::listen(|event| {
+                    if matches!(event.event_type, rdev::EventType::KeyPress(_)) {
+                        if let Some(tx) = SENDER.lock().unwrap().as_ref() {
+                            let _ = tx.send(Ok(()));
+                        }
+                    }
+                });
+                if let Err(e) = res {
+                    if let Some(tx) = SENDER.lock().unwrap().take() {
+                        let _ = tx.send(Err(io::Error::other(format!("{e:?}"))));
+                    }
+                }
+            });
+        });
+
+        Some(Box::pin(
+            tokio_stream::wrappers::UnboundedReceiverStream::new(rx),
+        ))
+    }
+}
@@ -0,0 +1,27 @@
+//! Readiness and status updates for running the tray as a systemd `Type=notify` service.
+//!
+//! Everything here is a no-op without the `systemd` feature, or when not started by systemd.
+
+/// Tell systemd the tray is up. Only the first call sends anything.
+pub fn ready() {
+    #[cfg(all(feature = "systemd", target_os = "linux"))]
+    {
+        static READY: std::sync::Once = std::sync::Once::new();
+        READY.call_once(|| notify(&[sd_notify::NotifyState::Ready]));
+    }
+}
+
+/// Set the one line status shown by `systemctl status`
+pub fn status(status: &str) {
+    #[cfg(all(feature = "systemd", target_os = "linux"))]
+    notify(&[sd_notify::NotifyState::Status(status)]);
+    #[cfg(not(all(feature = "systemd", target_os = "linux")))]
+    let _ = status;
+}
+
+#[cfg(all(feature = "systemd", target_os = "linux"))]
+fn notify(state: &[sd_notify::NotifyState]) {
+    if let Err(e) = sd_notify::notify(false, state) {
+        log::debug!("failed to notify systemd: {e}");
+    }
+}
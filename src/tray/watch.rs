@@ -0,0 +1,58 @@
+//! Reload the config automatically when the file changes on disk
+
+use std::path::Path;
+use std::time::Duration;
+
+use log::{debug, warn};
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
+
+use super::TrayCommand;
+
+/// Quiet period after the last change before reloading, since editors often save in several
+/// steps (truncate and write, or write a temp file and rename it over the original)
+const DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// Send [`TrayCommand::ReloadConfig`] whenever the config file changes. Watching stops when the
+/// returned watcher is dropped.
+pub fn watch_config(
+    path: &Path,
+    tx: UnboundedSender<TrayCommand>,
+) -> notify::Result<RecommendedWatcher> {
+    // Watch the directory rather than the file, which stops being watched once an editor
+    // replaces it with a renamed copy
+    let dir = path.parent().unwrap_or(Path::new("."));
+    let name = path.file_name().map(ToOwned::to_owned);
+
+    let (changed_tx, mut changed_rx) = unbounded_channel();
+    let mut watcher =
+        notify::recommended_watcher(move |res: notify::Result<notify::Event>| match res {
+            Ok(event) => {
+                let is_config = event.paths.iter().any(|p| p.file_name() == name.as_deref());
+                if is_config && !matches!(event.kind, EventKind::Access(_)) {
+                    let _ = changed_tx.send(());
+                }
+            },
+            Err(e) => warn!("config watcher error: {e}"),
+        })?;
+    watcher.watch(dir, RecursiveMode::NonRecursive)?;
+
+    tokio::spawn(async move {
+        while changed_rx.recv().await.is_some() {
+            // Wait for the burst of events from a single save to settle
+            loop {
+                match tokio::time::timeout(DEBOUNCE, changed_rx.recv()).await {
+                    Ok(Some(())) => continue,
+                    Ok(None) => return,
+                    Err(_) => break,
+                }
+            }
+            debug!("config file changed, reloading");
+            if tx.send(TrayCommand::ReloadConfig).is_err() {
+                return;
+            }
+        }
+    });
+
+    Ok(watcher)
+}
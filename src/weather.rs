@@ -1,28 +1,67 @@
 //! Utilities for getting weather info
 
 use std::error::Error;
+use std::fs;
+use std::future::Future;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-use bpaf::Bpaf;
-use chrono::Timelike;
+use bpaf::{Bpaf, Parser};
+use chrono::{DateTime, Datelike, Timelike, Utc};
+use directories::ProjectDirs;
+use futures::future::LocalBoxFuture;
 use ipinfo::IpInfo;
+use log::{error, info, warn};
 use open_meteo_api::query::OpenMeteo;
-use zoom_sync_core::Board;
+use serde::{Deserialize, Serialize};
+use zoom_sync_core::{Board, WeatherExtras};
 
 #[derive(Clone, Debug, Bpaf)]
 #[bpaf(adjacent)]
 pub struct Coords {
-    /// Optional coordinates to use for fetching weather data, skipping ipinfo geolocation api.
+    /// Optional coordinates to use for fetching weather data, skipping ip geolocation.
     #[bpaf(long)]
     #[allow(dead_code)]
     pub coords: (),
     /// Latitude
-    #[bpaf(positional("LAT"))]
+    #[bpaf(
+        positional("LAT"),
+        guard(valid_lat, "latitude must be between -90 and 90")
+    )]
     pub lat: f32,
     /// Longitude
-    #[bpaf(positional("LON"))]
+    #[bpaf(
+        positional("LON"),
+        guard(valid_long, "longitude must be between -180 and 180")
+    )]
     pub long: f32,
 }
 
+fn valid_lat(lat: &f32) -> bool {
+    (-90.0..=90.0).contains(lat)
+}
+
+fn valid_long(long: &f32) -> bool {
+    (-180.0..=180.0).contains(long)
+}
+
+impl Coords {
+    /// Create validated coordinates
+    pub fn new(lat: f32, long: f32) -> Result<Self, String> {
+        if !valid_lat(&lat) {
+            return Err(format!("latitude {lat} must be between -90 and 90"));
+        }
+        if !valid_long(&long) {
+            return Err(format!("longitude {long} must be between -180 and 180"));
+        }
+        Ok(Self {
+            coords: (),
+            lat,
+            long,
+        })
+    }
+}
+
 /// Weather forecast options:
 #[derive(Clone, Debug, Bpaf)]
 pub enum WeatherArgs {
@@ -33,6 +72,12 @@ pub enum WeatherArgs {
     Auto {
         #[bpaf(external, optional)]
         coords: Option<Coords>,
+        /// Optional city name to geocode with open-meteo, skipping ip geolocation.
+        #[bpaf(long, argument("NAME"))]
+        city: Option<String>,
+        /// Optional ipinfo access token, avoiding the anonymous rate limit for geolocation.
+        #[bpaf(long, env("IPINFO_TOKEN"), argument("TOKEN"))]
+        ipinfo_token: Option<String>,
     },
     #[bpaf(adjacent)]
     Manual {
@@ -44,135 +89,665 @@ pub enum WeatherArgs {
         /// WMO Index
         #[bpaf(positional("WMO"))]
         wmo: u8,
-        /// Current temperature
-        #[bpaf(positional("CUR"))]
-        current: u8,
-        /// Minumum temperature
-        #[bpaf(positional("MIN"))]
-        min: u8,
-        /// Maximum temperature
-        #[bpaf(positional("MAX"))]
-        max: u8,
+        #[bpaf(external(current_temperature))]
+        current: i16,
+        #[bpaf(external(min_temperature))]
+        min: i16,
+        #[bpaf(external(max_temperature))]
+        max: i16,
+        /// Optional coordinates, used to tell day from night by the actual sunrise and sunset
+        #[bpaf(external, optional)]
+        coords: Option<Coords>,
     },
 }
 
-pub async fn get_coords() -> Result<(f32, f32), Box<dyn Error>> {
-    println!("fetching geolocation from ipinfo ...");
-    let mut ipinfo = IpInfo::new(ipinfo::IpInfoConfig {
-        token: None,
-        ..Default::default()
-    })?;
-    let info = ipinfo.lookup_self_v4().await?;
-    let (lat, long) = info.loc.split_once(',').unwrap();
-    Ok((lat.parse().unwrap(), long.parse().unwrap()))
+/// Positional temperature. Parsed from any item, since bpaf takes a plain positional like `-5`
+/// for a flag.
+fn temperature(meta: &'static str, help: &'static str) -> impl Parser<i16> {
+    bpaf::any::<String, _, _>(meta, |s| s.parse().ok()).help(help)
+}
+
+fn current_temperature() -> impl Parser<i16> {
+    temperature("CUR", "Current temperature")
+}
+
+fn min_temperature() -> impl Parser<i16> {
+    temperature("MIN", "Minimum temperature")
+}
+
+fn max_temperature() -> impl Parser<i16> {
+    temperature("MAX", "Maximum temperature")
+}
+
+/// Whether the sun is above the horizon at a location, from the NOAA solar position equations.
+/// Near the poles this holds through the midnight sun and stays false through the polar night.
+pub fn sun_is_up(lat: f32, long: f32, time: DateTime<Utc>) -> bool {
+    use std::f64::consts::PI;
+
+    let hour = time.hour() as f64 + time.minute() as f64 / 60. + time.second() as f64 / 3600.;
+    // fractional year in radians
+    let y = 2. * PI / 365. * (time.ordinal0() as f64 + (hour - 12.) / 24.);
+    let eqtime = 229.18
+        * (0.000075 + 0.001868 * y.cos()
+            - 0.032077 * y.sin()
+            - 0.014615 * (2. * y).cos()
+            - 0.040849 * (2. * y).sin());
+    let declination = 0.006918 - 0.399912 * y.cos() + 0.070257 * y.sin()
+        - 0.006758 * (2. * y).cos()
+        + 0.000907 * (2. * y).sin()
+        - 0.002697 * (3. * y).cos()
+        + 0.00148 * (3. * y).sin();
+
+    // true solar time in minutes, then the hour angle of the sun
+    let solar_minutes = hour * 60. + eqtime + 4. * long as f64;
+    let hour_angle = (solar_minutes / 4. - 180.).to_radians();
+    let lat = (lat as f64).to_radians();
+    let elevation = (lat.sin() * declination.sin()
+        + lat.cos() * declination.cos() * hour_angle.cos())
+    .asin()
+    .to_degrees();
+
+    // sunrise and sunset are when the upper edge clears the horizon, after refraction
+    elevation > -0.833
+}
+
+/// Default time limit for each weather related network request
+pub const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Fail `request` if it takes longer than `timeout`, so a hung network can't stall a sync
+async fn with_timeout<T>(
+    timeout: Duration,
+    what: &str,
+    request: impl Future<Output = Result<T, Box<dyn Error>>>,
+) -> Result<T, Box<dyn Error>> {
+    tokio::time::timeout(timeout, request)
+        .await
+        .map_err(|_| format!("{what} timed out after {timeout:?}"))?
+}
+
+/// Source of the approximate coordinates of this machine
+pub trait GeoProvider {
+    /// Name of the provider, for logs
+    fn name(&self) -> &'static str;
+    /// Look up the current coordinates
+    fn locate(&self) -> LocalBoxFuture<'_, Result<Coords, Box<dyn Error>>>;
+}
+
+/// Geolocation from the ipinfo api, anonymous or with an access token
+pub struct IpInfoGeo {
+    pub token: Option<String>,
+}
+
+impl GeoProvider for IpInfoGeo {
+    fn name(&self) -> &'static str {
+        "ipinfo"
+    }
+
+    fn locate(&self) -> LocalBoxFuture<'_, Result<Coords, Box<dyn Error>>> {
+        Box::pin(async move {
+            let mut ipinfo = IpInfo::new(ipinfo::IpInfoConfig {
+                token: self.token.clone(),
+                ..Default::default()
+            })?;
+            let info = ipinfo.lookup_self_v4().await.inspect_err(|e| {
+                if self.token.is_none() && e.to_string().to_lowercase().contains("rate limit") {
+                    warn!(
+                        "hint: anonymous ipinfo requests are rate limited, set an access token \
+                         with IPINFO_TOKEN, --ipinfo-token, or weather.ipinfo_token in the tray \
+                         config"
+                    );
+                }
+            })?;
+            let (lat, long) = info
+                .loc
+                .split_once(',')
+                .ok_or_else(|| format!("invalid location {:?}", info.loc))?;
+            Ok(Coords::new(lat.parse()?, long.parse()?)?)
+        })
+    }
+}
+
+/// Geolocation from the free ip-api.com endpoint, which needs no token
+pub struct IpApiGeo;
+
+impl GeoProvider for IpApiGeo {
+    fn name(&self) -> &'static str {
+        "ip-api"
+    }
+
+    fn locate(&self) -> LocalBoxFuture<'_, Result<Coords, Box<dyn Error>>> {
+        #[derive(Deserialize)]
+        struct Response {
+            status: String,
+            message: Option<String>,
+            lat: Option<f32>,
+            lon: Option<f32>,
+        }
+
+        Box::pin(async move {
+            // the free tier is only served over http
+            let res: Response = reqwest::Client::new()
+                .get("http://ip-api.com/json/")
+                .query(&[("fields", "status,message,lat,lon")])
+                .send()
+                .await?
+                .error_for_status()?
+                .json()
+                .await?;
+            match (res.status.as_str(), res.lat, res.lon) {
+                ("success", Some(lat), Some(long)) => Ok(Coords::new(lat, long)?),
+                _ => Err(res.message.unwrap_or(res.status).into()),
+            }
+        })
+    }
+}
+
+/// Fixed coordinates, ie as a last resort when every api is unreachable
+pub struct StaticGeo(pub f32, pub f32);
+
+impl GeoProvider for StaticGeo {
+    fn name(&self) -> &'static str {
+        "static fallback"
+    }
+
+    fn locate(&self) -> LocalBoxFuture<'_, Result<Coords, Box<dyn Error>>> {
+        Box::pin(async move { Ok(Coords::new(self.0, self.1)?) })
+    }
+}
+
+/// Configurable geolocation source, see [`GeoChain`]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum GeoSource {
+    /// Use the ipinfo api
+    Ipinfo,
+    /// Use the ip-api.com api
+    IpApi,
+    /// Use a fixed `[latitude, longitude]`
+    Static(f32, f32),
+}
+
+impl GeoSource {
+    /// Sources used unless configured otherwise
+    pub const DEFAULT: &[GeoSource] = &[GeoSource::Ipinfo, GeoSource::IpApi];
+
+    fn provider(&self, ipinfo_token: Option<&str>) -> Box<dyn GeoProvider> {
+        match *self {
+            GeoSource::Ipinfo => Box::new(IpInfoGeo {
+                token: ipinfo_token.map(ToOwned::to_owned),
+            }),
+            GeoSource::IpApi => Box::new(IpApiGeo),
+            GeoSource::Static(lat, long) => Box::new(StaticGeo(lat, long)),
+        }
+    }
 }
 
-/// Weather data from API
+/// Geolocation providers, tried in order until one succeeds
+pub struct GeoChain(pub Vec<Box<dyn GeoProvider>>);
+
+impl GeoChain {
+    /// Build the chain for a list of sources
+    pub fn new(sources: &[GeoSource], ipinfo_token: Option<&str>) -> Self {
+        Self(sources.iter().map(|s| s.provider(ipinfo_token)).collect())
+    }
+
+    /// Coordinates from the first provider that answers within `timeout`
+    pub async fn resolve(&self, timeout: Duration) -> Result<Coords, Box<dyn Error>> {
+        if self.0.is_empty() {
+            return Err("all geolocation providers are disabled".into());
+        }
+        for provider in &self.0 {
+            let name = provider.name();
+            info!("fetching geolocation from {name} ...");
+            match with_timeout(timeout, name, provider.locate()).await {
+                Ok(coords) => {
+                    info!("geolocated with {name} [{}, {}]", coords.lat, coords.long);
+                    return Ok(coords);
+                },
+                Err(e) => warn!("failed to fetch geolocation from {name}: {e}"),
+            }
+        }
+        Err("every geolocation provider failed".into())
+    }
+}
+
+/// Look up the coordinates of a city with the open-meteo geocoding api
+pub async fn geocode(city: &str) -> Result<Coords, Box<dyn Error>> {
+    #[derive(Deserialize)]
+    struct Response {
+        #[serde(default)]
+        results: Vec<Place>,
+    }
+    #[derive(Deserialize)]
+    struct Place {
+        name: String,
+        country: Option<String>,
+        latitude: f32,
+        longitude: f32,
+    }
+
+    info!("geocoding {city} with open-meteo ...");
+    let res: Response = reqwest::Client::new()
+        .get("https://geocoding-api.open-meteo.com/v1/search")
+        .query(&[("name", city), ("count", "1")])
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    let place = res
+        .results
+        .into_iter()
+        .next()
+        .ok_or_else(|| format!("no location found for {city}"))?;
+    info!(
+        "resolved {city} to {}{} [{}, {}]",
+        place.name,
+        place.country.map(|c| format!(", {c}")).unwrap_or_default(),
+        place.latitude,
+        place.longitude
+    );
+    Ok(Coords::new(place.latitude, place.longitude)?)
+}
+
+/// Weather report, with temperatures in celsius
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub struct WeatherData {
     pub wmo: u8,
     pub is_day: bool,
     pub current: f32,
     pub min: f32,
     pub max: f32,
+    /// Relative humidity in percent
+    pub humidity: Option<f32>,
+    /// Apparent ("feels like") temperature
+    pub apparent_temp: Option<f32>,
+    /// Wind speed in km/h
+    pub wind_speed: Option<f32>,
 }
 
-/// Get the current weather, using ipinfo for geolocation, and open-meteo for forcasting
-pub async fn get_weather(
-    lat: f32,
-    long: f32,
-    fahrenheit: bool,
-) -> Result<WeatherData, Box<dyn Error>> {
-    println!("fetching current weather from open-meteo for [{lat}, {long}] ...");
-    let res = OpenMeteo::new()
-        .coordinates(lat, long)?
-        .current_weather()?
-        .time_zone(open_meteo_api::models::TimeZone::Auto)?
-        .daily()?
-        .query()
-        .await?;
+impl WeatherData {
+    /// Convert all temperatures to fahrenheit
+    pub fn to_fahrenheit(self) -> Self {
+        let f = |c: f32| c * 9. / 5. + 32.;
+        Self {
+            current: f(self.current),
+            min: f(self.min),
+            max: f(self.max),
+            apparent_temp: self.apparent_temp.map(f),
+            ..self
+        }
+    }
 
-    let current = res.current_weather.unwrap();
-    let wmo = current.weathercode as u8;
-    let is_day = current.is_day == 1.0;
+    /// Extra readings for boards that can display them
+    pub fn extras(&self) -> WeatherExtras {
+        WeatherExtras {
+            humidity: self.humidity.map(|h| h.round().clamp(0., 100.) as u8),
+            apparent_temp: self.apparent_temp.map(|t| t.round() as i16),
+            wind_speed: self.wind_speed,
+        }
+    }
+}
 
-    let daily = res.daily.unwrap();
-    let mut min = daily.temperature_2m_min.first().unwrap().unwrap();
-    let mut max = daily.temperature_2m_max.first().unwrap().unwrap();
-    let mut temp = current.temperature;
+/// Source of weather reports for a location
+pub trait WeatherProvider {
+    /// Fetch the current weather and today's min/max at the given coordinates
+    async fn fetch(&self, lat: f32, long: f32) -> Result<WeatherData, Box<dyn Error>>;
+}
+
+/// Forecasts from the free open-meteo api
+pub struct OpenMeteoProvider;
+
+impl WeatherProvider for OpenMeteoProvider {
+    async fn fetch(&self, lat: f32, long: f32) -> Result<WeatherData, Box<dyn Error>> {
+        info!("fetching current weather from open-meteo for [{lat}, {long}] ...");
+        let res = OpenMeteo::new()
+            .coordinates(lat, long)?
+            .current_weather()?
+            .time_zone(open_meteo_api::models::TimeZone::Auto)?
+            .hourly()?
+            .daily()?
+            .query()
+            .await?;
+
+        let current = res.current_weather.ok_or("missing current weather")?;
+        let daily = res.daily.ok_or("missing daily forecast")?;
+
+        // index of the current hour in the hourly readings, ie 13:00 for a 13:15 report
+        let hourly = res.hourly.as_ref();
+        let hour = hourly.and_then(|h| {
+            let hour = current.time.get(..13)?;
+            h.time.iter().position(|t| t.starts_with(hour))
+        });
+        let at_hour = |values: &[Option<f32>]| values.get(hour?).copied().flatten();
 
-    if fahrenheit {
-        min = min * 9. / 5. + 32.;
-        max = max * 9. / 5. + 32.;
-        temp = temp * 9. / 5. + 32.;
+        Ok(WeatherData {
+            wmo: current.weathercode as u8,
+            is_day: current.is_day == 1.0,
+            current: current.temperature,
+            min: daily
+                .temperature_2m_min
+                .first()
+                .copied()
+                .flatten()
+                .ok_or("missing daily min temperature")?,
+            max: daily
+                .temperature_2m_max
+                .first()
+                .copied()
+                .flatten()
+                .ok_or("missing daily max temperature")?,
+            humidity: hourly.and_then(|h| at_hour(&h.relativehumidity_2m)),
+            apparent_temp: hourly.and_then(|h| at_hour(&h.apparent_temperature)),
+            wind_speed: Some(current.windspeed),
+        })
     }
+}
+
+/// Runs a user program with `LAT LON` appended to its arguments. The program must print a json
+/// report to stdout, ie `{"wmo":3,"is_day":true,"current":12.5,"min":8,"max":14}` (celsius).
+/// `humidity`, `apparent_temp` and `wind_speed` may be included as well.
+pub struct CommandProvider<'a>(pub &'a [String]);
 
-    Ok(WeatherData {
-        wmo,
-        is_day,
-        current: temp,
-        min,
-        max,
-    })
+impl WeatherProvider for CommandProvider<'_> {
+    async fn fetch(&self, lat: f32, long: f32) -> Result<WeatherData, Box<dyn Error>> {
+        let (program, args) = self.0.split_first().ok_or("weather command is empty")?;
+        info!("fetching current weather from {program} for [{lat}, {long}] ...");
+        let output = tokio::process::Command::new(program)
+            .args(args)
+            .arg(lat.to_string())
+            .arg(long.to_string())
+            .kill_on_drop(true)
+            .output()
+            .await?;
+        if !output.status.success() {
+            return Err(format!(
+                "weather command exited with {}: {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )
+            .into());
+        }
+        Ok(serde_json::from_slice(&output.stdout)?)
+    }
 }
 
-pub async fn apply_weather(
-    board: &mut dyn Board,
+/// Configurable weather provider selection
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Provider {
+    /// Use the open-meteo api
+    #[default]
+    OpenMeteo,
+    /// Run a program and parse its json output, see [`CommandProvider`]
+    Command(Vec<String>),
+}
+
+impl WeatherProvider for Provider {
+    async fn fetch(&self, lat: f32, long: f32) -> Result<WeatherData, Box<dyn Error>> {
+        match self {
+            Provider::OpenMeteo => OpenMeteoProvider.fetch(lat, long).await,
+            Provider::Command(command) => CommandProvider(command).fetch(lat, long).await,
+        }
+    }
+}
+
+/// Last successful weather report, reused when fetching fails
+#[derive(Serialize, Deserialize)]
+struct CachedWeather {
+    /// Unix timestamp of the fetch, in seconds
+    fetched_at: u64,
+    data: WeatherData,
+}
+
+impl CachedWeather {
+    /// Path of the cache file under the config directory
+    fn path() -> Option<PathBuf> {
+        ProjectDirs::from("", "", "zoom-sync")
+            .map(|dirs| dirs.config_dir().join("cache").join("weather.json"))
+    }
+
+    fn now() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default()
+    }
+
+    /// Persist a fresh report. Failures are only logged, since the cache is best effort.
+    fn store(data: &WeatherData) {
+        let Some(path) = Self::path() else { return };
+        let cached = CachedWeather {
+            fetched_at: Self::now(),
+            data: *data,
+        };
+        let res = serde_json::to_vec(&cached)
+            .map_err(Box::<dyn Error>::from)
+            .and_then(|json| {
+                fs::create_dir_all(path.parent().unwrap())?;
+                Ok(fs::write(&path, json)?)
+            });
+        if let Err(e) = res {
+            warn!("failed to cache weather report: {e}");
+        }
+    }
+
+    /// Load the cached report if it is younger than `max_age`
+    fn load(max_age: Duration) -> Option<WeatherData> {
+        let contents = fs::read(Self::path()?).ok()?;
+        let cached: CachedWeather = serde_json::from_slice(&contents).ok()?;
+        let age = Self::now().saturating_sub(cached.fetched_at);
+        (age <= max_age.as_secs()).then_some(cached.data)
+    }
+}
+
+/// Fetch a report in auto mode, without touching the board. Without coordinates or a city, the
+/// location comes from the first `geolocation` source that succeeds, and a failed fetch falls
+/// back to the last successful report if it is younger than `max_cache_age`. Each network
+/// request is limited to `timeout`.
+/// Resolved coordinates are kept in `args`. Manual and disabled weather fetch nothing.
+pub async fn fetch_weather(
     args: &mut WeatherArgs,
+    provider: &impl WeatherProvider,
+    geolocation: &[GeoSource],
+    max_cache_age: Option<Duration>,
+    timeout: Duration,
+) -> Result<Option<WeatherData>, Box<dyn Error>> {
+    let WeatherArgs::Auto {
+        coords,
+        city,
+        ipinfo_token,
+    } = args
+    else {
+        return Ok(None);
+    };
+
+    // resolve the city once, later updates reuse the coordinates
+    if let Some(name) = city.as_deref().filter(|_| coords.is_none()) {
+        match with_timeout(timeout, "geocoding", geocode(name)).await {
+            Ok(c) => *coords = Some(c),
+            Err(e) => warn!("failed to geocode {name}: {e}"),
+        }
+    }
+
+    // attempt to backfill coordinates if not provided
+    if coords.is_none() && city.is_none() {
+        let chain = GeoChain::new(geolocation, ipinfo_token.as_deref());
+        match chain.resolve(timeout).await {
+            Ok(c) => *coords = Some(c),
+            Err(e) => warn!("failed to geolocate: {e}"),
+        }
+    }
+
+    // try to update weather if we have some coordinates
+    let fetched = match *coords {
+        Some(Coords { lat, long, .. }) => {
+            with_timeout(timeout, "weather request", provider.fetch(lat, long)).await
+        },
+        None => Err("no coordinates available".into()),
+    };
+    match fetched {
+        Ok(data) => {
+            CachedWeather::store(&data);
+            Ok(Some(data))
+        },
+        Err(e) => match max_cache_age.and_then(CachedWeather::load) {
+            Some(data) => {
+                warn!("failed to fetch weather, using cached report: {e}");
+                Ok(Some(data))
+            },
+            None => Err(format!("failed to fetch weather: {e}").into()),
+        },
+    }
+}
+
+/// Send a report from [`fetch_weather`] to the board, or the manual values in manual mode.
+/// Returns the report that was sent, if any.
+pub fn send_weather(
+    board: &mut dyn Board,
+    args: &WeatherArgs,
+    data: Option<WeatherData>,
     farenheit: bool,
-) -> Result<(), Box<dyn Error>> {
+) -> Result<Option<WeatherData>, Box<dyn Error>> {
     let weather = board.as_weather().ok_or("board does not support weather")?;
-
     match args {
-        WeatherArgs::Disabled => println!("skipping weather"),
-        WeatherArgs::Auto { coords } => {
-            // attempt to backfill coordinates if not provided
-            if coords.is_none() {
-                match get_coords().await {
-                    Ok((lat, long)) => {
-                        *coords = Some(Coords {
-                            coords: (),
-                            lat,
-                            long,
-                        })
-                    },
-                    Err(e) => eprintln!("warning: failed to fetch geolocation from ipinfo: {e}"),
-                }
-            }
-
-            // try to update weather if we have some coordinates
-            if let Some(Coords { lat, long, .. }) = *coords {
-                match get_weather(lat, long, farenheit).await {
-                    Ok(data) => {
-                        weather
-                            .set_weather(
-                                data.wmo,
-                                data.is_day,
-                                data.current as u8,
-                                data.min as u8,
-                                data.max as u8,
-                            )
-                            .map_err(|e| format!("failed to set weather: {e}"))?;
-                        println!(
-                            "updated weather {{ wmo: {}, is_day: {}, current: {}, min: {}, max: {} }}",
-                            data.wmo, data.is_day, data.current, data.min, data.max
-                        );
-                    },
-                    Err(e) => eprintln!("failed to fetch weather, skipping: {e}"),
-                }
-            }
+        WeatherArgs::Disabled => {
+            info!("skipping weather");
+            Ok(None)
+        },
+        WeatherArgs::Auto { .. } => {
+            let Some(data) = data else {
+                return Ok(None);
+            };
+            let data = if farenheit {
+                data.to_fahrenheit()
+            } else {
+                data
+            };
+            weather
+                .set_weather_extended(
+                    data.wmo,
+                    data.is_day,
+                    data.current.round() as i16,
+                    data.min.round() as i16,
+                    data.max.round() as i16,
+                    data.extras(),
+                )
+                .map_err(|e| format!("failed to set weather: {e}"))?;
+            info!(
+                "updated weather {{ wmo: {}, is_day: {}, current: {}, min: {}, max: {} }}",
+                data.wmo, data.is_day, data.current, data.min, data.max
+            );
+            Ok(Some(data))
         },
         WeatherArgs::Manual {
             wmo,
             current,
             min,
             max,
+            coords,
             ..
         } => {
-            let hour = chrono::Local::now().hour();
-            let is_day = (6..=18).contains(&hour);
+            let is_day = match coords {
+                Some(Coords { lat, long, .. }) => sun_is_up(*lat, *long, Utc::now()),
+                // without a location, guess from the local clock
+                None => (6..=18).contains(&chrono::Local::now().hour()),
+            };
             weather.set_weather(*wmo, is_day, *current, *min, *max)?;
+            Ok(None)
         },
     }
+}
+
+/// Update the board's weather, fetching a report first in auto mode (see [`fetch_weather`]).
+/// A failed fetch without a cached report skips the update.
+/// Returns the report that was sent, if any.
+pub async fn apply_weather(
+    board: &mut dyn Board,
+    args: &mut WeatherArgs,
+    provider: &impl WeatherProvider,
+    geolocation: &[GeoSource],
+    farenheit: bool,
+    max_cache_age: Option<Duration>,
+    timeout: Duration,
+) -> Result<Option<WeatherData>, Box<dyn Error>> {
+    board.as_weather().ok_or("board does not support weather")?;
+    let data = fetch_weather(args, provider, geolocation, max_cache_age, timeout)
+        .await
+        .unwrap_or_else(|e| {
+            error!("{e}, skipping");
+            None
+        });
+    send_weather(board, args, data, farenheit)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    /// Provider that is always unreachable
+    struct Down;
+
+    impl GeoProvider for Down {
+        fn name(&self) -> &'static str {
+            "down"
+        }
+
+        fn locate(&self) -> LocalBoxFuture<'_, Result<Coords, Box<dyn Error>>> {
+            Box::pin(async { Err("connection refused".into()) })
+        }
+    }
+
+    #[tokio::test]
+    async fn geolocation_falls_back_in_order() {
+        let chain = GeoChain(vec![
+            Box::new(Down),
+            Box::new(StaticGeo(52.52, 13.41)),
+            Box::new(StaticGeo(1., 2.)),
+        ]);
+        let coords = chain.resolve(DEFAULT_REQUEST_TIMEOUT).await.unwrap();
+        assert_eq!((coords.lat, coords.long), (52.52, 13.41));
+    }
 
-    Ok(())
+    #[tokio::test]
+    async fn geolocation_fails_when_nothing_answers() {
+        let timeout = DEFAULT_REQUEST_TIMEOUT;
+        assert!(GeoChain(vec![]).resolve(timeout).await.is_err());
+        assert!(GeoChain(vec![Box::new(Down)])
+            .resolve(timeout)
+            .await
+            .is_err());
+        // out of range fallbacks are rejected rather than sent to the weather api
+        let invalid = GeoChain::new(&[GeoSource::Static(91., 0.)], None);
+        assert!(invalid.resolve(timeout).await.is_err());
+    }
+
+    fn utc(y: i32, m: u32, d: u32, h: u32, min: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(y, m, d, h, min, 0).unwrap()
+    }
+
+    #[test]
+    fn long_summer_evenings_are_day() {
+        // oslo sets around 22:45 local (20:45 utc) at midsummer
+        assert!(sun_is_up(59.91, 10.75, utc(2025, 6, 21, 20, 15)));
+        assert!(!sun_is_up(59.91, 10.75, utc(2025, 6, 21, 21, 15)));
+    }
+
+    #[test]
+    fn polar_day_and_night() {
+        // tromsø has the midnight sun in june and no sunrise in december
+        assert!(sun_is_up(69.65, 18.96, utc(2025, 6, 21, 23, 0)));
+        assert!(!sun_is_up(69.65, 18.96, utc(2025, 12, 21, 11, 0)));
+    }
+
+    #[test]
+    fn equator_follows_the_clock() {
+        assert!(sun_is_up(0., 0., utc(2025, 3, 20, 12, 0)));
+        assert!(!sun_is_up(0., 0., utc(2025, 3, 20, 0, 0)));
+        // a quarter turn east it's noon, a quarter turn west it's midnight
+        assert!(sun_is_up(0., 90., utc(2025, 3, 20, 6, 0)));
+        assert!(!sun_is_up(0., -90., utc(2025, 3, 20, 6, 0)));
+    }
 }